@@ -0,0 +1,116 @@
+//! zbus proxies for the D-Bus interfaces of the pstate_update daemon, for applications
+//! that want to show or control it. Both blocking and async proxies are generated, e.g.
+//! `DaemonProxyBlocking` and `DaemonProxy`.
+
+use std::collections::HashMap;
+
+/// Well-known name the daemon claims on the system bus.
+pub const SERVICE_NAME: &str = "org.pstate_update1";
+/// Object path of the daemon interface.
+pub const OBJECT_PATH: &str = "/org/pstate_update1";
+/// Name of the daemon interface.
+pub const INTERFACE_NAME: &str = "org.pstate_update1.Daemon";
+
+/// Control and state of the daemon.
+#[zbus::dbus_proxy(
+    interface = "org.pstate_update1.Daemon",
+    default_service = "org.pstate_update1",
+    default_path = "/org/pstate_update1"
+)]
+pub trait Daemon {
+    /// Layer a `[profile]` section from the config on top of the active profile.
+    fn select_profile(&self, name: &str) -> zbus::Result<()>;
+
+    /// Go back to the mapping of the active profile alone.
+    fn clear_profile(&self) -> zbus::Result<()>;
+
+    /// Force an EPP and governor on every profile. An empty string leaves the knob alone.
+    fn set_profile_override(&self, epp: &str, governor: &str) -> zbus::Result<()>;
+
+    /// Go back to the mapping after `set_profile_override`.
+    fn clear_override(&self) -> zbus::Result<()>;
+
+    /// Apply the mapping of a built-in profile once.
+    fn apply_profile(&self, profile: &str) -> zbus::Result<()>;
+
+    /// Lock the daemon to a profile until `uninhibit` is called with the returned cookie
+    /// or the caller leaves the bus.
+    fn inhibit(&self, profile: &str, reason: &str) -> zbus::Result<u32>;
+
+    /// Release a lock taken with `inhibit`.
+    fn uninhibit(&self, cookie: u32) -> zbus::Result<()>;
+
+    /// Locks taken with `inhibit`, as owner, profile and reason.
+    #[dbus_proxy(property)]
+    fn inhibitors(&self) -> zbus::Result<Vec<(String, String, String)>>;
+
+    /// Name of the profile most recently applied.
+    #[dbus_proxy(property)]
+    fn active_profile(&self) -> zbus::Result<String>;
+
+    /// EPPs written by the most recent application, comma-separated if policies differ.
+    #[dbus_proxy(property)]
+    fn applied_epp(&self) -> zbus::Result<String>;
+
+    /// Governors written by the most recent application, comma-separated if policies
+    /// differ.
+    #[dbus_proxy(property)]
+    fn applied_governor(&self) -> zbus::Result<String>;
+
+    /// When the most recent application finished, in seconds since the Unix epoch.
+    #[dbus_proxy(property)]
+    fn last_apply_timestamp(&self) -> zbus::Result<u64>;
+
+    /// Number of CPU cores whose EPP the daemon manages.
+    #[dbus_proxy(property)]
+    fn managed_core_count(&self) -> zbus::Result<u32>;
+
+    /// A new ActiveProfile value was received from power-profiles-daemon.
+    #[dbus_proxy(signal)]
+    fn profile_received(&self, profile: &str) -> zbus::Result<()>;
+
+    /// A profile was applied, with the knob, path and value of every successful write and
+    /// the number of writes that failed.
+    #[dbus_proxy(signal)]
+    fn profile_applied(
+        &self,
+        profile: &str,
+        written: Vec<(&str, &str, &str)>,
+        failures: u32,
+    ) -> zbus::Result<()>;
+
+    /// A value was written to a sysfs file.
+    #[dbus_proxy(signal)]
+    fn value_written(&self, knob: &str, path: &str, value: &str) -> zbus::Result<()>;
+
+    /// Writing a value to a sysfs file failed.
+    #[dbus_proxy(signal)]
+    fn write_failed(&self, knob: &str, path: &str, value: &str, error: &str) -> zbus::Result<()>;
+}
+
+/// Counters of the daemon's work since it started or they were reset.
+#[zbus::dbus_proxy(
+    interface = "org.pstate_update1.Statistics",
+    default_service = "org.pstate_update1",
+    default_path = "/org/pstate_update1"
+)]
+pub trait Statistics {
+    /// Set every counter back to zero.
+    fn reset(&self) -> zbus::Result<()>;
+
+    /// Number of times a different profile was applied.
+    #[dbus_proxy(property)]
+    fn profile_changes(&self) -> zbus::Result<u64>;
+
+    /// Number of failed writes per cpufreq policy, or per sysfs file for system-wide knobs.
+    #[dbus_proxy(property)]
+    fn write_failures(&self) -> zbus::Result<HashMap<String, u32>>;
+
+    /// Seconds since a different profile was last applied, or 0 before the first.
+    #[dbus_proxy(property)]
+    fn seconds_since_last_change(&self) -> zbus::Result<u64>;
+
+    /// Seconds spent in each profile.
+    #[dbus_proxy(property)]
+    fn profile_time(&self) -> zbus::Result<HashMap<String, u64>>;
+}
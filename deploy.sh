@@ -5,5 +5,12 @@ cargo build --release
 sudo cp target/release/pstate_update /usr/local/bin/
 sudo mkdir -p /etc/pstate_update
 sudo cp config.toml /etc/pstate_update/
-sudo cp pstate_update.service /etc/systemd/system/
+sudo cp pstate_update.service pstate_update-ondemand.service pstate_update.socket \
+    /etc/systemd/system/
+sudo cp org.pstate_update1.conf /etc/dbus-1/system.d/
+sudo cp org.pstate_update1.service /usr/share/dbus-1/system-services/
+sudo cp org.pstate_update1.policy /usr/share/polkit-1/actions/
+sudo mkdir -p /usr/share/dbus-1/interfaces
+target/release/pstate_update introspect \
+    | sudo tee /usr/share/dbus-1/interfaces/org.pstate_update1.xml > /dev/null
 sudo systemctl daemon-reload
@@ -0,0 +1,3026 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::drm;
+use crate::notify::Urgency;
+use crate::power::{BatteryWarning, PowerSource};
+use crate::sysfs;
+use crate::{EnergyPerformancePreference, PPDPowerProfile, ScalingGovernor};
+
+/// Config files tried in order when no explicit config file is given.
+pub const DEFAULT_SEARCH_PATH: &str = "/etc/pstate_update/config.toml:config.toml";
+
+/// Default configuration, used when no config file is found. This is the sample
+/// `config.toml` in the repository root.
+pub const DEFAULT_CONFIG: &str = include_str!("../config.toml");
+
+/// Extensions of the supported config formats. A `.toml` candidate in the search path is
+/// also tried with the other extensions, in this order.
+const CONFIG_EXTENSIONS: [&str; 4] = ["toml", "yaml", "yml", "json"];
+
+/// A value for each power profile.
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileMap<T> {
+    power_saver: T,
+    balanced: T,
+    performance: T,
+}
+
+impl<T> ProfileMap<T> {
+    pub fn for_profile(&self, profile: &PPDPowerProfile) -> &T {
+        match profile {
+            PPDPowerProfile::Performance => &self.performance,
+            PPDPowerProfile::Balanced => &self.balanced,
+            PPDPowerProfile::PowerSaver => &self.power_saver,
+        }
+    }
+}
+
+pub type EPPConfig = ProfileMap<EnergyPerformancePreference>;
+pub type GovernorConfig = ProfileMap<ScalingGovernor>;
+
+/// A CPU frequency limit, either absolute or relative to the policy's `cpuinfo_max_freq`.
+#[derive(Clone, Copy, serde::Deserialize)]
+#[serde(try_from = "NumberOrString")]
+pub enum Frequency {
+    KHz(u64),
+    Percent(u32),
+}
+
+/// A config value that may be written either as an integer or as a string.
+pub enum NumberOrString {
+    Number(i64),
+    Text(String),
+}
+
+impl<'de> serde::Deserialize<'de> for NumberOrString {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = NumberOrString;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "an integer or a string")
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                Ok(NumberOrString::Number(v))
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                i64::try_from(v)
+                    .map(NumberOrString::Number)
+                    .map_err(|_| E::custom(format!("{v} is too large")))
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(NumberOrString::Text(v.to_string()))
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+/// Value of a sysctl, written to procfs as is.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(from = "NumberOrString")]
+pub struct SysctlValue(pub String);
+
+impl From<NumberOrString> for SysctlValue {
+    fn from(value: NumberOrString) -> Self {
+        match value {
+            NumberOrString::Number(n) => SysctlValue(n.to_string()),
+            NumberOrString::Text(s) => SysctlValue(s),
+        }
+    }
+}
+
+/// Sysctls to set, keyed by name like `vm.dirty_writeback_centisecs`.
+pub type Sysctls = BTreeMap<String, SysctlValue>;
+
+/// A file in `/sys`, the only place vendor knobs may be written to.
+#[derive(serde::Deserialize)]
+#[serde(try_from = "String")]
+pub struct SysfsFile(pub path::PathBuf);
+
+impl TryFrom<String> for SysfsFile {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let file = path::PathBuf::from(&s);
+        let parent_dir = file.components().any(|c| c == path::Component::ParentDir);
+        if !s.starts_with("/sys/") || parent_dir {
+            return Err(format!("{s} is not a file in /sys."));
+        }
+        Ok(SysfsFile(file))
+    }
+}
+
+impl serde::Serialize for SysfsFile {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&self.0.display())
+    }
+}
+
+/// A vendor knob in sysfs, like `throttle_thermal_policy` of asus-wmi, with the value to
+/// write for each profile. Profiles without a value leave it alone.
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct VendorKnob {
+    pub path: SysfsFile,
+    #[serde(flatten)]
+    pub values: ProfileMap<Option<SysctlValue>>,
+}
+
+/// Frequencies may be given as a plain number of kHz or as a string with a unit.
+impl TryFrom<NumberOrString> for Frequency {
+    type Error = String;
+
+    fn try_from(value: NumberOrString) -> Result<Self, Self::Error> {
+        match value {
+            NumberOrString::Number(khz) => u64::try_from(khz)
+                .map(Frequency::KHz)
+                .map_err(|_| format!("Frequency {khz} must not be negative.")),
+            NumberOrString::Text(s) => Frequency::from_str(&s),
+        }
+    }
+}
+
+impl FromStr for Frequency {
+    type Err = String;
+
+    /// Parse frequencies like `1800000`, `1800000kHz`, `1800MHz`, `1.8GHz` or `60%`.
+    fn from_str(input: &str) -> Result<Frequency, Self::Err> {
+        let s = input.trim();
+        if let Some(percent) = s.strip_suffix('%') {
+            return match percent.trim().parse::<u32>() {
+                Ok(p) if p <= 100 => Ok(Frequency::Percent(p)),
+                _ => Err(format!(
+                    "Invalid frequency percentage '{input}'. Expected 0-100%."
+                )),
+            };
+        }
+        let split = s
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let scale = match unit.trim().to_ascii_lowercase().as_str() {
+            "" | "khz" => 1.0,
+            "mhz" => 1_000.0,
+            "ghz" => 1_000_000.0,
+            _ => {
+                return Err(format!(
+                    "Invalid frequency unit in '{input}'. Expected kHz, MHz, GHz or %."
+                ))
+            }
+        };
+        match number.parse::<f64>() {
+            Ok(n) => Ok(Frequency::KHz((n * scale).round() as u64)),
+            Err(_) => Err(format!("Invalid frequency '{input}'.")),
+        }
+    }
+}
+
+impl fmt::Display for Frequency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Frequency::KHz(khz) => write!(f, "{khz} kHz"),
+            Frequency::Percent(p) => write!(f, "{p}%"),
+        }
+    }
+}
+
+impl serde::Serialize for Frequency {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl Frequency {
+    /// Absolute frequency in kHz. Percentages need the policy's `cpuinfo_max_freq`, and
+    /// resolve to `None` without it.
+    pub fn to_khz(self, cpuinfo_max_khz: Option<u64>) -> Option<u64> {
+        match self {
+            Frequency::KHz(khz) => Some(khz),
+            Frequency::Percent(p) => Some(cpuinfo_max_khz? * u64::from(p) / 100),
+        }
+    }
+}
+
+/// x86 energy/performance bias (EPB), from 0 (performance) to 15 (power). Written as a
+/// number or as one of the names the kernel uses, like `balance-power`.
+#[derive(Clone, Copy, serde::Deserialize)]
+#[serde(try_from = "NumberOrString")]
+pub struct EnergyPerfBias(pub u8);
+
+/// Names of EPB values, as accepted by the kernel.
+const EPB_NAMES: [(&str, u8); 5] = [
+    ("performance", 0),
+    ("balance-performance", 4),
+    ("normal", 6),
+    ("balance-power", 8),
+    ("power", 15),
+];
+
+impl TryFrom<NumberOrString> for EnergyPerfBias {
+    type Error = String;
+
+    fn try_from(value: NumberOrString) -> Result<Self, Self::Error> {
+        match value {
+            NumberOrString::Number(n @ 0..=15) => Ok(EnergyPerfBias(n as u8)),
+            NumberOrString::Number(n) => Err(format!("EPB {n} must be between 0 and 15.")),
+            NumberOrString::Text(s) => EPB_NAMES
+                .iter()
+                .find(|(name, _)| *name == s)
+                .map(|(_, value)| EnergyPerfBias(*value))
+                .ok_or_else(|| {
+                    let names: Vec<_> = EPB_NAMES.iter().map(|(name, _)| *name).collect();
+                    format!(
+                        "Invalid EPB '{s}'. Expected 0-15 or one of: {}",
+                        names.join(", ")
+                    )
+                }),
+        }
+    }
+}
+
+impl fmt::Display for EnergyPerfBias {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl serde::Serialize for EnergyPerfBias {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(self.0)
+    }
+}
+
+/// NVMe latency tolerance in microseconds. The kernel reads it as a signed 32-bit number.
+#[derive(Clone, Copy, serde::Deserialize)]
+#[serde(try_from = "u64")]
+pub struct LatencyUs(pub u32);
+
+impl TryFrom<u64> for LatencyUs {
+    type Error = String;
+
+    fn try_from(us: u64) -> Result<Self, Self::Error> {
+        match u32::try_from(us) {
+            Ok(us) if us <= i32::MAX as u32 => Ok(LatencyUs(us)),
+            _ => Err(format!(
+                "NVMe latency tolerance {us} us must be at most {} us.",
+                i32::MAX
+            )),
+        }
+    }
+}
+
+impl fmt::Display for LatencyUs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl serde::Serialize for LatencyUs {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.0)
+    }
+}
+
+/// A list of logical CPUs, written like `0-7,16-23` in the config.
+#[derive(serde::Deserialize, Default, PartialEq)]
+#[serde(try_from = "String")]
+pub struct CpuList(pub Vec<u32>);
+
+impl TryFrom<String> for CpuList {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        sysfs::parse_cpu_list(&s).map(CpuList)
+    }
+}
+
+impl fmt::Display for CpuList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut cpus = self.0.clone();
+        cpus.sort_unstable();
+        cpus.dedup();
+        let mut ranges: Vec<(u32, u32)> = Vec::new();
+        for cpu in cpus {
+            match ranges.last_mut() {
+                Some((_, last)) if *last + 1 == cpu => *last = cpu,
+                _ => ranges.push((cpu, cpu)),
+            }
+        }
+        let parts: Vec<String> = ranges
+            .iter()
+            .map(|(first, last)| match first == last {
+                true => first.to_string(),
+                false => format!("{first}-{last}"),
+            })
+            .collect();
+        write!(f, "{}", parts.join(","))
+    }
+}
+
+impl serde::Serialize for CpuList {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// CPUs a profile takes offline: a number of CPUs, counted from the highest, or a list.
+#[derive(serde::Deserialize)]
+#[serde(try_from = "NumberOrString")]
+pub enum OfflineCpus {
+    Count(usize),
+    List(CpuList),
+}
+
+impl TryFrom<NumberOrString> for OfflineCpus {
+    type Error = String;
+
+    fn try_from(value: NumberOrString) -> Result<Self, Self::Error> {
+        match value {
+            NumberOrString::Number(n) => usize::try_from(n)
+                .map(OfflineCpus::Count)
+                .map_err(|_| format!("Number of CPUs {n} must not be negative.")),
+            NumberOrString::Text(s) => CpuList::try_from(s).map(OfflineCpus::List),
+        }
+    }
+}
+
+impl OfflineCpus {
+    /// The CPUs to take offline among `hotplug_cpus`, those that can be taken offline in
+    /// ascending order.
+    pub fn resolve(&self, hotplug_cpus: &[u32]) -> Vec<u32> {
+        match self {
+            OfflineCpus::Count(n) => hotplug_cpus.iter().rev().take(*n).copied().collect(),
+            OfflineCpus::List(list) => list.0.clone(),
+        }
+    }
+}
+
+impl serde::Serialize for OfflineCpus {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            OfflineCpus::Count(n) => serializer.serialize_u64(*n as u64),
+            OfflineCpus::List(list) => list.serialize(serializer),
+        }
+    }
+}
+
+/// Operation mode of the amd_pstate driver. Only `active` offers EPP.
+#[derive(Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AmdPstateMode {
+    Active,
+    Guided,
+    Passive,
+}
+
+impl AmdPstateMode {
+    /// Every mode amd_pstate can be switched to.
+    pub const ALL: [AmdPstateMode; 3] = [
+        AmdPstateMode::Active,
+        AmdPstateMode::Guided,
+        AmdPstateMode::Passive,
+    ];
+}
+
+impl fmt::Display for AmdPstateMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AmdPstateMode::Active => write!(f, "active"),
+            AmdPstateMode::Guided => write!(f, "guided"),
+            AmdPstateMode::Passive => write!(f, "passive"),
+        }
+    }
+}
+
+/// Knob values that replace the underlying mapping for some profiles. Profiles that are
+/// left out keep the underlying mapping.
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Overlay {
+    pub epp: ProfileMap<Option<EnergyPerformancePreference>>,
+    pub scaling_governor: ProfileMap<Option<ScalingGovernor>>,
+    pub min_freq: ProfileMap<Option<Frequency>>,
+    pub max_freq: ProfileMap<Option<Frequency>>,
+}
+
+impl Overlay {
+    /// Whether the overlay sets any value at all.
+    pub fn is_empty(&self) -> bool {
+        !(is_set(&self.epp)
+            || is_set(&self.scaling_governor)
+            || is_set(&self.min_freq)
+            || is_set(&self.max_freq))
+    }
+}
+
+/// The value a custom profile sets, or else the value the map has for the profile.
+fn pick<'a, T>(
+    custom: Option<&'a T>,
+    map: &'a ProfileMap<Option<T>>,
+    profile: &PPDPowerProfile,
+) -> Option<&'a T> {
+    custom.or(map.for_profile(profile).as_ref())
+}
+
+/// Whether any profile in the map has a value.
+fn is_set<T>(map: &ProfileMap<Option<T>>) -> bool {
+    PPDPowerProfile::ALL
+        .iter()
+        .any(|p| map.for_profile(p).is_some())
+}
+
+/// Declares a section with overlay knobs as written in the config: the section's own
+/// fields followed by the knobs of `Overlay`. The knobs are listed rather than flattened
+/// from `Overlay`, so that errors keep their full key path.
+macro_rules! overlay_fields {
+    ($(#[$meta:meta])* struct $name:ident { $($fields:tt)* }) => {
+        $(#[$meta])*
+        #[serde(deny_unknown_fields)]
+        struct $name {
+            $($fields)*
+            #[serde(default)]
+            epp: ProfileMap<Option<EnergyPerformancePreference>>,
+            #[serde(default)]
+            scaling_governor: ProfileMap<Option<ScalingGovernor>>,
+            #[serde(default)]
+            min_freq: ProfileMap<Option<Frequency>>,
+            #[serde(default)]
+            max_freq: ProfileMap<Option<Frequency>>,
+        }
+
+        impl $name {
+            /// Move the knobs out into an `Overlay`.
+            fn take_knobs(&mut self) -> Overlay {
+                Overlay {
+                    epp: std::mem::take(&mut self.epp),
+                    scaling_governor: std::mem::take(&mut self.scaling_governor),
+                    min_freq: std::mem::take(&mut self.min_freq),
+                    max_freq: std::mem::take(&mut self.max_freq),
+                }
+            }
+        }
+    };
+}
+
+/// Overrides for a single cpufreq policy, or for a range of CPUs when `cpus` is set.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(from = "PolicyOverrideFields")]
+pub struct PolicyOverride {
+    /// Logical CPUs the section applies to. When set, the section name is only a label,
+    /// and the section applies to every policy whose CPUs are all in the list.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpus: Option<CpuList>,
+    #[serde(flatten)]
+    pub knobs: Overlay,
+}
+
+overlay_fields! {
+    /// Policy sections as written in the config.
+    #[derive(serde::Deserialize)]
+    struct PolicyOverrideFields {
+        #[serde(default)]
+        cpus: Option<CpuList>,
+    }
+}
+
+impl From<PolicyOverrideFields> for PolicyOverride {
+    fn from(mut f: PolicyOverrideFields) -> Self {
+        PolicyOverride {
+            knobs: f.take_knobs(),
+            cpus: f.cpus,
+        }
+    }
+}
+
+/// Time of day in minutes since midnight, written like `22:00` in the config.
+#[derive(serde::Deserialize, Clone, Copy, PartialEq)]
+#[serde(try_from = "String")]
+pub struct TimeOfDay(pub u32);
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let parsed = s.split_once(':').and_then(|(h, m)| {
+            let (h, m): (u32, u32) = (h.trim().parse().ok()?, m.trim().parse().ok()?);
+            (h < 24 && m < 60).then_some(h * 60 + m)
+        });
+        parsed
+            .map(TimeOfDay)
+            .ok_or_else(|| format!("Invalid time of day {s}, expected HH:MM"))
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.0 / 60, self.0 % 60)
+    }
+}
+
+impl serde::Serialize for TimeOfDay {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Overrides that apply during a daily time window.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(from = "ScheduleFields")]
+pub struct Schedule {
+    pub from: TimeOfDay,
+    /// End of the window, which wraps past midnight if it is before `from`.
+    pub to: TimeOfDay,
+    #[serde(flatten)]
+    pub knobs: Overlay,
+}
+
+impl Schedule {
+    /// Whether the window covers the given time of day.
+    pub fn covers(&self, time: TimeOfDay) -> bool {
+        let (from, to, t) = (self.from.0, self.to.0, time.0);
+        if from <= to {
+            from <= t && t < to
+        } else {
+            t >= from || t < to
+        }
+    }
+}
+
+overlay_fields! {
+    /// Schedules as written in the config.
+    #[derive(serde::Deserialize)]
+    struct ScheduleFields {
+        from: TimeOfDay,
+        to: TimeOfDay,
+    }
+}
+
+impl From<ScheduleFields> for Schedule {
+    fn from(mut f: ScheduleFields) -> Self {
+        Schedule {
+            knobs: f.take_knobs(),
+            from: f.from,
+            to: f.to,
+        }
+    }
+}
+
+/// Overrides that apply while the laptop is docked, along with how docking is detected.
+/// The laptop counts as docked while on AC and either an external display is connected or
+/// `match_path` exists.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(from = "DockedFields")]
+pub struct Docked {
+    /// Whether a connected external display counts as docked.
+    pub external_display: bool,
+    /// File whose existence counts as docked, e.g. a dock's device in sysfs or a symlink
+    /// created by a udev rule.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub match_path: Option<path::PathBuf>,
+    #[serde(flatten)]
+    pub knobs: Overlay,
+}
+
+overlay_fields! {
+    /// The docked section as written in the config.
+    #[derive(serde::Deserialize)]
+    struct DockedFields {
+        #[serde(default = "default_true")]
+        external_display: bool,
+        #[serde(default)]
+        match_path: Option<path::PathBuf>,
+    }
+}
+
+impl From<DockedFields> for Docked {
+    fn from(mut f: DockedFields) -> Self {
+        Docked {
+            knobs: f.take_knobs(),
+            external_display: f.external_display,
+            match_path: f.match_path,
+        }
+    }
+}
+
+/// Overrides that apply while an application holds a profile through power-profiles-daemon,
+/// e.g. a game holding `performance`.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(from = "HoldFields")]
+pub struct Hold {
+    /// Whether battery rules are ignored while a hold is in place.
+    pub suppress_battery_rules: bool,
+    #[serde(flatten)]
+    pub knobs: Overlay,
+}
+
+overlay_fields! {
+    /// The hold section as written in the config.
+    #[derive(serde::Deserialize)]
+    struct HoldFields {
+        #[serde(default)]
+        suppress_battery_rules: bool,
+    }
+}
+
+impl From<HoldFields> for Hold {
+    fn from(mut f: HoldFields) -> Self {
+        Hold {
+            knobs: f.take_knobs(),
+            suppress_battery_rules: f.suppress_battery_rules,
+        }
+    }
+}
+
+/// Overrides that apply once logind has reported every session idle for `after_s`
+/// seconds, e.g. for a desktop left alone.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(from = "IdleFields")]
+pub struct Idle {
+    /// Seconds of idleness before the overrides apply.
+    pub after_s: u64,
+    #[serde(flatten)]
+    pub knobs: Overlay,
+}
+
+overlay_fields! {
+    /// The idle section as written in the config.
+    #[derive(serde::Deserialize)]
+    struct IdleFields {
+        #[serde(default = "default_idle_after_s")]
+        after_s: u64,
+    }
+}
+
+impl From<IdleFields> for Idle {
+    fn from(mut f: IdleFields) -> Self {
+        Idle {
+            knobs: f.take_knobs(),
+            after_s: f.after_s,
+        }
+    }
+}
+
+fn default_idle_after_s() -> u64 {
+    300
+}
+
+/// How failed sysfs writes and D-Bus connections are retried. Delays double with every
+/// further attempt.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    /// Attempts per sysfs write before it is reported as failed.
+    pub write_attempts: u32,
+    /// Delay before the first retry of a failed write.
+    pub write_backoff_ms: u64,
+    /// Consecutive D-Bus failures tolerated before the daemon exits, or 0 to retry
+    /// forever.
+    pub dbus_attempts: u32,
+    /// Delay before the first reconnect after a D-Bus failure.
+    pub dbus_backoff_ms: u64,
+    /// Upper bound for the delay between reconnects.
+    pub dbus_max_backoff_ms: u64,
+    /// How long to wait for power-profiles-daemon to appear on the bus before counting it
+    /// as a D-Bus failure, or 0 to wait forever.
+    pub ppd_wait_ms: u64,
+    /// How long to wait for the reply to a D-Bus call, e.g. to power-profiles-daemon,
+    /// before giving up on it, or 0 to wait forever.
+    pub dbus_timeout_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            write_attempts: 3,
+            write_backoff_ms: 50,
+            dbus_attempts: 5,
+            dbus_backoff_ms: 1000,
+            dbus_max_backoff_ms: 30_000,
+            ppd_wait_ms: 120_000,
+            dbus_timeout_ms: 5000,
+        }
+    }
+}
+
+/// Delay before the given retry, counting from 1, starting at `base_ms` and doubling every
+/// time up to `max_ms`.
+fn backoff(base_ms: u64, retry: u32, max_ms: u64) -> Duration {
+    let factor = 1u64
+        .checked_shl(retry.saturating_sub(1))
+        .unwrap_or(u64::MAX);
+    Duration::from_millis(base_ms.saturating_mul(factor).min(max_ms))
+}
+
+impl RetryConfig {
+    /// Delay before the given retry of a failed write, counting from 1.
+    pub fn write_backoff(&self, retry: u32) -> Duration {
+        backoff(self.write_backoff_ms, retry, u64::MAX)
+    }
+
+    /// Delay before reconnecting after the given number of consecutive D-Bus failures.
+    pub fn dbus_backoff(&self, failures: u32) -> Duration {
+        backoff(self.dbus_backoff_ms, failures, self.dbus_max_backoff_ms)
+    }
+
+    /// Whether the daemon should give up after the given number of consecutive D-Bus
+    /// failures.
+    pub fn dbus_exhausted(&self, failures: u32) -> bool {
+        self.dbus_attempts != 0 && failures >= self.dbus_attempts
+    }
+
+    /// How long to wait for the reply to a D-Bus call.
+    pub fn dbus_timeout(&self) -> Duration {
+        Duration::from_millis(self.dbus_timeout_ms)
+    }
+}
+
+/// Desktop notifications shown by the daemon.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotifyConfig {
+    /// Whether failed writes and profile changes that cannot be processed are shown.
+    pub errors: bool,
+    /// Minimum time between two notifications about failures, in seconds.
+    pub error_interval_s: u64,
+    /// Whether every profile change is shown along with the EPP and governor it wrote.
+    pub profile_change: bool,
+    /// Urgency of the profile change notifications.
+    pub urgency: Urgency,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        NotifyConfig {
+            errors: true,
+            error_interval_s: 300,
+            profile_change: false,
+            urgency: Urgency::Low,
+        }
+    }
+}
+
+/// A power in watts, which must be positive.
+#[derive(Clone, Copy, serde::Deserialize)]
+#[serde(try_from = "f64")]
+pub struct Watts(pub f64);
+
+impl TryFrom<f64> for Watts {
+    type Error = String;
+
+    fn try_from(watts: f64) -> Result<Self, Self::Error> {
+        if watts.is_finite() && watts > 0.0 {
+            Ok(Watts(watts))
+        } else {
+            Err(format!("Power {watts} W must be a positive number."))
+        }
+    }
+}
+
+impl Watts {
+    /// The power in microwatts, as sysfs takes it.
+    pub fn to_microwatts(self) -> u64 {
+        (self.0 * 1e6).round() as u64
+    }
+}
+
+impl fmt::Display for Watts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} W", self.0)
+    }
+}
+
+impl serde::Serialize for Watts {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.0)
+    }
+}
+
+/// RAPL package power limits for a profile. Limits that are not set are left alone.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PowerLimits {
+    /// Sustained power limit (PL1) in watts.
+    pub pl1_watts: Option<Watts>,
+    /// Time window of PL1 in milliseconds.
+    pub pl1_window_ms: Option<u64>,
+    /// Burst power limit (PL2) in watts.
+    pub pl2_watts: Option<Watts>,
+    /// Time window of PL2 in milliseconds.
+    pub pl2_window_ms: Option<u64>,
+}
+
+/// Frequency caps of Intel integrated GPUs for a profile, in MHz. Caps that are not set
+/// are left alone.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct GpuFreqCaps {
+    pub min_mhz: Option<u32>,
+    pub max_mhz: Option<u32>,
+    /// Frequency the GPU boosts to under load, at most `max_mhz`.
+    pub boost_mhz: Option<u32>,
+}
+
+/// Which amdgpu cards the `[gpu_level]` performance levels are written to.
+#[derive(Clone, Default, PartialEq, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub enum GpuTarget {
+    #[default]
+    All,
+    /// Only GPUs integrated into the CPU, leaving a discrete GPU to its own driver.
+    Integrated,
+    Discrete,
+    /// One card by its DRM name, e.g. `card1`, or its PCI slot, e.g. `0000:03:00.0`, for
+    /// systems where telling integrated from discrete GPUs goes wrong.
+    Card(String),
+}
+
+impl TryFrom<String> for GpuTarget {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let is_card = s
+            .strip_prefix("card")
+            .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()));
+        let is_slot = s.len() == 12
+            && s.char_indices().all(|(i, c)| match i {
+                4 | 7 => c == ':',
+                10 => c == '.',
+                _ => c.is_ascii_hexdigit(),
+            });
+        match s.as_str() {
+            "all" => Ok(GpuTarget::All),
+            "integrated" => Ok(GpuTarget::Integrated),
+            "discrete" => Ok(GpuTarget::Discrete),
+            _ if is_card || is_slot => Ok(GpuTarget::Card(s)),
+            _ => Err(format!(
+                "Invalid GPU target {s:?}, expected all, integrated, discrete, a card name \
+                 like card1 or a PCI slot like 0000:03:00.0."
+            )),
+        }
+    }
+}
+
+impl serde::Serialize for GpuTarget {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl GpuTarget {
+    /// Whether the amdgpu device in the given device directory is targeted.
+    pub fn includes(&self, device: &path::Path) -> bool {
+        match self {
+            GpuTarget::All => true,
+            GpuTarget::Integrated => drm::is_integrated(device),
+            GpuTarget::Discrete => !drm::is_integrated(device),
+            GpuTarget::Card(card) => {
+                let name = |p: &path::Path| p.file_name().is_some_and(|n| n == card.as_str());
+                device.parent().is_some_and(name) || device.canonicalize().is_ok_and(|p| name(&p))
+            }
+        }
+    }
+
+    /// amdgpu devices in `drm_path` that are targeted.
+    pub fn devices(&self, drm_path: &path::Path) -> Vec<path::PathBuf> {
+        drm::find_amdgpu_devices(drm_path)
+            .into_iter()
+            .filter(|d| self.includes(d))
+            .collect()
+    }
+}
+
+impl fmt::Display for GpuTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GpuTarget::All => write!(f, "all"),
+            GpuTarget::Integrated => write!(f, "integrated"),
+            GpuTarget::Discrete => write!(f, "discrete"),
+            GpuTarget::Card(card) => write!(f, "{card}"),
+        }
+    }
+}
+
+/// Global knobs of the intel_pstate driver for a profile. Knobs that are not set are left
+/// alone.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct IntelPstate {
+    /// Disable turbo frequencies.
+    pub no_turbo: Option<bool>,
+    /// Lowest performance the driver may select, in percent of the maximum.
+    pub min_perf_pct: Option<u8>,
+    /// Highest performance the driver may select, in percent of the maximum.
+    pub max_perf_pct: Option<u8>,
+    /// Let the driver boost HWP performance when tasks wake up from I/O.
+    pub hwp_dynamic_boost: Option<bool>,
+}
+
+/// Values forced on every profile while on battery with the battery level below a
+/// threshold.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatteryRule {
+    /// Battery level in percent below which the rule applies.
+    pub below: f64,
+    pub epp: Option<EnergyPerformancePreference>,
+    pub scaling_governor: Option<ScalingGovernor>,
+    pub min_freq: Option<Frequency>,
+    pub max_freq: Option<Frequency>,
+}
+
+/// Upper frequency limits forced on top of the power-saver mapping while UPower warns
+/// about the battery.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct LowBattery {
+    /// Upper frequency limit while the battery is low.
+    pub max_freq: Option<Frequency>,
+    /// Upper frequency limit while the battery is critical. Falls back to `max_freq`.
+    pub critical_max_freq: Option<Frequency>,
+}
+
+/// Profile applied instead of the active one while a game has GameMode requested.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct GameMode {
+    /// Built-in profile or `[profile]` section to apply.
+    #[serde(default = "default_gamemode_profile")]
+    pub profile: String,
+}
+
+/// Profile applied instead of the active one while the screen is locked.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScreenLock {
+    /// Built-in profile or `[profile]` section to apply.
+    #[serde(default = "default_screen_lock_profile")]
+    pub profile: String,
+}
+
+/// Where and for whom the daemon serves its HTTP API.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct HttpConfig {
+    /// Address and port to listen on.
+    #[serde(default = "default_http_listen")]
+    pub listen: String,
+    /// Bearer token required to change the profile. Changes are refused without one.
+    pub token: Option<String>,
+}
+
+/// MQTT broker the daemon reports applied profiles to and takes commands from.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MqttConfig {
+    /// Host name or address of the broker.
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    /// Prefix of the topics used by this machine. Defaults to `pstate_update/<hostname>`.
+    pub topic: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Connect over TLS. Without it, the connection and the password are plain text.
+    #[serde(default)]
+    pub tls: bool,
+    /// PEM file with the CA certificates to verify the broker against, instead of the
+    /// system's.
+    pub ca: Option<path::PathBuf>,
+}
+
+/// Values forced on every profile while a temperature sensor runs hot.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThermalRule {
+    /// hwmon sensor, as `<chip>` or `<chip>/<label>`, e.g. `k10temp/Tctl`.
+    pub sensor: String,
+    /// Temperature in degrees Celsius at or above which the rule starts to apply.
+    pub above: f64,
+    /// How many degrees the temperature must drop below `above` before the rule stops
+    /// applying again.
+    #[serde(default = "default_hysteresis")]
+    pub hysteresis: f64,
+    pub epp: Option<EnergyPerformancePreference>,
+    pub scaling_governor: Option<ScalingGovernor>,
+    pub min_freq: Option<Frequency>,
+    pub max_freq: Option<Frequency>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_hysteresis() -> f64 {
+    5.0
+}
+
+fn default_gamemode_profile() -> String {
+    PPDPowerProfile::Performance.to_string()
+}
+
+fn default_http_listen() -> String {
+    "127.0.0.1:8037".to_string()
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_screen_lock_profile() -> String {
+    PPDPowerProfile::PowerSaver.to_string()
+}
+
+/// Knobs set directly by a rule or custom profile, which take precedence over the
+/// profile mapping.
+struct Forced<'a> {
+    epp: Option<&'a EnergyPerformancePreference>,
+    scaling_governor: Option<&'a ScalingGovernor>,
+    min_freq: Option<&'a Frequency>,
+    max_freq: Option<&'a Frequency>,
+}
+
+/// Mapping for a profile that power-profiles-daemon may report besides the built-in
+/// ones, e.g. when tuned-ppd provides the profiles.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomProfile {
+    /// Built-in profile whose mapping is used for the knobs that are not set here.
+    #[serde(default = "default_base_profile")]
+    pub base: PPDPowerProfile,
+    pub epp: Option<EnergyPerformancePreference>,
+    pub scaling_governor: Option<ScalingGovernor>,
+    pub min_freq: Option<Frequency>,
+    pub max_freq: Option<Frequency>,
+    pub apply_delay_ms: Option<u64>,
+    pub exec_before: Option<Vec<String>>,
+    pub exec_after: Option<Vec<String>>,
+    pub cpuidle_governor: Option<String>,
+    pub platform_profile: Option<String>,
+    pub amd_pstate_mode: Option<AmdPstateMode>,
+    pub boost: Option<bool>,
+    pub smt: Option<bool>,
+    pub offline_cpus: Option<OfflineCpus>,
+    pub max_cstate: Option<String>,
+    pub epb: Option<EnergyPerfBias>,
+    pub rapl: Option<PowerLimits>,
+    pub intel_pstate: Option<IntelPstate>,
+    pub gpu_level: Option<String>,
+    pub gpu_power_profile: Option<String>,
+    pub gpu_power_cap: Option<Watts>,
+    pub i915: Option<GpuFreqCaps>,
+    pub sysctls: Option<Sysctls>,
+    pub nvme_latency_us: Option<LatencyUs>,
+}
+
+/// EPP and governor forced on every profile through the daemon interface, e.g. to run a
+/// build at full performance.
+pub struct UserOverride {
+    pub epp: Option<EnergyPerformancePreference>,
+    pub scaling_governor: Option<ScalingGovernor>,
+}
+
+impl UserOverride {
+    /// Parse the values given to the daemon interface, where an empty string leaves the
+    /// knob to the mapping.
+    pub fn parse(epp: &str, governor: &str) -> Result<Self, String> {
+        let epp = match epp {
+            "" => None,
+            epp => Some(
+                EnergyPerformancePreference::from_str(epp)
+                    .map_err(|_| format!("Unknown EPP '{epp}'."))?,
+            ),
+        };
+        let scaling_governor = match governor {
+            "" => None,
+            gov => Some(
+                ScalingGovernor::from_str(gov)
+                    .map_err(|_| format!("Unknown scaling governor '{gov}'."))?,
+            ),
+        };
+        Ok(UserOverride {
+            epp,
+            scaling_governor,
+        })
+    }
+}
+
+impl fmt::Display for UserOverride {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let epp = self.epp.as_ref().map(|e| e.to_string());
+        let gov = self.scaling_governor.as_ref().map(|g| g.to_string());
+        write!(
+            f,
+            "EPP {}, governor {}",
+            epp.as_deref().unwrap_or("unchanged"),
+            gov.as_deref().unwrap_or("unchanged")
+        )
+    }
+}
+
+fn default_base_profile() -> PPDPowerProfile {
+    PPDPowerProfile::Balanced
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Built-in profile whose mapping is used for profiles that are neither built in nor
+    /// have a `[profile]` section.
+    #[serde(default)]
+    pub unknown_profile: Option<PPDPowerProfile>,
+    /// Built-in profile applied while power-profiles-daemon is absent, once it has not
+    /// appeared within `retry.ppd_wait_ms`. Without it, its absence counts as a D-Bus
+    /// failure.
+    #[serde(default)]
+    pub fallback_profile: Option<PPDPowerProfile>,
+    /// EPP per profile. Every EPP is kept if left out, e.g. on acpi-cpufreq which has
+    /// none.
+    #[serde(default)]
+    pub epp: EPPConfig,
+    pub scaling_governor: GovernorConfig,
+    /// CPUs that are never touched, e.g. isolated cores. Policies with any of these CPUs
+    /// are left out entirely.
+    #[serde(default)]
+    pub exclude_cpus: CpuList,
+    /// Whether EPPs are written at all. When false, EPP is treated as `keep` everywhere.
+    #[serde(default = "default_true")]
+    pub manage_epp: bool,
+    /// Whether scaling governors are written at all. When false, the governor is treated
+    /// as `keep` everywhere.
+    #[serde(default = "default_true")]
+    pub manage_governor: bool,
+    /// Whether the daemon claims the power-profiles-daemon name and implements its
+    /// interface itself, for systems without power-profiles-daemon.
+    #[serde(default)]
+    pub provider: bool,
+    /// Lower frequency limit per profile. Profiles without one leave the limit alone.
+    #[serde(default)]
+    pub min_freq: ProfileMap<Option<Frequency>>,
+    /// Upper frequency limit per profile. Profiles without one leave the limit alone.
+    #[serde(default)]
+    pub max_freq: ProfileMap<Option<Frequency>>,
+    /// Values used instead of the mapping above while on AC power.
+    #[serde(default)]
+    pub on_ac: Overlay,
+    /// Values used instead of the mapping above while on battery.
+    #[serde(default)]
+    pub on_battery: Overlay,
+    /// Per-policy overrides, keyed by policy directory name, e.g. `policy4`.
+    #[serde(default)]
+    pub policy: BTreeMap<String, PolicyOverride>,
+    /// Overrides for daily time windows.
+    #[serde(default)]
+    pub schedule: Vec<Schedule>,
+    /// Overrides while docked. Docking is not detected at all without this section.
+    #[serde(default)]
+    pub docked: Option<Docked>,
+    /// Overrides while an application holds a profile through power-profiles-daemon.
+    #[serde(default)]
+    pub hold: Option<Hold>,
+    /// Overrides while the machine is left idle.
+    #[serde(default)]
+    pub idle: Option<Idle>,
+    /// Values used instead of the mapping above while power-profiles-daemon reports the
+    /// performance profile as degraded.
+    #[serde(default)]
+    pub degraded: Overlay,
+    /// Custom profiles, keyed by the name power-profiles-daemon reports.
+    #[serde(default)]
+    pub profile: BTreeMap<String, CustomProfile>,
+    /// Alternative names for profiles, e.g. `low-power` for `power-saver`, keyed by the
+    /// name power-profiles-daemon reports. Names are matched ignoring case.
+    #[serde(default)]
+    pub aliases: BTreeMap<String, String>,
+    /// Minimum time to wait for ActiveProfile to settle before applying a change.
+    #[serde(default)]
+    pub debounce_ms: u64,
+    /// Time to wait before applying a change to the profile.
+    #[serde(default)]
+    pub apply_delay_ms: ProfileMap<Option<u64>>,
+    /// cpuidle governor per profile, e.g. `teo` or `menu`. Left alone if not set.
+    #[serde(default)]
+    pub cpuidle_governor: ProfileMap<Option<String>>,
+    /// ACPI platform profile per profile, e.g. `quiet` or `performance`. Left alone if
+    /// not set.
+    #[serde(default)]
+    pub platform_profile: ProfileMap<Option<String>>,
+    /// amd_pstate operation mode per profile, `active`, `guided` or `passive`. Left alone
+    /// if not set.
+    #[serde(default)]
+    pub amd_pstate_mode: ProfileMap<Option<AmdPstateMode>>,
+    /// Whether turbo frequencies are allowed per profile. Left alone if not set.
+    #[serde(default)]
+    pub boost: ProfileMap<Option<bool>>,
+    /// Whether SMT sibling threads are online per profile. Left alone if not set.
+    #[serde(default)]
+    pub smt: ProfileMap<Option<bool>>,
+    /// CPUs taken offline per profile. Every other CPU is brought back online, and CPUs
+    /// are left alone if not set.
+    #[serde(default)]
+    pub offline_cpus: ProfileMap<Option<OfflineCpus>>,
+    /// Deepest idle state allowed per profile, by name like `C1`, or `all`. Deeper states
+    /// are disabled and the others enabled. Left alone if not set.
+    #[serde(default)]
+    pub max_cstate: ProfileMap<Option<String>>,
+    /// x86 energy/performance bias per profile. Left alone if not set.
+    #[serde(default)]
+    pub epb: ProfileMap<Option<EnergyPerfBias>>,
+    /// RAPL package power limits per profile. Left alone if not set.
+    #[serde(default)]
+    pub rapl: ProfileMap<Option<PowerLimits>>,
+    /// intel_pstate global knobs per profile. Left alone if not set.
+    #[serde(default)]
+    pub intel_pstate: ProfileMap<Option<IntelPstate>>,
+    /// amdgpu DPM performance level per profile, e.g. `low` or `auto`. Left alone if not
+    /// set.
+    #[serde(default)]
+    pub gpu_level: ProfileMap<Option<String>>,
+    /// amdgpu cards the performance levels are written to: `all`, `integrated`,
+    /// `discrete`, or one card by name or PCI slot.
+    #[serde(default)]
+    pub gpu_target: GpuTarget,
+    /// amdgpu power profile mode per profile, by the name listed in
+    /// `pp_power_profile_mode`, e.g. `POWER_SAVING`. Left alone if not set.
+    #[serde(default)]
+    pub gpu_power_profile: ProfileMap<Option<String>>,
+    /// amdgpu power cap in watts per profile, clamped to the range the card accepts. Left
+    /// alone if not set.
+    #[serde(default)]
+    pub gpu_power_cap: ProfileMap<Option<Watts>>,
+    /// Intel integrated GPU frequency caps per profile. Left alone if not set.
+    #[serde(default)]
+    pub i915: ProfileMap<Option<GpuFreqCaps>>,
+    /// Sysctls per profile. Sysctls that a profile does not set are left alone.
+    #[serde(default)]
+    pub sysctls: ProfileMap<Option<Sysctls>>,
+    /// NVMe latency tolerance in microseconds per profile, which bounds the power states
+    /// APST may enter. Left alone if not set.
+    #[serde(default)]
+    pub nvme_latency_us: ProfileMap<Option<LatencyUs>>,
+    /// Vendor knobs in sysfs, written with the built-in profile, or the base profile of a
+    /// `[profile]` section.
+    #[serde(default)]
+    pub vendor_knob: Vec<VendorKnob>,
+    /// Values forced on every profile at low battery levels.
+    #[serde(default)]
+    pub battery_rule: Vec<BatteryRule>,
+    /// Forces the power-saver mapping while UPower warns about the battery. UPower's
+    /// warnings are ignored without this section.
+    #[serde(default)]
+    pub low_battery: Option<LowBattery>,
+    /// Values forced on every profile at high temperatures.
+    #[serde(default)]
+    pub thermal_rule: Vec<ThermalRule>,
+    /// Profile applied while games run under Feral GameMode. GameMode is not followed at
+    /// all without this section.
+    #[serde(default)]
+    pub gamemode: Option<GameMode>,
+    /// Profile applied while the screen is locked. The screen lock is not followed at all
+    /// without this section.
+    #[serde(default)]
+    pub screen_lock: Option<ScreenLock>,
+    /// Shell commands to run before switching to the profile.
+    #[serde(default)]
+    pub exec_before: ProfileMap<Option<Vec<String>>>,
+    /// Shell commands to run after switching to the profile.
+    #[serde(default)]
+    pub exec_after: ProfileMap<Option<Vec<String>>>,
+    /// How failed sysfs writes and D-Bus connections are retried.
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// Desktop notifications shown by the daemon.
+    #[serde(default)]
+    pub notify: NotifyConfig,
+    /// HTTP API of the daemon, only served by builds with the `http` feature.
+    #[serde(default)]
+    pub http: Option<HttpConfig>,
+    /// MQTT broker to report to, only used by builds with the `mqtt` feature.
+    #[serde(default)]
+    pub mqtt: Option<MqttConfig>,
+}
+
+impl PolicyOverride {
+    /// Whether the section's CPU list covers all of the given CPUs.
+    fn covers(&self, cpus: &[u32]) -> bool {
+        match &self.cpus {
+            Some(list) => !cpus.is_empty() && cpus.iter().all(|c| list.0.contains(c)),
+            None => false,
+        }
+    }
+}
+
+/// What a value is looked up for: a cpufreq policy under the current conditions.
+pub struct Scope<'a> {
+    pub policy: &'a str,
+    pub cpus: &'a [u32],
+    /// Current power source, if known.
+    pub power_source: Option<PowerSource>,
+    /// Custom profile being applied on top of the built-in one, if any.
+    pub custom: Option<&'a CustomProfile>,
+    /// Override set through the daemon interface, if any.
+    pub user_override: Option<&'a UserOverride>,
+    /// Current battery level in percent, if known.
+    pub battery_level: Option<f64>,
+    /// Thermal rules that currently apply, in config order.
+    pub thermal_rules: Vec<&'a ThermalRule>,
+    /// Schedules whose time window is current, in config order.
+    pub schedules: Vec<&'a Schedule>,
+    /// Whether the laptop is docked.
+    pub docked: bool,
+    /// Whether an application holds a profile through power-profiles-daemon.
+    pub held: bool,
+    /// Whether every session has been idle for long enough for the idle section.
+    pub idle: bool,
+    /// Whether power-profiles-daemon reports the performance profile as degraded.
+    pub degraded: bool,
+    /// Current battery warning from UPower, if any.
+    pub battery_warning: Option<BatteryWarning>,
+}
+
+impl Config {
+    /// Name a reported profile name is an alias for, or the name itself if it is not an
+    /// alias.
+    fn resolve_alias<'a>(&'a self, name: &'a str) -> &'a str {
+        self.aliases
+            .iter()
+            .find(|(alias, _)| alias.eq_ignore_ascii_case(name))
+            .map_or(name, |(_, target)| target.as_str())
+    }
+
+    /// Resolve a profile name reported by power-profiles-daemon into the built-in profile
+    /// whose mapping to use, along with the custom profile that refines it, if any.
+    pub fn resolve_profile(
+        &self,
+        name: &str,
+    ) -> Result<(PPDPowerProfile, Option<&CustomProfile>), String> {
+        let name = self.resolve_alias(name);
+        if let Ok(profile) = PPDPowerProfile::from_str(name) {
+            return Ok((profile, None));
+        }
+        if let Some(custom) = self.profile.get(name) {
+            return Ok((custom.base.clone(), Some(custom)));
+        }
+        match &self.unknown_profile {
+            Some(profile) => {
+                log::warn!("Unknown profile {name}, using the mapping for {profile}.");
+                Ok((profile.clone(), None))
+            }
+            None => Err(format!(
+                "Unknown profile {name}. Add a [profile.\"{name}\"] section or an [aliases] \
+                 entry, or set unknown_profile in the config to map it."
+            )),
+        }
+    }
+
+    /// How long to wait before applying a change to the named profile. This is the
+    /// longer of `debounce_ms` and the profile's `apply_delay_ms`.
+    pub fn apply_delay(&self, name: &str) -> Duration {
+        let name = self.resolve_alias(name);
+        let delay = match PPDPowerProfile::from_str(name) {
+            Ok(profile) => *self.apply_delay_ms.for_profile(&profile),
+            Err(_) => match (self.profile.get(name), &self.unknown_profile) {
+                (Some(custom), _) => custom
+                    .apply_delay_ms
+                    .or(*self.apply_delay_ms.for_profile(&custom.base)),
+                (None, Some(profile)) => *self.apply_delay_ms.for_profile(profile),
+                (None, None) => None,
+            },
+        };
+        Duration::from_millis(delay.unwrap_or(0).max(self.debounce_ms))
+    }
+
+    /// Whether each schedule's time window covers the given time of day, in config order.
+    pub fn schedules_at(&self, time: TimeOfDay) -> Vec<bool> {
+        self.schedule.iter().map(|s| s.covers(time)).collect()
+    }
+
+    /// Whether the mapping depends on the power source.
+    pub fn uses_power_source(&self) -> bool {
+        !(self.on_ac.is_empty()
+            && self.on_battery.is_empty()
+            && self.battery_rule.is_empty()
+            && self.docked.is_none())
+    }
+
+    /// Battery rule that applies on the given power source and battery level. This is the
+    /// rule with the lowest threshold above the level, and only applies on battery.
+    pub fn battery_rule(
+        &self,
+        power_source: Option<PowerSource>,
+        level: Option<f64>,
+    ) -> Option<&BatteryRule> {
+        let level = level.filter(|_| power_source == Some(PowerSource::Battery))?;
+        self.battery_rule
+            .iter()
+            .filter(|r| level < r.below)
+            .min_by(|a, b| a.below.total_cmp(&b.below))
+    }
+
+    /// Whether the power-saver mapping replaces the active profile's at the given battery
+    /// warning.
+    pub fn forces_power_saver(&self, warning: Option<BatteryWarning>) -> bool {
+        warning.is_some() && self.low_battery.is_some()
+    }
+
+    /// Upper frequency limit forced at the given battery warning, if any.
+    fn low_battery_max_freq(&self, warning: Option<BatteryWarning>) -> Option<&Frequency> {
+        let low_battery = self.low_battery.as_ref()?;
+        match warning? {
+            BatteryWarning::Low => low_battery.max_freq.as_ref(),
+            BatteryWarning::Critical => low_battery
+                .critical_max_freq
+                .as_ref()
+                .or(low_battery.max_freq.as_ref()),
+        }
+    }
+
+    /// All overlays in the config, regardless of where they apply.
+    fn all_overlays(&self) -> impl Iterator<Item = &Overlay> {
+        [&self.on_ac, &self.on_battery, &self.degraded]
+            .into_iter()
+            .chain(self.policy.values().map(|o| &o.knobs))
+            .chain(self.schedule.iter().map(|s| &s.knobs))
+            .chain(self.docked.iter().map(|d| &d.knobs))
+            .chain(self.hold.iter().map(|h| &h.knobs))
+            .chain(self.idle.iter().map(|i| &i.knobs))
+    }
+
+    /// Whether any profile sets a lower frequency limit anywhere in the config.
+    pub fn manages_min_freq(&self) -> bool {
+        is_set(&self.min_freq)
+            || self.all_overlays().any(|o| is_set(&o.min_freq))
+            || self.profile.values().any(|c| c.min_freq.is_some())
+            || self.battery_rule.iter().any(|r| r.min_freq.is_some())
+            || self.thermal_rule.iter().any(|r| r.min_freq.is_some())
+    }
+
+    /// Whether any profile sets an upper frequency limit anywhere in the config.
+    pub fn manages_max_freq(&self) -> bool {
+        is_set(&self.max_freq)
+            || self.all_overlays().any(|o| is_set(&o.max_freq))
+            || self.profile.values().any(|c| c.max_freq.is_some())
+            || self.battery_rule.iter().any(|r| r.max_freq.is_some())
+            || self.thermal_rule.iter().any(|r| r.max_freq.is_some())
+            || self
+                .low_battery
+                .as_ref()
+                .is_some_and(|l| l.max_freq.is_some() || l.critical_max_freq.is_some())
+    }
+
+    /// Overlays that apply in the given scope, in order of precedence. A policy section
+    /// named after the policy comes first, followed by sections whose `cpus` cover all of
+    /// the policy's CPUs in lexical order of their names, then the degraded section while
+    /// performance is degraded, the hold section while a profile is held, the idle section
+    /// while the machine is left idle, current schedules in config order, the docked
+    /// section while docked, and finally the overlay for the current power source.
+    fn overlays_for<'a>(&'a self, scope: &Scope<'a>) -> Vec<&'a Overlay> {
+        let named = self.policy.get(scope.policy).filter(|o| o.cpus.is_none());
+        let ranges = self.policy.values().filter(|o| o.covers(scope.cpus));
+        let degraded = Some(&self.degraded).filter(|_| scope.degraded);
+        let hold = self.hold.as_ref().filter(|_| scope.held);
+        let idle = self.idle.as_ref().filter(|_| scope.idle);
+        let docked = self.docked.as_ref().filter(|_| scope.docked);
+        let source = scope.power_source.map(|s| match s {
+            PowerSource::Ac => &self.on_ac,
+            PowerSource::Battery => &self.on_battery,
+        });
+        named
+            .into_iter()
+            .chain(ranges)
+            .map(|o| &o.knobs)
+            .chain(degraded)
+            .chain(hold.map(|h| &h.knobs))
+            .chain(idle.map(|i| &i.knobs))
+            .chain(scope.schedules.iter().map(|s| &s.knobs))
+            .chain(docked.map(|d| &d.knobs))
+            .chain(source)
+            .collect()
+    }
+
+    /// Look up a knob in the overlays that apply in the given scope.
+    fn lookup_overlay<'a, T>(
+        &'a self,
+        scope: &Scope<'a>,
+        profile: &PPDPowerProfile,
+        knob: impl Fn(&'a Overlay) -> &'a ProfileMap<Option<T>>,
+    ) -> Option<&'a T> {
+        self.overlays_for(scope)
+            .into_iter()
+            .find_map(|o| knob(o).for_profile(profile).as_ref())
+    }
+
+    /// Sections that set knobs directly in the given scope, regardless of the profile
+    /// mapping, in order of precedence: active thermal rules, the frequency limit for a
+    /// battery warning, the battery rule unless a hold suppresses it, the override set
+    /// through the daemon interface and the custom profile.
+    fn forced<'a>(&'a self, scope: &Scope<'a>) -> Vec<Forced<'a>> {
+        let thermal = scope.thermal_rules.iter().map(|r| Forced {
+            epp: r.epp.as_ref(),
+            scaling_governor: r.scaling_governor.as_ref(),
+            min_freq: r.min_freq.as_ref(),
+            max_freq: r.max_freq.as_ref(),
+        });
+        let warning = self
+            .low_battery_max_freq(scope.battery_warning)
+            .map(|max_freq| Forced {
+                epp: None,
+                scaling_governor: None,
+                min_freq: None,
+                max_freq: Some(max_freq),
+            });
+        let suppressed = scope.held && self.hold.as_ref().is_some_and(|h| h.suppress_battery_rules);
+        let battery = self
+            .battery_rule(scope.power_source, scope.battery_level)
+            .filter(|_| !suppressed)
+            .map(|r| Forced {
+                epp: r.epp.as_ref(),
+                scaling_governor: r.scaling_governor.as_ref(),
+                min_freq: r.min_freq.as_ref(),
+                max_freq: r.max_freq.as_ref(),
+            });
+        let user_override = scope.user_override.map(|o| Forced {
+            epp: o.epp.as_ref(),
+            scaling_governor: o.scaling_governor.as_ref(),
+            min_freq: None,
+            max_freq: None,
+        });
+        let custom = scope.custom.map(|c| Forced {
+            epp: c.epp.as_ref(),
+            scaling_governor: c.scaling_governor.as_ref(),
+            min_freq: c.min_freq.as_ref(),
+            max_freq: c.max_freq.as_ref(),
+        });
+        thermal
+            .chain(warning)
+            .chain(battery)
+            .chain(user_override)
+            .chain(custom)
+            .collect()
+    }
+
+    /// EPP for the given scope and profile, taking overrides into account.
+    pub fn epp_for<'a>(
+        &'a self,
+        scope: &Scope<'a>,
+        profile: &PPDPowerProfile,
+    ) -> &'a EnergyPerformancePreference {
+        if !self.manage_epp {
+            return &EnergyPerformancePreference::Keep;
+        }
+        self.forced(scope)
+            .iter()
+            .find_map(|f| f.epp)
+            .or_else(|| self.lookup_overlay(scope, profile, |o| &o.epp))
+            .unwrap_or_else(|| self.epp.for_profile(profile))
+    }
+
+    /// Scaling governor for the given scope and profile, taking overrides into account.
+    pub fn governor_for<'a>(
+        &'a self,
+        scope: &Scope<'a>,
+        profile: &PPDPowerProfile,
+    ) -> &'a ScalingGovernor {
+        if !self.manage_governor {
+            return &ScalingGovernor::Keep;
+        }
+        self.forced(scope)
+            .iter()
+            .find_map(|f| f.scaling_governor)
+            .or_else(|| self.lookup_overlay(scope, profile, |o| &o.scaling_governor))
+            .unwrap_or_else(|| self.scaling_governor.for_profile(profile))
+    }
+
+    /// Lower frequency limit for the given scope and profile, if any.
+    pub fn min_freq_for<'a>(
+        &'a self,
+        scope: &Scope<'a>,
+        profile: &PPDPowerProfile,
+    ) -> Option<Frequency> {
+        self.forced(scope)
+            .iter()
+            .find_map(|f| f.min_freq)
+            .or_else(|| self.lookup_overlay(scope, profile, |o| &o.min_freq))
+            .or(self.min_freq.for_profile(profile).as_ref())
+            .copied()
+    }
+
+    /// Upper frequency limit for the given scope and profile, if any.
+    pub fn max_freq_for<'a>(
+        &'a self,
+        scope: &Scope<'a>,
+        profile: &PPDPowerProfile,
+    ) -> Option<Frequency> {
+        self.forced(scope)
+            .iter()
+            .find_map(|f| f.max_freq)
+            .or_else(|| self.lookup_overlay(scope, profile, |o| &o.max_freq))
+            .or(self.max_freq.for_profile(profile).as_ref())
+            .copied()
+    }
+
+    /// cpuidle governor for the given profile, if any.
+    pub fn cpuidle_governor_for<'a>(
+        &'a self,
+        profile: &PPDPowerProfile,
+        custom: Option<&'a CustomProfile>,
+    ) -> Option<&'a str> {
+        pick(
+            custom.and_then(|c| c.cpuidle_governor.as_ref()),
+            &self.cpuidle_governor,
+            profile,
+        )
+        .map(String::as_str)
+    }
+
+    /// ACPI platform profile for the given profile, if any.
+    pub fn platform_profile_for<'a>(
+        &'a self,
+        profile: &PPDPowerProfile,
+        custom: Option<&'a CustomProfile>,
+    ) -> Option<&'a str> {
+        pick(
+            custom.and_then(|c| c.platform_profile.as_ref()),
+            &self.platform_profile,
+            profile,
+        )
+        .map(String::as_str)
+    }
+
+    /// amd_pstate operation mode for the given profile, if any.
+    pub fn amd_pstate_mode_for(
+        &self,
+        profile: &PPDPowerProfile,
+        custom: Option<&CustomProfile>,
+    ) -> Option<AmdPstateMode> {
+        pick(
+            custom.and_then(|c| c.amd_pstate_mode.as_ref()),
+            &self.amd_pstate_mode,
+            profile,
+        )
+        .copied()
+    }
+
+    /// Whether turbo frequencies are allowed for the given profile, if set.
+    pub fn boost_for(
+        &self,
+        profile: &PPDPowerProfile,
+        custom: Option<&CustomProfile>,
+    ) -> Option<bool> {
+        pick(custom.and_then(|c| c.boost.as_ref()), &self.boost, profile).copied()
+    }
+
+    /// Whether SMT sibling threads are online for the given profile, if set.
+    pub fn smt_for(
+        &self,
+        profile: &PPDPowerProfile,
+        custom: Option<&CustomProfile>,
+    ) -> Option<bool> {
+        pick(custom.and_then(|c| c.smt.as_ref()), &self.smt, profile).copied()
+    }
+
+    /// CPUs taken offline for the given profile, if set.
+    pub fn offline_cpus_for<'a>(
+        &'a self,
+        profile: &PPDPowerProfile,
+        custom: Option<&'a CustomProfile>,
+    ) -> Option<&'a OfflineCpus> {
+        pick(
+            custom.and_then(|c| c.offline_cpus.as_ref()),
+            &self.offline_cpus,
+            profile,
+        )
+    }
+
+    /// Deepest idle state allowed for the given profile, if set.
+    pub fn max_cstate_for<'a>(
+        &'a self,
+        profile: &PPDPowerProfile,
+        custom: Option<&'a CustomProfile>,
+    ) -> Option<&'a str> {
+        pick(
+            custom.and_then(|c| c.max_cstate.as_ref()),
+            &self.max_cstate,
+            profile,
+        )
+        .map(String::as_str)
+    }
+
+    /// x86 energy/performance bias for the given profile, if any.
+    pub fn epb_for(
+        &self,
+        profile: &PPDPowerProfile,
+        custom: Option<&CustomProfile>,
+    ) -> Option<EnergyPerfBias> {
+        pick(custom.and_then(|c| c.epb.as_ref()), &self.epb, profile).copied()
+    }
+
+    /// RAPL package power limits for the given profile, if any.
+    pub fn rapl_for<'a>(
+        &'a self,
+        profile: &PPDPowerProfile,
+        custom: Option<&'a CustomProfile>,
+    ) -> Option<&'a PowerLimits> {
+        pick(custom.and_then(|c| c.rapl.as_ref()), &self.rapl, profile)
+    }
+
+    /// intel_pstate global knobs for the given profile, if any.
+    pub fn intel_pstate_for<'a>(
+        &'a self,
+        profile: &PPDPowerProfile,
+        custom: Option<&'a CustomProfile>,
+    ) -> Option<&'a IntelPstate> {
+        pick(
+            custom.and_then(|c| c.intel_pstate.as_ref()),
+            &self.intel_pstate,
+            profile,
+        )
+    }
+
+    /// amdgpu DPM performance level for the given profile, if any.
+    pub fn gpu_level_for<'a>(
+        &'a self,
+        profile: &PPDPowerProfile,
+        custom: Option<&'a CustomProfile>,
+    ) -> Option<&'a str> {
+        pick(
+            custom.and_then(|c| c.gpu_level.as_ref()),
+            &self.gpu_level,
+            profile,
+        )
+        .map(String::as_str)
+    }
+
+    /// amdgpu power profile mode for the given profile, if any.
+    pub fn gpu_power_profile_for<'a>(
+        &'a self,
+        profile: &PPDPowerProfile,
+        custom: Option<&'a CustomProfile>,
+    ) -> Option<&'a str> {
+        pick(
+            custom.and_then(|c| c.gpu_power_profile.as_ref()),
+            &self.gpu_power_profile,
+            profile,
+        )
+        .map(String::as_str)
+    }
+
+    /// amdgpu power cap in watts for the given profile, if any.
+    pub fn gpu_power_cap_for(
+        &self,
+        profile: &PPDPowerProfile,
+        custom: Option<&CustomProfile>,
+    ) -> Option<Watts> {
+        pick(
+            custom.and_then(|c| c.gpu_power_cap.as_ref()),
+            &self.gpu_power_cap,
+            profile,
+        )
+        .copied()
+    }
+
+    /// Intel integrated GPU frequency caps for the given profile, if any.
+    pub fn i915_for<'a>(
+        &'a self,
+        profile: &PPDPowerProfile,
+        custom: Option<&'a CustomProfile>,
+    ) -> Option<&'a GpuFreqCaps> {
+        pick(custom.and_then(|c| c.i915.as_ref()), &self.i915, profile)
+    }
+
+    /// Sysctls for the given profile, if any. A custom profile's table replaces the one
+    /// of its base profile.
+    pub fn sysctls_for<'a>(
+        &'a self,
+        profile: &PPDPowerProfile,
+        custom: Option<&'a CustomProfile>,
+    ) -> Option<&'a Sysctls> {
+        pick(
+            custom.and_then(|c| c.sysctls.as_ref()),
+            &self.sysctls,
+            profile,
+        )
+    }
+
+    /// NVMe latency tolerance in microseconds for the given profile, if any.
+    pub fn nvme_latency_us_for(
+        &self,
+        profile: &PPDPowerProfile,
+        custom: Option<&CustomProfile>,
+    ) -> Option<LatencyUs> {
+        pick(
+            custom.and_then(|c| c.nvme_latency_us.as_ref()),
+            &self.nvme_latency_us,
+            profile,
+        )
+        .copied()
+    }
+
+    /// Whether any profile sets the ACPI platform profile.
+    pub fn uses_platform_profile(&self) -> bool {
+        is_set(&self.platform_profile)
+            || self.profile.values().any(|c| c.platform_profile.is_some())
+    }
+
+    /// Commands to run before switching to the given profile.
+    pub fn exec_before_for<'a>(
+        &'a self,
+        profile: &PPDPowerProfile,
+        custom: Option<&'a CustomProfile>,
+    ) -> &'a [String] {
+        pick(
+            custom.and_then(|c| c.exec_before.as_ref()),
+            &self.exec_before,
+            profile,
+        )
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+    }
+
+    /// Commands to run after switching to the given profile.
+    pub fn exec_after_for<'a>(
+        &'a self,
+        profile: &PPDPowerProfile,
+        custom: Option<&'a CustomProfile>,
+    ) -> &'a [String] {
+        pick(
+            custom.and_then(|c| c.exec_after.as_ref()),
+            &self.exec_after,
+            profile,
+        )
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+    }
+
+    /// Describe aliases, and the GameMode and screen lock profiles, whose target is
+    /// neither a built-in profile nor a `[profile]` section.
+    pub fn check_aliases(&self) -> Vec<String> {
+        let unknown = |target: &str| {
+            PPDPowerProfile::from_str(target).is_err() && !self.profile.contains_key(target)
+        };
+        let gamemode = self
+            .gamemode
+            .iter()
+            .filter(|g| unknown(self.resolve_alias(&g.profile)))
+            .map(|g| format!("GameMode maps to unknown profile {}.", g.profile));
+        let screen_lock = self
+            .screen_lock
+            .iter()
+            .filter(|l| unknown(self.resolve_alias(&l.profile)))
+            .map(|l| format!("The screen lock maps to unknown profile {}.", l.profile));
+        self.aliases
+            .iter()
+            .filter(|(_, target)| unknown(target))
+            .map(|(alias, target)| format!("Alias {alias} maps to unknown profile {target}."))
+            .chain(gamemode)
+            .chain(screen_lock)
+            .collect()
+    }
+
+    /// Describe mismatches between the config and the profiles offered by
+    /// power-profiles-daemon, e.g. on platforms without a performance profile.
+    pub fn check_offered_profiles(&self, offered: &[String]) -> Vec<String> {
+        let unmapped = offered
+            .iter()
+            .filter(|name| self.unknown_profile.is_none() && self.resolve_profile(name).is_err())
+            .map(|name| {
+                format!("power-profiles-daemon offers {name}, which the config does not map.")
+            });
+        let missing = PPDPowerProfile::ALL
+            .iter()
+            .filter(|p| {
+                !offered.iter().any(|name| {
+                    PPDPowerProfile::from_str(self.resolve_alias(name)).as_ref() == Ok(*p)
+                })
+            })
+            .map(|p| format!("power-profiles-daemon does not offer {p}, so it never reports it."));
+        unmapped.chain(missing).collect()
+    }
+
+    /// Describe override sections that do not apply cleanly to the given policies, given
+    /// as pairs of policy name and CPUs. This catches sections named after a policy that
+    /// does not exist, and CPU ranges that match no policy or only part of one.
+    pub fn check_overrides(&self, policies: &[(String, Vec<u32>)]) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (name, o) in &self.policy {
+            let Some(list) = &o.cpus else {
+                if !policies.iter().any(|(p, _)| p == name) {
+                    problems.push(format!(
+                        "Override for {name} does not match any cpufreq policy."
+                    ));
+                }
+                continue;
+            };
+            let mut matched = false;
+            for (policy, cpus) in policies {
+                if o.covers(cpus) {
+                    matched = true;
+                } else if cpus.iter().any(|c| list.0.contains(c)) {
+                    problems.push(format!(
+                        "Override {name} covers only some CPUs of {policy}, so it does not apply \
+                         to it."
+                    ));
+                }
+            }
+            if !matched {
+                problems.push(format!(
+                    "Override {name} does not cover all CPUs of any cpufreq policy."
+                ));
+            }
+        }
+        problems
+    }
+}
+
+/// The embedded default config.
+pub fn default_config() -> Config {
+    let table = toml::from_str(DEFAULT_CONFIG).expect("embedded default config is valid");
+    parse_table(table).unwrap_or_else(|e| panic!("embedded default config is invalid: {e}"))
+}
+
+/// Where to look for the configuration file.
+pub struct ConfigLocation {
+    /// Explicitly requested config file. Overrides the search path when set.
+    pub file: Option<path::PathBuf>,
+    /// Candidate config files, tried in order until one exists.
+    pub search_path: Vec<path::PathBuf>,
+    /// `key=value` overrides from the command line, where the key is dotted, e.g.
+    /// `epp.balanced`. They are merged over every other layer.
+    pub overrides: Vec<(String, String)>,
+    /// Whether the user config and user drop-ins may be merged. Commands that write to
+    /// sysfs leave them out, so that an unprivileged user cannot influence the writes.
+    pub user_layers: bool,
+}
+
+impl ConfigLocation {
+    /// System config files to try, in order. Every `.toml` file is followed by its YAML and
+    /// JSON alternatives.
+    pub fn candidates(&self) -> Vec<path::PathBuf> {
+        with_alternatives(&self.search_path)
+    }
+
+    /// Resolve the config file to read, either the explicit one or the first existing
+    /// file among the candidates. Returns `None` if no candidate exists.
+    pub fn resolve(&self) -> Option<path::PathBuf> {
+        if let Some(f) = &self.file {
+            log::debug!("Using config file {f:?} given on command line.");
+            return Some(f.clone());
+        }
+        for candidate in self.candidates() {
+            if candidate.exists() {
+                log::debug!("Using config file {candidate:?} found in search path.");
+                return Some(candidate);
+            }
+            log::debug!("Could not find {candidate:?}. Trying next candidate.");
+        }
+        None
+    }
+}
+
+/// The given config files, with every `.toml` file followed by its YAML and JSON
+/// alternatives.
+fn with_alternatives(paths: &[path::PathBuf]) -> Vec<path::PathBuf> {
+    let mut candidates = Vec::new();
+    for p in paths {
+        if p.extension().is_some_and(|e| e == "toml") {
+            candidates.extend(CONFIG_EXTENSIONS.iter().map(|e| p.with_extension(e)));
+        } else {
+            candidates.push(p.clone());
+        }
+    }
+    candidates
+}
+
+/// The user config file, `$XDG_CONFIG_HOME/pstate_update/config.toml` or one of its
+/// alternatives, if it exists.
+fn user_config_file() -> Option<path::PathBuf> {
+    with_alternatives(&[user_config_dir()?.join("config.toml")])
+        .into_iter()
+        .find(|p| p.exists())
+}
+
+/// Directory with drop-in fragments for the given config file, e.g. `config.toml.d`.
+fn drop_in_dir(config_file: &path::Path) -> path::PathBuf {
+    let mut name = config_file.as_os_str().to_owned();
+    name.push(".d");
+    path::PathBuf::from(name)
+}
+
+/// User-level config directory, `$XDG_CONFIG_HOME/pstate_update`.
+fn user_config_dir() -> Option<path::PathBuf> {
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => path::PathBuf::from(dir),
+        _ => path::PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_home.join("pstate_update"))
+}
+
+/// User-level drop-in directory, `$XDG_CONFIG_HOME/pstate_update/config.toml.d`.
+fn user_drop_in_dir() -> Option<path::PathBuf> {
+    Some(user_config_dir()?.join("config.toml.d"))
+}
+
+/// All config files in the given drop-in directory, in lexical order.
+fn drop_in_files(dir: &path::Path) -> io::Result<Vec<path::PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    for entry in dir.read_dir()? {
+        let p = entry?.path();
+        let supported = p
+            .extension()
+            .is_some_and(|e| CONFIG_EXTENSIONS.iter().any(|c| e == *c));
+        if supported && p.is_file() {
+            files.push(p);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Recursively merge `overlay` into `base`. Tables are merged key by key, while any
+/// other value in `overlay` replaces the one in `base`.
+fn merge_values(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(b)), toml::Value::Table(o)) => merge_values(b, o),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Reasons the configuration could not be loaded.
+pub enum ConfigError {
+    /// A config file could not be found or read.
+    Io(io::Error),
+    /// A config file is not valid TOML, YAML or JSON.
+    Syntax { file: path::PathBuf, error: String },
+    /// The merged config does not match the expected schema.
+    Invalid {
+        /// Dotted path of the offending key, e.g. `epp.balanced`.
+        key: String,
+        /// The offending value, if the key is present.
+        value: Option<String>,
+        message: String,
+        /// The accepted value or key closest to the offending one, if any is close.
+        suggestion: Option<String>,
+    },
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "{e}"),
+            ConfigError::Syntax { file, error } => write!(f, "Could not parse {file:?}: {error}"),
+            ConfigError::Invalid {
+                key,
+                value,
+                message,
+                suggestion,
+            } => {
+                match value {
+                    Some(value) => write!(f, "Invalid value {value} for `{key}`: {message}")?,
+                    None => write!(f, "Invalid config at `{key}`: {message}")?,
+                }
+                if let Some(suggestion) = suggestion {
+                    write!(f, " Did you mean '{suggestion}'?")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Names accepted by the knob a key path ends in. Keys are looked up from the end, so
+/// that both `epp.balanced` and `profile.name.epp` resolve to the EPP names.
+fn accepted_names(key: &[String]) -> &'static [&'static str] {
+    for segment in key.iter().rev().take(2) {
+        match segment.as_str() {
+            "epp" => return &EnergyPerformancePreference::NAMES,
+            "scaling_governor" => return &ScalingGovernor::NAMES,
+            "unknown_profile" | "base" => return &PPDPowerProfile::NAMES,
+            _ => {}
+        }
+    }
+    &[]
+}
+
+/// The accepted name closest to the given value, if it is close enough to likely be
+/// a typo.
+fn closest_name<'a>(value: &str, names: &[&'a str]) -> Option<&'a str> {
+    names
+        .iter()
+        .map(|n| (strsim::levenshtein(value, n), *n))
+        .filter(|(d, n)| *d <= (n.len() / 3).max(2))
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, n)| n)
+}
+
+/// Keys of a `[[vendor_knob]]` entry. Its profile values are flattened, which rules out
+/// `deny_unknown_fields`, so the keys are checked against this list instead.
+const VENDOR_KNOB_KEYS: [&str; 4] = ["path", "power_saver", "balanced", "performance"];
+
+/// The error for an unknown key at the given path, suggesting the expected key closest
+/// to it.
+fn unknown_key(key: &[String], expected: &[&str]) -> ConfigError {
+    let name = key.last().map_or("", String::as_str);
+    ConfigError::Invalid {
+        key: key.join("."),
+        value: None,
+        message: "Unknown key.".to_string(),
+        suggestion: closest_name(name, expected).map(String::from),
+    }
+}
+
+/// The keys serde expected instead of an unknown one, from its message for
+/// `deny_unknown_fields`, like ``unknown field `x`, expected `a` or `b` ``.
+fn expected_fields(message: &str) -> Option<Vec<&str>> {
+    let (_, expected) = message.strip_prefix("unknown field `")?.split_once('`')?;
+    Some(expected.split('`').skip(1).step_by(2).collect())
+}
+
+/// Reject unknown keys in the `[[vendor_knob]]` entries.
+fn check_vendor_knob_keys(table: &toml::Table) -> Result<(), ConfigError> {
+    let Some(toml::Value::Array(knobs)) = table.get("vendor_knob") else {
+        return Ok(());
+    };
+    for (i, knob) in knobs.iter().enumerate() {
+        let mut keys = knob.as_table().into_iter().flat_map(|t| t.keys());
+        if let Some(name) = keys.find(|k| !VENDOR_KNOB_KEYS.contains(&k.as_str())) {
+            let key = ["vendor_knob".to_string(), i.to_string(), name.clone()];
+            return Err(unknown_key(&key, &VENDOR_KNOB_KEYS));
+        }
+    }
+    Ok(())
+}
+
+/// Deserialize the merged config table, reporting schema errors with the key path and
+/// offending value. Unknown keys are rejected, with the closest known key as suggestion.
+pub fn parse_table(mut table: toml::Table) -> Result<Config, ConfigError> {
+    // The format version has been dealt with by the migrations.
+    table.remove("version");
+    check_vendor_knob_keys(&table)?;
+    let value = toml::Value::Table(table);
+    let error = match serde_path_to_error::deserialize(value.clone()) {
+        Ok(config) => return Ok(config),
+        Err(e) => e,
+    };
+    let key: Vec<String> = error
+        .path()
+        .iter()
+        .map(|segment| match segment {
+            serde_path_to_error::Segment::Map { key } => key.clone(),
+            serde_path_to_error::Segment::Seq { index } => index.to_string(),
+            _ => "?".to_string(),
+        })
+        .collect();
+    let mut message = error.into_inner().message().trim().to_string();
+    if let Some(expected) = expected_fields(&message) {
+        return Err(unknown_key(&key, &expected));
+    }
+    if !message.ends_with('.') {
+        message.push('.');
+    }
+    let offending = key.iter().try_fold(&value, |v, k| v.get(k.as_str()));
+    let suggestion = offending
+        .and_then(|v| v.as_str())
+        .and_then(|v| closest_name(v, accepted_names(&key)))
+        .map(String::from);
+    Err(ConfigError::Invalid {
+        key: key.join("."),
+        value: offending.filter(|v| !v.is_table()).map(|v| v.to_string()),
+        message,
+        suggestion,
+    })
+}
+
+/// Read and parse a single config file into a table. The format is selected by the file
+/// extension: YAML for `.yaml` and `.yml`, JSON for `.json`, and TOML otherwise. An
+/// `edited` file is parsed from the given contents instead of being read.
+fn read_table(
+    file: &path::Path,
+    edited: Option<(&path::Path, &str)>,
+) -> Result<toml::Table, ConfigError> {
+    let s = match edited {
+        Some((edited, contents)) if edited == file => contents.to_string(),
+        _ => fs::read_to_string(file)
+            .map_err(|e| io::Error::new(e.kind(), format!("Could not read {file:?}: {e}")))?,
+    };
+    let extension = file
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+    let parsed = match extension {
+        "yaml" | "yml" => serde_yaml::from_str(&s).map_err(|e| e.to_string()),
+        "json" => serde_json::from_str(&s).map_err(|e| e.to_string()),
+        _ => toml::from_str(&s).map_err(|e| e.to_string()),
+    };
+    parsed.map_err(|error| ConfigError::Syntax {
+        file: file.to_path_buf(),
+        error,
+    })
+}
+
+/// Read a config file along with the files listed in its `include` key. Included files
+/// are merged first, in the order listed, so that the including file overrides them.
+/// Relative paths are resolved against the directory of the including file. Every file
+/// read is appended to `sources`, and `stack` holds the files currently being read, to
+/// detect include cycles.
+fn read_with_includes(
+    file: &path::Path,
+    provenance: &mut Provenance,
+    stack: &mut Vec<path::PathBuf>,
+    edited: Option<(&path::Path, &str)>,
+) -> Result<toml::Table, ConfigError> {
+    let syntax_error = |error: String| ConfigError::Syntax {
+        file: file.to_path_buf(),
+        error,
+    };
+    // Compare canonical paths, so that different spellings of a file are caught too.
+    let canonical = fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf());
+    if stack.contains(&canonical) {
+        return Err(syntax_error(format!("Include cycle through {stack:?}.")));
+    }
+    let mut table = read_table(file, edited)?;
+    migrate(&mut table, file)?;
+    let includes = match table.remove("include") {
+        None => Vec::new(),
+        Some(toml::Value::Array(list)) => list
+            .iter()
+            .map(|v| v.as_str().map(path::PathBuf::from))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| syntax_error("`include` must be a list of file paths.".into()))?,
+        Some(_) => {
+            return Err(syntax_error(
+                "`include` must be a list of file paths.".into(),
+            ));
+        }
+    };
+    apply_matches(&mut table, file)?;
+    let dir = file.parent().unwrap_or(path::Path::new(""));
+    let mut merged = toml::Table::new();
+    stack.push(canonical);
+    for include in includes {
+        let include = dir.join(include);
+        log::info!("Including config {include:?} from {file:?}.");
+        let included = read_with_includes(&include, provenance, stack, edited)?;
+        merge_values(&mut merged, included);
+    }
+    stack.pop();
+    provenance.record(&table, &file.display().to_string());
+    merge_values(&mut merged, table);
+    provenance.sources.push(file.to_path_buf());
+    Ok(merged)
+}
+
+/// Version of the config format understood by this release. Files without a `version` key
+/// are taken to be version 1.
+pub const CONFIG_VERSION: i64 = 2;
+
+/// Upgrades from each config format version to the next, starting with the upgrade from
+/// version 1, so there is always one fewer than `CONFIG_VERSION`. Each returns a
+/// description of every change it made.
+const MIGRATIONS: &[fn(&mut toml::Table) -> Vec<String>] = &[rename_gpu_level];
+
+/// Version 2 renamed the amdgpu performance level table `gpu` to `gpu_level`, to tell it
+/// apart from `gpu_target`, `gpu_power_profile` and `gpu_power_cap`.
+fn rename_gpu_level(table: &mut toml::Table) -> Vec<String> {
+    let mut changes = Vec::new();
+    rename_key(table, "gpu", "gpu_level", "", &mut changes);
+    changes
+}
+
+/// Rename a key of a config table, and of the `[profile]` and `[match]` sections in it,
+/// describing every rename with the key path prefixed by `prefix`.
+fn rename_key(
+    table: &mut toml::Table,
+    old: &str,
+    new: &str,
+    prefix: &str,
+    changes: &mut Vec<String>,
+) {
+    if let Some(value) = table.remove(old) {
+        if table.contains_key(new) {
+            changes.push(format!(
+                "`{prefix}{old}` is dropped in favor of `{prefix}{new}`."
+            ));
+        } else {
+            changes.push(format!("`{prefix}{old}` is renamed to `{prefix}{new}`."));
+            table.insert(new.to_string(), value);
+        }
+    }
+    for section in ["profile", "match"] {
+        let Some(toml::Value::Table(sections)) = table.get_mut(section) else {
+            continue;
+        };
+        for (name, value) in sections.iter_mut() {
+            if let toml::Value::Table(inner) = value {
+                let prefix = format!("{prefix}{section}.{name}.");
+                rename_key(inner, old, new, &prefix, changes);
+            }
+        }
+    }
+}
+
+/// Upgrade the table of a config file to the current format version, warning about every
+/// change so that the file can be updated. Files written for a newer version are rejected,
+/// since their keys may mean something else.
+fn migrate(table: &mut toml::Table, file: &path::Path) -> Result<(), ConfigError> {
+    let syntax_error = |error: String| ConfigError::Syntax {
+        file: file.to_path_buf(),
+        error,
+    };
+    let version = match table.get("version") {
+        None => 1,
+        Some(toml::Value::Integer(v)) if *v >= 1 => *v,
+        Some(_) => return Err(syntax_error("`version` must be a positive integer.".into())),
+    };
+    if version > CONFIG_VERSION {
+        return Err(syntax_error(format!(
+            "Config format version {version} is newer than the supported version \
+             {CONFIG_VERSION}. Upgrade pstate_update to use this file."
+        )));
+    }
+    let mut changed = false;
+    for (from, migration) in (1..).zip(MIGRATIONS).skip(version as usize - 1) {
+        for change in migration(table) {
+            changed = true;
+            log::warn!(
+                "Upgrading {file:?} from config version {from} to {}: {change}",
+                from + 1
+            );
+        }
+    }
+    if changed {
+        log::warn!(
+            "Apply the changes above to {file:?} and set `version = {CONFIG_VERSION}` to \
+             silence these warnings."
+        );
+    }
+    if table.contains_key("version") {
+        table.insert("version".into(), toml::Value::Integer(CONFIG_VERSION));
+    }
+    Ok(())
+}
+
+/// Merge the `[match.'<model>']` sections of a config file whose name occurs in the CPU
+/// model name (ignoring case) over the rest of the file, in lexical order of their names.
+fn apply_matches(table: &mut toml::Table, file: &path::Path) -> Result<(), ConfigError> {
+    let sections = match table.remove("match") {
+        None => return Ok(()),
+        Some(toml::Value::Table(sections)) => sections,
+        Some(_) => {
+            return Err(ConfigError::Syntax {
+                file: file.to_path_buf(),
+                error: "`match` must be a table of sections named after CPU models.".into(),
+            });
+        }
+    };
+    let model = sysfs::read_cpu_model().unwrap_or_default().to_lowercase();
+    let mut names: Vec<&String> = sections.keys().collect();
+    names.sort();
+    for name in names {
+        let Some(section) = sections[name].as_table() else {
+            return Err(ConfigError::Syntax {
+                file: file.to_path_buf(),
+                error: format!("`match.{name}` must be a table."),
+            });
+        };
+        if model.contains(&name.to_lowercase()) {
+            log::info!("Applying config section for CPU model {name:?} from {file:?}.");
+            merge_values(table, section.clone());
+        } else {
+            log::debug!("Skipping config section for CPU model {name:?} from {file:?}.");
+        }
+    }
+    Ok(())
+}
+
+/// Prefix of environment variables that override config keys, e.g.
+/// `PSTATE_UPDATE__EPP__PERFORMANCE` for `epp.performance`.
+pub const ENV_PREFIX: &str = "PSTATE_UPDATE__";
+
+/// Parse the value of an override variable as a TOML value, falling back to a plain
+/// string, so that both `64` and `performance` work without quoting.
+fn parse_env_value(s: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("value = {s}"))
+        .ok()
+        .and_then(|mut t| t.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(s.to_string()))
+}
+
+/// A table setting only the value at the given key path.
+fn nested_table(keys: &[String], value: toml::Value) -> toml::Table {
+    let mut value = value;
+    for key in keys.iter().rev() {
+        value = toml::Value::Table(toml::Table::from_iter([(key.clone(), value)]));
+    }
+    match value {
+        toml::Value::Table(t) => t,
+        _ => toml::Table::new(),
+    }
+}
+
+/// Collect config overrides from `PSTATE_UPDATE__*` environment variables. Each
+/// `__`-separated part of the name is a lowercased key, so
+/// `PSTATE_UPDATE__POLICY__POLICY4__EPP__BALANCED` sets `policy.policy4.epp.balanced`.
+/// Returns the override of each variable along with its name, in lexical order.
+fn read_env_overrides() -> Vec<(String, toml::Table)> {
+    let mut vars: Vec<(String, String)> = env::vars_os()
+        .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)))
+        .filter(|(k, _)| k.starts_with(ENV_PREFIX))
+        .collect();
+    vars.sort();
+    let mut overrides = Vec::new();
+    for (name, value) in vars {
+        let keys: Vec<String> = name[ENV_PREFIX.len()..]
+            .split("__")
+            .map(|k| k.to_lowercase())
+            .collect();
+        if keys.iter().any(|k| k.is_empty()) {
+            log::warn!("Ignoring malformed config override {name}.");
+            continue;
+        }
+        overrides.push((name, nested_table(&keys, parse_env_value(&value))));
+    }
+    overrides
+}
+
+/// Layers the config is merged from, in order of increasing precedence.
+#[derive(Clone, Copy, PartialEq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Layer {
+    /// The embedded default config, used when there is no system config file.
+    Default,
+    /// The system config file found in the search path, and its drop-ins.
+    System,
+    /// The user config file in `$XDG_CONFIG_HOME`, and the user drop-ins.
+    User,
+    /// `PSTATE_UPDATE__*` environment variables.
+    Env,
+    /// `--override` flags on the command line.
+    Cli,
+}
+
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Layer::Default => "default",
+            Layer::System => "system",
+            Layer::User => "user",
+            Layer::Env => "env",
+            Layer::Cli => "cli",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// Where a config value came from.
+#[derive(Clone, serde::Serialize)]
+pub struct Origin {
+    pub layer: Layer,
+    /// The file, environment variable or flag that set the value.
+    pub source: String,
+}
+
+/// Files merged so far, and where each value came from.
+struct Provenance {
+    /// Layer of the fragments currently being merged.
+    layer: Layer,
+    sources: Vec<path::PathBuf>,
+    /// Origin of each value, keyed by its dotted key path.
+    origins: BTreeMap<String, Origin>,
+}
+
+impl Provenance {
+    /// Record the origin of every value in a fragment that is merged over the config.
+    fn record(&mut self, fragment: &toml::Table, source: &str) {
+        self.record_at("", fragment, source);
+    }
+
+    fn record_at(&mut self, prefix: &str, fragment: &toml::Table, source: &str) {
+        for (key, value) in fragment {
+            let key = format!("{prefix}{key}");
+            let below = format!("{key}.");
+            match value {
+                // Tables are merged into tables, but replace any other value.
+                toml::Value::Table(t) => {
+                    self.origins.remove(&key);
+                    self.record_at(&below, t, source);
+                }
+                // Other values replace everything below their key.
+                _ => {
+                    self.origins.retain(|k, _| !k.starts_with(&below));
+                    let origin = Origin {
+                        layer: self.layer,
+                        source: source.to_string(),
+                    };
+                    self.origins.insert(key, origin);
+                }
+            }
+        }
+    }
+}
+
+/// Merged config table along with the files it was merged from.
+pub struct MergedConfig {
+    pub table: toml::Table,
+    /// Whether the embedded default was used because no config file was found.
+    pub embedded_default: bool,
+    /// Files that were merged, in order of increasing precedence.
+    pub sources: Vec<path::PathBuf>,
+    /// Environment variables that were merged over the files.
+    pub env_overrides: Vec<String>,
+    /// Command-line overrides that were merged last, as `key=value`.
+    pub cli_overrides: Vec<String>,
+    /// Origin of each value, keyed by its dotted key path.
+    pub origins: BTreeMap<String, Origin>,
+}
+
+/// Merge all config layers in order of increasing precedence: the system config file and
+/// its drop-ins, the user config file and the user drop-ins (drop-ins in lexical order),
+/// environment variable overrides, and finally command-line overrides. The embedded
+/// default takes the place of the system config file when there is none.
+pub fn read_merged(location: &ConfigLocation) -> Result<MergedConfig, ConfigError> {
+    merge_layers(location, None)
+}
+
+fn merge_layers(
+    location: &ConfigLocation,
+    edited: Option<(&path::Path, &str)>,
+) -> Result<MergedConfig, ConfigError> {
+    let mut provenance = Provenance {
+        layer: Layer::System,
+        sources: Vec::new(),
+        origins: BTreeMap::new(),
+    };
+    let config_file = location.resolve();
+    // User-level files are never read as root, so that root never depends on a home
+    // directory. An explicit config file replaces the user config file as well.
+    let user_layers = location.user_layers && !nix::unistd::geteuid().is_root();
+    let user_file = (user_layers && location.file.is_none())
+        .then(user_config_file)
+        .flatten();
+    let mut table = match &config_file {
+        Some(f) => {
+            log::info!("Reading config from {f:?}.");
+            read_with_includes(f, &mut provenance, &mut Vec::new(), edited)?
+        }
+        None => {
+            log::info!("No system config file found. Using the embedded default config.");
+            let table = toml::from_str(DEFAULT_CONFIG).expect("embedded default config is valid");
+            provenance.layer = Layer::Default;
+            provenance.record(&table, "embedded default");
+            table
+        }
+    };
+    // Without a config file, drop-ins are still read next to the preferred location.
+    let system_file = config_file.as_ref().or(location.search_path.first());
+    let layers = [
+        (Layer::System, None, system_file.map(|f| drop_in_dir(f))),
+        (
+            Layer::User,
+            user_file,
+            user_drop_in_dir().filter(|_| user_layers),
+        ),
+    ];
+    for (layer, file, dir) in layers {
+        provenance.layer = layer;
+        if let Some(file) = file {
+            log::info!("Merging user config {file:?}.");
+            let fragment = read_with_includes(&file, &mut provenance, &mut Vec::new(), edited)?;
+            merge_values(&mut table, fragment);
+        }
+        for file in dir
+            .map(|d| drop_in_files(&d))
+            .transpose()?
+            .unwrap_or_default()
+        {
+            log::info!("Merging drop-in config {file:?}.");
+            let fragment = read_with_includes(&file, &mut provenance, &mut Vec::new(), edited)?;
+            merge_values(&mut table, fragment);
+        }
+    }
+    provenance.layer = Layer::Env;
+    let mut env_overrides = Vec::new();
+    for (name, fragment) in read_env_overrides() {
+        log::info!("Merging config override from ${name}.");
+        provenance.record(&fragment, &format!("${name}"));
+        merge_values(&mut table, fragment);
+        env_overrides.push(name);
+    }
+    provenance.layer = Layer::Cli;
+    let mut cli_overrides = Vec::new();
+    for (key, value) in &location.overrides {
+        let keys: Vec<String> = key.split('.').map(str::to_string).collect();
+        if keys.iter().any(|k| k.is_empty()) {
+            return Err(ConfigError::Invalid {
+                key: key.clone(),
+                value: Some(value.clone()),
+                message: "Override keys must be dotted key paths like `epp.balanced`.".into(),
+                suggestion: None,
+            });
+        }
+        let fragment = nested_table(&keys, parse_env_value(value));
+        let flag = format!("{key}={value}");
+        provenance.record(&fragment, &format!("--override {flag}"));
+        merge_values(&mut table, fragment);
+        cli_overrides.push(flag);
+    }
+    Ok(MergedConfig {
+        table,
+        embedded_default: config_file.is_none(),
+        sources: provenance.sources,
+        env_overrides,
+        cli_overrides,
+        origins: provenance.origins,
+    })
+}
+
+pub fn read_config(location: &ConfigLocation) -> Result<Config, ConfigError> {
+    let merged = read_merged(location)?;
+    parse_table(merged.table)
+}
+
+/// Check that the config would still be valid with `file` replaced by `contents`, by
+/// merging and parsing every layer as `read_config` does, without writing anything.
+pub fn check_edit(
+    location: &ConfigLocation,
+    file: &path::Path,
+    contents: &str,
+) -> Result<(), ConfigError> {
+    let merged = merge_layers(location, Some((file, contents)))?;
+    parse_table(merged.table).map(drop)
+}
+
+/// Set a dotted key like `epp.balanced` in a TOML config file, keeping the comments and
+/// formatting of the rest of the file. Missing tables are created, and the value is
+/// parsed as TOML, falling back to a plain string. Returns the edited contents without
+/// writing them, so that they can be checked first.
+pub fn edit_key(file: &path::Path, key: &str, value: &str) -> Result<String, String> {
+    let old = fs::read_to_string(file).map_err(|e| format!("Could not read {file:?}: {e}"))?;
+    let mut doc: toml_edit::Document = old
+        .parse()
+        .map_err(|e| format!("Could not parse {file:?}: {e}"))?;
+    let keys: Vec<&str> = key.split('.').collect();
+    let Some((last, parents)) = keys
+        .split_last()
+        .filter(|_| keys.iter().all(|k| !k.is_empty()))
+    else {
+        return Err(format!(
+            "`{key}` is not a dotted key path like `epp.balanced`."
+        ));
+    };
+    let mut table = doc.as_table_mut() as &mut dyn toml_edit::TableLike;
+    for (i, parent) in parents.iter().enumerate() {
+        table = table
+            .entry(parent)
+            .or_insert_with(|| {
+                let mut t = toml_edit::Table::new();
+                t.set_implicit(true);
+                toml_edit::Item::Table(t)
+            })
+            .as_table_like_mut()
+            .ok_or_else(|| format!("`{}` is not a table.", keys[..=i].join(".")))?;
+    }
+    let mut new = value
+        .parse::<toml_edit::Value>()
+        .unwrap_or_else(|_| toml_edit::Value::from(value));
+    match table.get(last).and_then(|item| item.as_value()) {
+        // Keep the spacing and any trailing comment of the value being replaced.
+        Some(previous) => *new.decor_mut() = previous.decor().clone(),
+        None => new.decor_mut().clear(),
+    }
+    table.insert(last, toml_edit::Item::Value(new));
+    Ok(doc.to_string())
+}
+
+/// Replace a file by writing a temporary file next to it and renaming it over the file,
+/// so that a crash or a full disk never leaves a truncated config behind. The file keeps
+/// its permissions.
+pub fn write_atomically(file: &path::Path, contents: &str) -> io::Result<()> {
+    let name = file.file_name().unwrap_or_default().to_string_lossy();
+    let temp = file.with_file_name(format!(".{name}.{}.tmp", std::process::id()));
+    let result = (|| {
+        let mut out = fs::File::create(&temp)?;
+        io::Write::write_all(&mut out, contents.as_bytes())?;
+        if let Ok(metadata) = fs::metadata(file) {
+            out.set_permissions(metadata.permissions())?;
+        }
+        out.sync_all()?;
+        fs::rename(&temp, file)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(s: &str) -> Result<u32, String> {
+        TimeOfDay::try_from(s.to_string()).map(|t| t.0)
+    }
+
+    fn schedule(from: &str, to: &str) -> Schedule {
+        Schedule {
+            from: TimeOfDay(time(from).unwrap()),
+            to: TimeOfDay(time(to).unwrap()),
+            knobs: Overlay::default(),
+        }
+    }
+
+    fn cpu_list(s: &str) -> Result<Vec<u32>, String> {
+        CpuList::try_from(s.to_string()).map(|l| l.0)
+    }
+
+    fn frequency(value: NumberOrString) -> Result<String, String> {
+        Frequency::try_from(value).map(|f| f.to_string())
+    }
+
+    #[test]
+    fn time_of_day_parses_hours_and_minutes() {
+        assert_eq!(time("00:00"), Ok(0));
+        assert_eq!(time("22:30"), Ok(22 * 60 + 30));
+        assert_eq!(time(" 7:05 "), Ok(7 * 60 + 5));
+        assert_eq!(TimeOfDay(7 * 60 + 5).to_string(), "07:05");
+    }
+
+    #[test]
+    fn time_of_day_rejects_invalid_times() {
+        for s in ["24:00", "12:60", "12", "noon", "12:", ":30", "-1:00"] {
+            assert!(time(s).is_err(), "{s} should be rejected");
+        }
+    }
+
+    #[test]
+    fn schedule_covers_daytime_window() {
+        let window = schedule("09:00", "17:00");
+        assert!(!window.covers(TimeOfDay(8 * 60 + 59)));
+        assert!(window.covers(TimeOfDay(9 * 60)));
+        assert!(window.covers(TimeOfDay(16 * 60 + 59)));
+        assert!(!window.covers(TimeOfDay(17 * 60)));
+    }
+
+    #[test]
+    fn schedule_covers_window_wrapping_midnight() {
+        let night = schedule("22:00", "06:00");
+        assert!(night.covers(TimeOfDay(22 * 60)));
+        assert!(night.covers(TimeOfDay(23 * 60 + 59)));
+        assert!(night.covers(TimeOfDay(0)));
+        assert!(night.covers(TimeOfDay(5 * 60 + 59)));
+        assert!(!night.covers(TimeOfDay(6 * 60)));
+        assert!(!night.covers(TimeOfDay(12 * 60)));
+        assert!(!night.covers(TimeOfDay(21 * 60 + 59)));
+    }
+
+    #[test]
+    fn schedule_with_equal_ends_covers_nothing() {
+        let empty = schedule("12:00", "12:00");
+        assert!(!empty.covers(TimeOfDay(12 * 60)));
+        assert!(!empty.covers(TimeOfDay(0)));
+    }
+
+    #[test]
+    fn cpu_list_parses_ranges_and_singles() {
+        assert_eq!(cpu_list("0-3,8,10-11"), Ok(vec![0, 1, 2, 3, 8, 10, 11]));
+        assert_eq!(cpu_list("2 0 1"), Ok(vec![0, 1, 2]));
+        assert_eq!(cpu_list("3,1-3,"), Ok(vec![1, 2, 3]));
+        assert_eq!(cpu_list(""), Ok(vec![]));
+    }
+
+    #[test]
+    fn cpu_list_rejects_malformed_lists() {
+        for s in ["3-1", "a", "1-", "-2", "1-2-3", "0,x", "1.5"] {
+            assert!(cpu_list(s).is_err(), "{s} should be rejected");
+        }
+    }
+
+    #[test]
+    fn cpu_list_displays_as_ranges() {
+        assert_eq!(CpuList(vec![5, 0, 1, 2, 7, 6]).to_string(), "0-2,5-7");
+        assert_eq!(CpuList(vec![4]).to_string(), "4");
+    }
+
+    #[test]
+    fn frequency_parses_numbers_units_and_percentages() {
+        let text = |s: &str| frequency(NumberOrString::Text(s.to_string()));
+        assert_eq!(
+            frequency(NumberOrString::Number(1_800_000)).unwrap(),
+            "1800000 kHz"
+        );
+        assert_eq!(text("1800000kHz").unwrap(), "1800000 kHz");
+        assert_eq!(text("1800 MHz").unwrap(), "1800000 kHz");
+        assert_eq!(text("1.8GHz").unwrap(), "1800000 kHz");
+        assert_eq!(text("2.5ghz").unwrap(), "2500000 kHz");
+        assert_eq!(text("60%").unwrap(), "60%");
+    }
+
+    #[test]
+    fn frequency_rejects_invalid_values() {
+        assert!(frequency(NumberOrString::Number(-1)).is_err());
+        for s in ["101%", "-5%", "3 THz", "fast", "1.2.3GHz", ""] {
+            let result = frequency(NumberOrString::Text(s.to_string()));
+            assert!(result.is_err(), "{s} should be rejected");
+        }
+    }
+
+    #[test]
+    fn frequency_percent_needs_cpuinfo_max() {
+        assert_eq!(
+            Frequency::Percent(50).to_khz(Some(4_000_000)),
+            Some(2_000_000)
+        );
+        assert_eq!(Frequency::Percent(50).to_khz(None), None);
+        assert_eq!(Frequency::KHz(800_000).to_khz(None), Some(800_000));
+    }
+
+    #[test]
+    fn number_or_string_deserializes_both() {
+        let value: toml::Table = toml::from_str("a = 3\nb = \"x\"").unwrap();
+        let parse = |key: &str| -> NumberOrString { value[key].clone().try_into().unwrap() };
+        assert!(matches!(parse("a"), NumberOrString::Number(3)));
+        assert!(matches!(parse("b"), NumberOrString::Text(s) if s == "x"));
+    }
+
+    #[test]
+    fn watts_must_be_positive_and_finite() {
+        assert_eq!(
+            Watts::try_from(15.5).map(Watts::to_microwatts),
+            Ok(15_500_000)
+        );
+        for watts in [0.0, -5.0, f64::NAN, f64::INFINITY] {
+            assert!(
+                Watts::try_from(watts).is_err(),
+                "{watts} should be rejected"
+            );
+        }
+        let limits: PowerLimits = toml::from_str("pl1_watts = 15").unwrap();
+        assert_eq!(limits.pl1_watts.map(Watts::to_microwatts), Some(15_000_000));
+        assert!(toml::from_str::<PowerLimits>("pl1_watts = -15").is_err());
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        let table: toml::Table = toml::from_str(DEFAULT_CONFIG).unwrap();
+        let config = parse_table(table).unwrap_or_else(|e| panic!("{e}"));
+        assert!(config.check_aliases().is_empty());
+        assert!(config.check_overrides(&[]).is_empty());
+    }
+
+    #[test]
+    fn overlay_errors_keep_key_path() {
+        let mut table: toml::Table = toml::from_str(DEFAULT_CONFIG).unwrap();
+        let schedule: toml::Table =
+            toml::from_str("from = \"22:00\"\nto = \"06:00\"\nmax_freq.balanced = \"3 THz\"")
+                .unwrap();
+        table.insert("schedule".into(), vec![toml::Value::Table(schedule)].into());
+        let error = parse_table(table)
+            .err()
+            .expect("3 THz is not a frequency")
+            .to_string();
+        assert!(error.contains("schedule.0.max_freq.balanced"), "{error}");
+    }
+
+    #[test]
+    fn gpu_target_accepts_kinds_cards_and_slots() {
+        let target = |s: &str| GpuTarget::try_from(s.to_string()).map(|t| t.to_string());
+        for s in ["all", "integrated", "discrete", "card1", "0000:03:00.0"] {
+            assert_eq!(target(s).as_deref(), Ok(s));
+        }
+        for s in ["card", "cardx", "igpu", "0000:03:00", "0000-03-00.0"] {
+            assert!(target(s).is_err(), "{s} should be rejected");
+        }
+    }
+
+    #[test]
+    fn nvme_latency_is_bounded_to_i32() {
+        assert!(LatencyUs::try_from(i32::MAX as u64).is_ok());
+        assert!(LatencyUs::try_from(i32::MAX as u64 + 1).is_err());
+        assert!(LatencyUs::try_from(u64::MAX).is_err());
+    }
+
+    #[test]
+    fn migrate_upgrades_version_1() {
+        let mut table: toml::Table = toml::from_str(
+            "version = 1\n\
+             [gpu]\nbalanced = \"auto\"\n\
+             [profile.quiet]\nbase = \"power-saver\"\ngpu = \"low\"\n\
+             [match.ryzen.gpu]\nperformance = \"high\"\n",
+        )
+        .unwrap();
+        migrate(&mut table, path::Path::new("config.toml")).unwrap_or_else(|e| panic!("{e}"));
+        let expected: toml::Table = toml::from_str(
+            "version = 2\n\
+             [gpu_level]\nbalanced = \"auto\"\n\
+             [profile.quiet]\nbase = \"power-saver\"\ngpu_level = \"low\"\n\
+             [match.ryzen.gpu_level]\nperformance = \"high\"\n",
+        )
+        .unwrap();
+        assert_eq!(table, expected);
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected_with_suggestions() {
+        let parse = |toml: &str| {
+            let table: toml::Table = toml::from_str(toml).unwrap();
+            parse_table(table).err().expect("unknown key").to_string()
+        };
+        let config = DEFAULT_CONFIG.replace("balanced = \"balance_power\"", "ballanced = \"x\"");
+        let error = parse(&config);
+        assert!(error.contains("`epp.ballanced`"), "{error}");
+        assert!(error.contains("Did you mean 'balanced'?"), "{error}");
+        let config = DEFAULT_CONFIG.replace("[scaling_governor]", "[scaling_govenor]");
+        let error = parse(&config);
+        assert!(error.contains("`scaling_govenor`"), "{error}");
+        assert!(
+            error.contains("Did you mean 'scaling_governor'?"),
+            "{error}"
+        );
+        let error = parse(&format!("min_freqq = 5\n{DEFAULT_CONFIG}"));
+        assert!(error.contains("Did you mean 'min_freq'?"), "{error}");
+        let knob = "[[vendor_knob]]\npath = \"/sys/x\"\nperfromance = \"1\"\n";
+        let error = parse(&format!("{DEFAULT_CONFIG}\n{knob}"));
+        assert!(error.contains("`vendor_knob.0.perfromance`"), "{error}");
+        assert!(error.contains("Did you mean 'performance'?"), "{error}");
+    }
+
+    #[test]
+    fn closest_name_suggests_likely_typos() {
+        let names = &EnergyPerformancePreference::NAMES;
+        assert_eq!(closest_name("powr", names), Some("power"));
+        assert_eq!(
+            closest_name("balance_performace", names),
+            Some("balance_performance")
+        );
+        assert_eq!(closest_name("something else", names), None);
+    }
+
+    #[test]
+    fn offline_cpus_resolve_counts_from_the_highest() {
+        let hotplug = [1, 2, 3, 5];
+        let count = |n| OfflineCpus::try_from(NumberOrString::Number(n)).unwrap();
+        assert_eq!(count(2).resolve(&hotplug), vec![5, 3]);
+        assert_eq!(count(0).resolve(&hotplug), Vec::<u32>::new());
+        assert_eq!(count(10).resolve(&hotplug), vec![5, 3, 2, 1]);
+        let list = OfflineCpus::try_from(NumberOrString::Text("4-6".to_string())).unwrap();
+        assert_eq!(list.resolve(&hotplug), vec![4, 5, 6]);
+        assert!(OfflineCpus::try_from(NumberOrString::Number(-1)).is_err());
+    }
+
+    #[test]
+    fn energy_perf_bias_accepts_numbers_and_kernel_names() {
+        let epb = |value| EnergyPerfBias::try_from(value).map(|epb| epb.0);
+        let text = |s: &str| epb(NumberOrString::Text(s.to_string()));
+        assert_eq!(epb(NumberOrString::Number(0)), Ok(0));
+        assert_eq!(epb(NumberOrString::Number(15)), Ok(15));
+        assert!(epb(NumberOrString::Number(16)).is_err());
+        assert!(epb(NumberOrString::Number(-1)).is_err());
+        assert_eq!(text("balance-power"), Ok(8));
+        assert_eq!(text("normal"), Ok(6));
+        assert!(text("balance_power").is_err());
+    }
+
+    #[test]
+    fn sysfs_file_must_stay_in_sys() {
+        let file = |s: &str| SysfsFile::try_from(s.to_string()).map(|f| f.0);
+        assert_eq!(
+            file("/sys/devices/platform/asus-nb-wmi/throttle_thermal_policy"),
+            Ok(path::PathBuf::from(
+                "/sys/devices/platform/asus-nb-wmi/throttle_thermal_policy"
+            ))
+        );
+        for s in [
+            "/sys",
+            "/proc/sys/vm/swappiness",
+            "sys/power/state",
+            "/sys/../etc/passwd",
+        ] {
+            assert!(file(s).is_err(), "{s} should be rejected");
+        }
+    }
+}
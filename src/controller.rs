@@ -0,0 +1,2678 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::os::unix::net::UnixStream;
+use std::path;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use chrono::Timelike;
+use futures_util::future::{self, Either};
+use futures_util::io::{AsyncBufReadExt, AsyncReadExt};
+use futures_util::StreamExt;
+
+use crate::config::{
+    self, AmdPstateMode, Config, ConfigLocation, CpuList, CustomProfile, EnergyPerfBias, Frequency,
+    GpuFreqCaps, GpuTarget, IntelPstate, LatencyUs, OfflineCpus, PowerLimits, RetryConfig,
+    Schedule, Scope, Sysctls, ThermalRule, TimeOfDay, UserOverride, Watts,
+};
+use crate::drm;
+use crate::executor;
+use crate::hooks;
+use crate::hwmon;
+use crate::logind::{self, LoginManagerProxy};
+#[cfg(feature = "mqtt")]
+use crate::mqtt;
+use crate::notify::{self, ErrorNotifier};
+use crate::power::{self, BatteryWarning, PowerSource};
+use crate::powercap::{self, PL1_CONSTRAINT, PL2_CONSTRAINT};
+use crate::provider;
+use crate::rpc;
+use crate::service::{self, Events, Inhibitor};
+use crate::storage;
+use crate::sysfs;
+use crate::systemd;
+use crate::upower::{self, DisplayDeviceProxy, UPowerProxy};
+use crate::{
+    ppd_proxy, EnergyPerformancePreference, PPDPowerProfile, PowerProfilesDaemonManagerProxy,
+    ScalingGovernor, PPD_SERVICES,
+};
+
+// Names of the knobs in planned writes, D-Bus signals and validation problems, after the
+// config keys that set them.
+pub const EPP_KNOB: &str = "epp";
+pub const GOVERNOR_KNOB: &str = "scaling_governor";
+pub const MIN_FREQ_KNOB: &str = "min_freq";
+pub const MAX_FREQ_KNOB: &str = "max_freq";
+pub const BOOST_KNOB: &str = "boost";
+pub const EPB_KNOB: &str = "epb";
+pub const PLATFORM_PROFILE_KNOB: &str = "platform_profile";
+pub const CPUIDLE_GOVERNOR_KNOB: &str = "cpuidle_governor";
+pub const MAX_CSTATE_KNOB: &str = "max_cstate";
+pub const AMD_PSTATE_MODE_KNOB: &str = "amd_pstate_mode";
+pub const SMT_KNOB: &str = "smt";
+pub const OFFLINE_CPUS_KNOB: &str = "offline_cpus";
+pub const GPU_LEVEL_KNOB: &str = "gpu_level";
+pub const GPU_POWER_PROFILE_KNOB: &str = "gpu_power_profile";
+pub const GPU_POWER_CAP_KNOB: &str = "gpu_power_cap";
+pub const I915_KNOB: &str = "i915";
+pub const SYSCTLS_KNOB: &str = "sysctls";
+pub const NVME_LATENCY_KNOB: &str = "nvme_latency_us";
+pub const VENDOR_KNOB: &str = "vendor_knob";
+
+/// A single sysfs write the controller intends to make.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct PlannedWrite {
+    pub knob: String,
+    pub path: path::PathBuf,
+    pub value: String,
+}
+
+impl PlannedWrite {
+    fn new(knob: &str, path: &path::Path, value: &str) -> Self {
+        PlannedWrite {
+            knob: knob.to_string(),
+            path: path.to_path_buf(),
+            value: value.to_string(),
+        }
+    }
+
+    /// Write the value to the sysfs file, or only log it when doing a dry run. Failed
+    /// writes are retried as configured, since drivers may reject writes while busy.
+    fn apply(&self, dry_run: bool, retry: &RetryConfig) -> io::Result<()> {
+        let (knob, value, file) = (&self.knob, &self.value, &self.path);
+        if dry_run {
+            log::info!("Dry run: would write {knob} '{value}' to file {file:?}.");
+            return Ok(());
+        }
+        log::debug!("Writing {knob} '{value}' to file {file:?}.");
+        let mut attempt = 1;
+        loop {
+            match fs::write(file, value) {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt < retry.write_attempts => {
+                    let delay = retry.write_backoff(attempt);
+                    log::debug!(
+                        "Failed to write {knob} to {file:?}: {e}. Retrying in {} ms.",
+                        delay.as_millis()
+                    );
+                    thread::sleep(delay);
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Perform all writes in the plan, in order, and report each result to `events` if
+/// given. Failures are logged, not returned. Returns the writes that succeeded.
+pub fn apply_plan<'a>(
+    plan: &'a [PlannedWrite],
+    dry_run: bool,
+    retry: &RetryConfig,
+    events: Option<&Events>,
+) -> Vec<&'a PlannedWrite> {
+    let mut written = Vec::new();
+    for w in plan {
+        let path = w.path.to_string_lossy();
+        match w.apply(dry_run, retry) {
+            Ok(()) => {
+                if let Some(events) = events {
+                    events.value_written(&w.knob, &path, &w.value);
+                }
+                written.push(w);
+            }
+            Err(e) => {
+                log::error!("Failed to write {} to core ({:?}): {e}.", w.knob, w.path);
+                if let Some(events) = events {
+                    events.write_failed(&w.knob, &path, &w.value, &e.to_string());
+                }
+            }
+        }
+    }
+    written
+}
+
+/// Distinct values written for a knob, comma-separated in the order of the policies.
+fn distinct_values(written: &[&PlannedWrite], knob: &str) -> String {
+    let mut values: Vec<&str> = Vec::new();
+    for w in written.iter().filter(|w| w.knob == knob) {
+        if !values.contains(&w.value.as_str()) {
+            values.push(&w.value);
+        }
+    }
+    values.join(",")
+}
+
+/// A list of values, or `unchanged` if nothing was written.
+fn or_unchanged(values: &str) -> &str {
+    if values.is_empty() {
+        "unchanged"
+    } else {
+        values
+    }
+}
+
+/// EPPs and governors written when applying a profile, as by `distinct_values`.
+pub struct AppliedValues {
+    pub epp: String,
+    pub governor: String,
+}
+
+/// Name and CPUs of the cpufreq policy a sysfs file belongs to, e.g. `policy4`.
+fn policy_of(file: &path::Path) -> (String, Vec<u32>) {
+    match file.parent() {
+        Some(dir) => (sysfs::policy_name(dir), sysfs::read_policy_cpus(dir)),
+        None => Default::default(),
+    }
+}
+
+/// `EPPController` controls the CPU EPP levels
+pub struct EPPController {
+    /// Found again whenever switching the amd_pstate mode replaces the policy files.
+    pub epp_core_files: Vec<path::PathBuf>,
+    pub governor_core_files: Vec<path::PathBuf>,
+    /// CPUs taken offline through `offline_cpus`, which are told apart from sibling
+    /// threads disabled by turning SMT off.
+    pub offlined_cpus: Vec<u32>,
+    pub config: Arc<Config>,
+    /// Only log the writes that would be made, without touching sysfs.
+    pub dry_run: bool,
+    /// Profile name most recently received from power-profiles-daemon.
+    pub active_profile: Option<String>,
+    /// `[profile]` section selected through the daemon interface, which is layered on top
+    /// of the active profile.
+    pub selected_profile: Option<String>,
+    /// EPP and governor forced through the daemon interface, on top of every profile.
+    pub user_override: Option<UserOverride>,
+    /// Shows failures on the desktop.
+    pub notifier: ErrorNotifier,
+    /// Reports applied profiles to the MQTT broker, if one is configured.
+    #[cfg(feature = "mqtt")]
+    pub mqtt: Option<mqtt::Publisher>,
+    /// Current power source, if known.
+    pub power_source: Option<PowerSource>,
+    /// Current battery level in percent, if known.
+    pub battery_level: Option<f64>,
+    /// Current battery warning from UPower, if any.
+    pub battery_warning: Option<BatteryWarning>,
+    /// Whether each thermal rule in the config currently applies.
+    pub thermal_active: Vec<bool>,
+    /// Whether each schedule in the config currently applies.
+    pub schedule_active: Vec<bool>,
+    /// Whether the laptop is currently docked.
+    pub docked: bool,
+    /// Whether an application currently holds a profile through power-profiles-daemon.
+    pub held: bool,
+    /// Whether power-profiles-daemon currently reports the performance profile as
+    /// degraded.
+    pub degraded: bool,
+    /// Whether a game currently has GameMode requested.
+    pub gaming: bool,
+    /// Whether the screens of all desktop sessions are currently locked.
+    pub locked: bool,
+    /// Whether every session has been idle for long enough for the idle section.
+    pub idle: bool,
+    /// Locks taken by applications through the daemon interface, the most recent last.
+    pub inhibitors: Vec<Inhibitor>,
+    /// How long the daemon keeps running without profile changes or control requests.
+    /// Runs until stopped if `None`.
+    pub idle_timeout: Option<Duration>,
+    /// power-profiles-daemon drives the ACPI platform profile itself, so it is left alone.
+    pub ppd_owns_platform_profile: bool,
+    /// Pollers started for optional config sections.
+    pub pollers: Pollers,
+}
+
+/// Which pollers and listeners for optional config sections are running. They keep
+/// running once started, so that a reload removing their section only leaves their events
+/// unused.
+#[derive(Default)]
+pub struct Pollers {
+    thermal: bool,
+    dock: bool,
+    schedule: bool,
+    battery_warning: bool,
+}
+
+/// Whether power-profiles-daemon drives the ACPI platform profile itself. PPD names the
+/// driver of each profile in its `Profiles` property, as `PlatformDriver` since v0.20 and
+/// as `Driver` before that.
+pub fn ppd_owns_platform_profile() -> bool {
+    let profiles = zbus::blocking::Connection::system().and_then(|conn| {
+        let proxy =
+            PowerProfilesDaemonManagerProxy::from(ppd_proxy(&conn)?.into_inner().into_inner());
+        executor::block_on_call(proxy.profiles())
+    });
+    let profiles = match profiles {
+        Ok(p) => p,
+        Err(e) => {
+            log::warn!("Could not read the drivers used by power-profiles-daemon: {e}");
+            return false;
+        }
+    };
+    let owned = profiles.iter().any(|p| {
+        ["PlatformDriver", "Driver"]
+            .iter()
+            .filter_map(|key| p.get(*key))
+            .any(|v| <&str>::try_from(&**v) == Ok("platform_profile"))
+    });
+    if owned {
+        log::warn!(
+            "power-profiles-daemon already drives platform_profile. Leaving it alone to \
+             avoid fighting over it."
+        );
+    }
+    owned
+}
+
+/// Names of the profiles in the `Profiles` property of power-profiles-daemon, i.e. the
+/// ones it offers on this platform.
+pub fn offered_profiles(profiles: &[HashMap<String, zbus::zvariant::OwnedValue>]) -> Vec<String> {
+    profiles
+        .iter()
+        .filter_map(|p| p.get("Profile"))
+        .filter_map(|v| <&str>::try_from(&**v).ok())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Input to the daemon event loop.
+pub enum Event {
+    /// power-profiles-daemon reported a new ActiveProfile.
+    ActiveProfileChanged(String),
+    /// The config should be re-read and re-applied.
+    Reload,
+    /// The system switched between AC and battery power.
+    PowerSourceChanged(PowerSource),
+    /// UPower reported a new battery level in percent.
+    BatteryLevelChanged(f64),
+    /// UPower reported a new battery warning level.
+    BatteryWarningChanged(Option<BatteryWarning>),
+    /// The first game requested GameMode, or the last one exited.
+    GamingChanged(bool),
+    /// The screens of all desktop sessions were locked, or one was unlocked.
+    ScreenLockChanged(bool),
+    /// The temperature sensors should be checked against the thermal rules.
+    ThermalTick,
+    /// The displays and dock should be checked for a change of docked state.
+    DockTick,
+    /// power-profiles-daemon reported new ActiveProfileHolds, as the IDs of the
+    /// applications holding a profile.
+    HoldsChanged(Vec<String>),
+    /// power-profiles-daemon reported a new PerformanceDegraded reason, empty if
+    /// performance is no longer degraded.
+    DegradedChanged(String),
+    /// The system resumed from suspend, so firmware may have reset the written values.
+    Resumed,
+    /// power-profiles-daemon appeared on the bus while the fallback profile was applied.
+    PpdAppeared,
+    /// The clock should be checked against the schedules.
+    ScheduleTick,
+    /// logind reported how long every session has been idle, or `None` if one is in use.
+    SessionsIdle(Option<Duration>),
+    /// The systemd watchdog should be pinged.
+    WatchdogTick,
+    /// The time since the last profile change or control request should be checked
+    /// against the idle timeout.
+    IdleTick,
+    /// A profile from the config was selected through the daemon interface, or the
+    /// selection was cleared.
+    ProfileSelected(Option<String>),
+    /// An override was set through the daemon interface, or cleared.
+    OverrideChanged(Option<UserOverride>),
+    /// Applying a built-in profile once was requested through the daemon interface.
+    ApplyRequested(PPDPowerProfile),
+    /// An application took or released a lock through the daemon interface.
+    InhibitorsChanged(Vec<Inhibitor>),
+    /// A client with the given unique name left the bus.
+    ClientLeft(String),
+    /// The control socket asks for the daemon's status.
+    StatusRequested(mpsc::Sender<rpc::Status>),
+    /// Listening for ActiveProfile changes failed.
+    ListenerFailed(zbus::Error),
+    /// The ActiveProfile change stream ended.
+    ListenerStopped,
+    /// The connection to the system bus was lost.
+    Disconnected(zbus::Error),
+}
+
+/// Writes of the given sysctls. Unknown sysctls are skipped with a warning.
+fn plan_sysctls(sysctls: &Sysctls) -> Vec<PlannedWrite> {
+    let root = path::Path::new(sysfs::SYSCTL_PATH);
+    let mut plan = Vec::new();
+    for (name, value) in sysctls {
+        match sysfs::sysctl_file(root, name).filter(|f| f.exists()) {
+            Some(file) => plan.push(PlannedWrite::new(SYSCTLS_KNOB, &file, &value.0)),
+            None => log::warn!("Unknown sysctl {name}, skipping it."),
+        }
+    }
+    plan
+}
+
+/// Writes of the latency tolerance to every NVMe controller.
+fn plan_nvme_latency(latency_us: LatencyUs) -> Vec<PlannedWrite> {
+    let controllers = storage::find_nvme_controllers(path::Path::new(storage::NVME_PATH));
+    if controllers.is_empty() {
+        log::warn!("An NVMe latency tolerance is configured, but no NVMe drive was found.");
+    }
+    controllers
+        .iter()
+        .map(|c| {
+            let file = c.join(storage::LATENCY_TOLERANCE_FILE);
+            PlannedWrite::new(NVME_LATENCY_KNOB, &file, &latency_us.to_string())
+        })
+        .collect()
+}
+
+/// Writes of the DPM performance level to every targeted amdgpu card.
+fn plan_gpu_level(level: &str, target: &GpuTarget) -> Vec<PlannedWrite> {
+    let devices = target.devices(path::Path::new(drm::DRM_PATH));
+    if devices.is_empty() && *target == GpuTarget::All {
+        log::warn!("A GPU performance level is configured, but no amdgpu card was found.");
+    } else if devices.is_empty() {
+        log::warn!("A GPU performance level is configured, but no {target} amdgpu card was found.");
+    }
+    devices
+        .iter()
+        .map(|d| PlannedWrite::new(GPU_LEVEL_KNOB, &d.join(drm::DPM_LEVEL_FILE), level))
+        .collect()
+}
+
+/// Writes of the power cap to the hwmon of every targeted amdgpu card, clamped to the
+/// range each card accepts.
+fn plan_gpu_power_cap(watts: Watts, target: &GpuTarget) -> Vec<PlannedWrite> {
+    let hwmons: Vec<_> = target
+        .devices(path::Path::new(drm::DRM_PATH))
+        .iter()
+        .filter_map(|d| drm::find_power_cap_hwmon(d))
+        .collect();
+    if hwmons.is_empty() {
+        log::warn!("A GPU power cap is configured, but no amdgpu card with a power cap was found.");
+    }
+    let uw = watts.to_microwatts();
+    hwmons
+        .iter()
+        .map(|hwmon| {
+            let (min, max) = drm::read_power_cap_range(hwmon);
+            let clamped = uw.clamp(min, max.max(min));
+            if clamped != uw {
+                log::info!(
+                    "Clamping the GPU power cap of {watts} to {} W for {hwmon:?}.",
+                    clamped as f64 / 1e6
+                );
+            }
+            let file = hwmon.join(drm::POWER_CAP_FILE);
+            PlannedWrite::new(GPU_POWER_CAP_KNOB, &file, &clamped.to_string())
+        })
+        .collect()
+}
+
+/// Writes selecting the named power profile mode on every targeted amdgpu card, by the
+/// index the card lists for it. Cards without such a mode are skipped with a warning.
+fn plan_gpu_power_profile(name: &str, target: &GpuTarget) -> Vec<PlannedWrite> {
+    let devices = target.devices(path::Path::new(drm::DRM_PATH));
+    if devices.is_empty() {
+        log::warn!("A GPU power profile is configured, but no amdgpu card was found.");
+    }
+    devices
+        .iter()
+        .filter_map(|d| {
+            let modes = drm::read_power_profile_modes(d);
+            let Some((index, _)) = modes.iter().find(|(_, mode)| mode == name) else {
+                log::warn!("amdgpu card {d:?} has no power profile mode {name}, skipping it.");
+                return None;
+            };
+            let file = d.join(drm::POWER_PROFILE_MODE_FILE);
+            Some(PlannedWrite::new(
+                GPU_POWER_PROFILE_KNOB,
+                &file,
+                &index.to_string(),
+            ))
+        })
+        .collect()
+}
+
+/// Frequency cap writes for every i915 card. The caps are written in an order the driver
+/// accepts from the current ones: the upper cap first unless it drops below the current
+/// lower cap, and the boost frequency last.
+fn plan_i915_caps(caps: &GpuFreqCaps) -> Vec<PlannedWrite> {
+    let cards = drm::find_i915_cards(path::Path::new(drm::DRM_PATH));
+    if cards.is_empty() {
+        log::warn!("i915 frequency caps are configured, but no i915 card was found.");
+    }
+    let mut plan = Vec::new();
+    for card in &cards {
+        let current_min: Option<u32> = sysfs::read_value(&card.join(drm::I915_MIN_FREQ_FILE))
+            .ok()
+            .and_then(|v| v.parse().ok());
+        let min = (drm::I915_MIN_FREQ_FILE, caps.min_mhz);
+        let max = (drm::I915_MAX_FREQ_FILE, caps.max_mhz);
+        let order = match (caps.max_mhz, current_min) {
+            (Some(new_max), Some(current_min)) if new_max < current_min => [min, max],
+            _ => [max, min],
+        };
+        let boost = (drm::I915_BOOST_FREQ_FILE, caps.boost_mhz);
+        for (file, mhz) in order.into_iter().chain([boost]) {
+            if let Some(mhz) = mhz {
+                plan.push(PlannedWrite::new(
+                    I915_KNOB,
+                    &card.join(file),
+                    &mhz.to_string(),
+                ));
+            }
+        }
+    }
+    plan
+}
+
+/// Power limit writes for every RAPL package zone. The time windows are written before
+/// the limits they belong to.
+fn plan_power_limits(limits: &PowerLimits) -> Vec<PlannedWrite> {
+    let to_uw = |watts: Watts| watts.to_microwatts().to_string();
+    let to_us = |ms: u64| {
+        let us = ms.checked_mul(1000).map(|us| us.to_string());
+        if us.is_none() {
+            log::warn!("RAPL time window of {ms} ms is too long, skipping it.");
+        }
+        us
+    };
+    let values = [
+        (
+            "pl1_window",
+            PL1_CONSTRAINT,
+            "time_window_us",
+            limits.pl1_window_ms.and_then(to_us),
+        ),
+        (
+            "pl1",
+            PL1_CONSTRAINT,
+            "power_limit_uw",
+            limits.pl1_watts.map(to_uw),
+        ),
+        (
+            "pl2_window",
+            PL2_CONSTRAINT,
+            "time_window_us",
+            limits.pl2_window_ms.and_then(to_us),
+        ),
+        (
+            "pl2",
+            PL2_CONSTRAINT,
+            "power_limit_uw",
+            limits.pl2_watts.map(to_uw),
+        ),
+    ];
+    let zones = powercap::find_package_zones(path::Path::new(powercap::POWERCAP_PATH));
+    if zones.is_empty() {
+        log::warn!("Could not find any RAPL package zones, skipping power limits.");
+    }
+    let mut plan = Vec::new();
+    for zone in &zones {
+        for (knob, constraint, attr, value) in &values {
+            let Some(value) = value else {
+                continue;
+            };
+            match powercap::constraint_file(zone, constraint, attr) {
+                Some(file) => plan.push(PlannedWrite::new(knob, &file, value)),
+                None => log::warn!("{zone:?} has no {constraint} constraint, skipping {knob}."),
+            }
+        }
+    }
+    plan
+}
+
+/// Knobs whose writes replace policy files or take CPUs offline, which `apply` makes
+/// before planning the rest of the profile.
+const TOPOLOGY_KNOBS: [&str; 3] = [AMD_PSTATE_MODE_KNOB, SMT_KNOB, OFFLINE_CPUS_KNOB];
+
+/// How many times, and how far apart, to look for the policy files after a topology
+/// change.
+const TOPOLOGY_SETTLE_ATTEMPTS: u32 = 10;
+const TOPOLOGY_SETTLE_DELAY: Duration = Duration::from_millis(100);
+
+/// Whether an offline CPU is a sibling thread disabled by turning SMT off, rather than
+/// one taken offline through `offline_cpus`. A sibling is not the first thread of its
+/// core in `topology/thread_siblings_list`. Offline CPUs usually lose their topology,
+/// so without it a CPU counts as a sibling unless `offlined` says it was taken offline
+/// through `offline_cpus`.
+fn is_smt_sibling(cpu: u32, online_file: &path::Path, offlined: &[u32]) -> bool {
+    let siblings_file = online_file.with_file_name("topology/thread_siblings_list");
+    match sysfs::read_value(&siblings_file).map(CpuList::try_from) {
+        Ok(Ok(siblings)) => siblings.0.first().is_some_and(|first| *first != cpu),
+        _ => !offlined.contains(&cpu),
+    }
+}
+
+/// Writes that take the given CPUs offline and bring every other one back online, for the
+/// CPUs whose state differs. CPUs come online before others go offline, so that fewer are
+/// never online than the profile wants. With SMT off, sibling threads disabled by it stay
+/// offline and do not count towards a number of CPUs to take offline. `offlined` lists
+/// the CPUs taken offline through `offline_cpus` before.
+fn plan_cpu_online(
+    offline: &OfflineCpus,
+    exclude_cpus: &[u32],
+    offlined: &[u32],
+) -> Vec<PlannedWrite> {
+    let smt_off = sysfs::read_value(path::Path::new(sysfs::SMT_CONTROL_PATH))
+        .is_ok_and(|state| state == "off" || state == "forceoff");
+    let cpus: Vec<_> = sysfs::find_hotplug_cpus(path::Path::new(sysfs::CPU_PATH))
+        .into_iter()
+        .filter(|(cpu, _)| !exclude_cpus.contains(cpu))
+        .map(|(cpu, file)| {
+            let online = sysfs::read_value(&file).is_ok_and(|v| v == "1");
+            let sibling = smt_off && !online && is_smt_sibling(cpu, &file, offlined);
+            (cpu, file, online, sibling)
+        })
+        .collect();
+    let ids: Vec<u32> = cpus
+        .iter()
+        .filter(|(_, _, _, sibling)| !sibling)
+        .map(|(cpu, _, _, _)| *cpu)
+        .collect();
+    let targets = offline.resolve(&ids);
+    for cpu in targets.iter().filter(|cpu| !ids.contains(cpu)) {
+        log::warn!("CPU {cpu} cannot be taken offline or is excluded, skipping it.");
+    }
+    let (mut up, mut down) = (Vec::new(), Vec::new());
+    for (cpu, file, online, sibling) in &cpus {
+        match (targets.contains(cpu), online, sibling) {
+            (true, true, _) => down.push(PlannedWrite::new(OFFLINE_CPUS_KNOB, file, "0")),
+            (false, false, false) => up.push(PlannedWrite::new(OFFLINE_CPUS_KNOB, file, "1")),
+            _ => {}
+        }
+    }
+    up.extend(down);
+    up
+}
+
+/// The CPU whose `online` file a write goes to.
+fn online_cpu(write: &PlannedWrite) -> Option<u32> {
+    let dir = write.path.parent()?.file_name()?.to_str()?;
+    dir.strip_prefix("cpu")?.parse().ok()
+}
+
+/// Write of the SMT control, if it differs from the current state. Turning SMT off takes
+/// the sibling threads offline, and turning it on brings them back.
+fn plan_smt(on: bool) -> Vec<PlannedWrite> {
+    let file = path::Path::new(sysfs::SMT_CONTROL_PATH);
+    let value = if on { "on" } else { "off" };
+    match sysfs::read_value(file) {
+        Ok(current) if current == value => Vec::new(),
+        Ok(current) if current == "on" || current == "off" => {
+            vec![PlannedWrite::new(SMT_KNOB, file, value)]
+        }
+        Ok(current) => {
+            log::warn!("SMT is configured, but SMT control is {current}.");
+            Vec::new()
+        }
+        Err(_) => {
+            log::warn!("SMT is configured, but the kernel has no SMT control.");
+            Vec::new()
+        }
+    }
+}
+
+/// Write of the amd_pstate operation mode, if it differs from the current one. Switching
+/// re-registers the driver, so the mode is never written needlessly.
+fn plan_amd_pstate_mode(mode: AmdPstateMode) -> Vec<PlannedWrite> {
+    let file = path::Path::new(sysfs::AMD_PSTATE_STATUS_PATH);
+    let mode = mode.to_string();
+    match sysfs::read_value(file) {
+        Ok(current) if current == mode => Vec::new(),
+        Ok(_) => vec![PlannedWrite::new(AMD_PSTATE_MODE_KNOB, file, &mode)],
+        Err(_) => {
+            log::warn!("An amd_pstate mode is configured, but amd_pstate is not in use.");
+            Vec::new()
+        }
+    }
+}
+
+/// Boost writes for the policies of the given governor files, through the per-policy or
+/// the global boost files as found by `sysfs::find_boost_files`.
+fn plan_boost(governor_files: &[path::PathBuf], on: bool) -> Vec<PlannedWrite> {
+    let dirs: Vec<_> = governor_files.iter().filter_map(|f| f.parent()).collect();
+    let files = sysfs::find_boost_files(path::Path::new(sysfs::CPUFREQ_PATH), &dirs);
+    if files.is_empty() {
+        log::warn!("Boost is configured, but the cpufreq driver has no boost files.");
+    }
+    let value = if on { "1" } else { "0" };
+    files
+        .iter()
+        .map(|f| PlannedWrite::new(BOOST_KNOB, f, value))
+        .collect()
+}
+
+/// Writes of the intel_pstate global knobs. The driver clamps each performance limit to
+/// the other, so the lower limit is written first when the new upper limit is below the
+/// current lower one. Knobs the running kernel lacks are skipped.
+fn plan_intel_pstate(knobs: &IntelPstate) -> Vec<PlannedWrite> {
+    let dir = path::Path::new(sysfs::INTEL_PSTATE_PATH);
+    if !dir.exists() {
+        log::warn!("intel_pstate knobs are configured, but intel_pstate is not in use.");
+        return Vec::new();
+    }
+    let current_min: Option<u8> = sysfs::read_value(&dir.join("min_perf_pct"))
+        .ok()
+        .and_then(|v| v.parse().ok());
+    let flag = |on: bool| if on { "1" } else { "0" }.to_string();
+    let min = ("min_perf_pct", knobs.min_perf_pct.map(|p| p.to_string()));
+    let max = ("max_perf_pct", knobs.max_perf_pct.map(|p| p.to_string()));
+    let limits = match (knobs.max_perf_pct, current_min) {
+        (Some(new_max), Some(current_min)) if new_max < current_min => [min, max],
+        _ => [max, min],
+    };
+    let values = [("no_turbo", knobs.no_turbo.map(flag))]
+        .into_iter()
+        .chain(limits)
+        .chain([("hwp_dynamic_boost", knobs.hwp_dynamic_boost.map(flag))]);
+    let mut plan = Vec::new();
+    for (knob, value) in values {
+        let Some(value) = value else {
+            continue;
+        };
+        let file = dir.join(knob);
+        if file.exists() {
+            plan.push(PlannedWrite::new(knob, &file, &value));
+        } else {
+            log::warn!("intel_pstate has no {knob} knob, skipping it.");
+        }
+    }
+    plan
+}
+
+/// Wait until power-profiles-daemon owns one of its names on the bus, e.g. when the daemon
+/// starts before it at boot. Returns whether it did within `timeout`, waiting forever if
+/// that is zero. After giving up, a late appearance is sent to `late` as `PpdAppeared`.
+fn wait_for_ppd(
+    conn: &zbus::blocking::Connection,
+    timeout: Duration,
+    late: SessionSender,
+) -> zbus::Result<bool> {
+    let dbus = zbus::block_on(zbus::fdo::DBusProxy::new(conn.inner()))?;
+    // Subscribe before checking, so that a name cannot appear unnoticed in between.
+    let mut changes = executor::block_on_call(dbus.receive_name_owner_changed())?;
+    for (name, _) in PPD_SERVICES {
+        let name = name.try_into()?;
+        if executor::block_on_call(async { Ok(dbus.name_has_owner(name).await?) })? {
+            return Ok(true);
+        }
+    }
+    log::info!("Waiting for power-profiles-daemon to appear on the bus.");
+    let (sender, appeared) = mpsc::channel();
+    executor::spawn(async move {
+        while let Some(change) = changes.next().await {
+            let appeared = change.args().is_ok_and(|a| {
+                a.new_owner().is_some() && PPD_SERVICES.iter().any(|(n, _)| a.name() == n)
+            });
+            if appeared {
+                if sender.send(()).is_err() {
+                    late.send(Event::PpdAppeared);
+                }
+                return;
+            }
+            if !late.is_live() {
+                return;
+            }
+        }
+    });
+    systemd::notify("STATUS=Waiting for power-profiles-daemon.");
+    if !timeout.is_zero() {
+        // Do not let systemd give up on startup while still waiting.
+        systemd::notify(&format!("EXTEND_TIMEOUT_USEC={}", timeout.as_micros()));
+    }
+    match systemd::recv_timeout(&appeared, timeout) {
+        Ok(()) => {
+            log::info!("power-profiles-daemon appeared on the bus.");
+            Ok(true)
+        }
+        Err(mpsc::RecvTimeoutError::Timeout) => Ok(false),
+        Err(mpsc::RecvTimeoutError::Disconnected) => Err(zbus::Error::Failure(
+            "Stopped watching for power-profiles-daemon to appear on the bus".into(),
+        )),
+    }
+}
+
+/// One run of the event loop on a bus connection. Listener tasks may keep the connection
+/// alive after the event loop has given up on it, so dropping the session stops them from
+/// forwarding events and gives up the names claimed on it.
+struct BusSession {
+    conn: zbus::blocking::Connection,
+    live: Arc<AtomicBool>,
+}
+
+impl BusSession {
+    fn new(conn: &zbus::blocking::Connection) -> Self {
+        BusSession {
+            conn: conn.clone(),
+            live: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    /// Sender for listeners bound to this session's connection.
+    fn sender(&self, sender: &mpsc::Sender<Event>) -> SessionSender {
+        SessionSender {
+            sender: sender.clone(),
+            live: self.live.clone(),
+        }
+    }
+}
+
+impl Drop for BusSession {
+    fn drop(&mut self) {
+        self.live.store(false, Ordering::Relaxed);
+        // Fails harmlessly if a name was never claimed or the bus is gone.
+        for name in [
+            service::SERVICE_NAME,
+            provider::SERVICE_NAME,
+            provider::UPOWER_SERVICE_NAME,
+        ] {
+            let _ = self.conn.release_name(name);
+        }
+    }
+}
+
+/// Event sender for a listener bound to one bus session.
+struct SessionSender {
+    sender: mpsc::Sender<Event>,
+    live: Arc<AtomicBool>,
+}
+
+impl SessionSender {
+    fn is_live(&self) -> bool {
+        self.live.load(Ordering::Relaxed)
+    }
+
+    /// Forward an event to the event loop. Returns false if the listener should stop,
+    /// because the session has ended or the event loop is gone.
+    fn send(&self, event: Event) -> bool {
+        self.is_live() && self.sender.send(event).is_ok()
+    }
+}
+
+/// Forward clients leaving the bus to the event channel, so that their locks are released.
+fn spawn_client_listener(
+    conn: &zbus::blocking::Connection,
+    sender: SessionSender,
+) -> zbus::Result<()> {
+    let rule = zbus::MatchRule::builder()
+        .msg_type(zbus::MessageType::Signal)
+        .sender("org.freedesktop.DBus")?
+        .member("NameOwnerChanged")?
+        .arg(2, "")?
+        .build();
+    let mut messages = zbus::block_on(zbus::MessageStream::for_match_rule(
+        rule,
+        conn.inner(),
+        None,
+    ))?;
+    executor::spawn(async move {
+        while let Some(message) = messages.next().await {
+            let Ok(message) = message else {
+                continue;
+            };
+            let Ok((name, _, _)) = message.body::<(String, String, String)>() else {
+                continue;
+            };
+            if name.starts_with(':') && !sender.send(Event::ClientLeft(name)) {
+                return;
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Forward the loss of the bus connection to the event channel. Property change streams
+/// keep waiting silently when the bus goes away, but message streams end with the read
+/// error. The stream only matches the newer name of power-profiles-daemon changing owner,
+/// so it stays quiet otherwise.
+fn spawn_disconnect_listener(
+    conn: &zbus::blocking::Connection,
+    sender: SessionSender,
+) -> zbus::Result<()> {
+    let rule = zbus::MatchRule::builder()
+        .msg_type(zbus::MessageType::Signal)
+        .sender("org.freedesktop.DBus")?
+        .member("NameOwnerChanged")?
+        .add_arg(PPD_SERVICES[0].0)?
+        .build();
+    let mut messages = zbus::block_on(zbus::MessageStream::for_match_rule(
+        rule,
+        conn.inner(),
+        None,
+    ))?;
+    executor::spawn(async move {
+        while let Some(message) = messages.next().await {
+            match message {
+                Ok(_) if sender.is_live() => continue,
+                Ok(_) => return,
+                Err(e) => {
+                    sender.send(Event::Disconnected(e));
+                    return;
+                }
+            }
+        }
+        let error = zbus::Error::Failure("The system bus closed the connection".into());
+        sender.send(Event::Disconnected(error));
+    });
+    Ok(())
+}
+
+/// How long to wait before reading a changed property again after the read timed out.
+const TIMED_OUT_READ_DELAY: Duration = Duration::from_secs(1);
+
+/// Read a changed property, reading it again while the read times out, since the change
+/// would otherwise be lost until the next one.
+async fn read_change<T, F>(property: &str, read: impl Fn() -> F) -> zbus::Result<T>
+where
+    F: std::future::Future<Output = zbus::Result<T>>,
+{
+    loop {
+        match executor::call(read()).await {
+            Err(e) if executor::timed_out(&e) => {
+                log::warn!("Could not read {property}, trying again: {e}");
+                executor::sleep(TIMED_OUT_READ_DELAY).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Forward ActiveProfile changes from power-profiles-daemon to the event channel.
+fn spawn_active_profile_listener(
+    proxy: PowerProfilesDaemonManagerProxy<'static>,
+    sender: SessionSender,
+) {
+    executor::spawn(async move {
+        let mut changes = proxy.receive_active_profile_changed().await;
+        while let Some(change) = changes.next().await {
+            let event = match read_change("ActiveProfile", || change.get()).await {
+                Ok(val) => Event::ActiveProfileChanged(val),
+                Err(e) => {
+                    sender.send(Event::ListenerFailed(e));
+                    return;
+                }
+            };
+            if !sender.send(event) {
+                return;
+            }
+        }
+        sender.send(Event::ListenerStopped);
+    });
+}
+
+/// IDs of the applications in an ActiveProfileHolds value.
+fn hold_applications(holds: &[HashMap<String, zbus::zvariant::OwnedValue>]) -> Vec<String> {
+    holds
+        .iter()
+        .map(|h| {
+            h.get("ApplicationId")
+                .and_then(|v| <&str>::try_from(&**v).ok())
+                .unwrap_or("unknown")
+                .to_string()
+        })
+        .collect()
+}
+
+/// Forward ActiveProfileHolds changes from power-profiles-daemon to the event channel.
+/// Holds are optional, so a change that cannot be read only stops the listener.
+fn spawn_holds_listener(proxy: PowerProfilesDaemonManagerProxy<'static>, sender: SessionSender) {
+    executor::spawn(async move {
+        let mut changes = proxy.receive_active_profile_holds_changed().await;
+        while let Some(change) = changes.next().await {
+            let holds = match read_change("ActiveProfileHolds", || change.get()).await {
+                Ok(h) => h,
+                Err(e) => {
+                    log::warn!("Could not read ActiveProfileHolds: {e}");
+                    return;
+                }
+            };
+            if !sender.send(Event::HoldsChanged(hold_applications(&holds))) {
+                return;
+            }
+        }
+    });
+}
+
+/// Forward PerformanceDegraded changes from power-profiles-daemon to the event channel.
+/// A change that cannot be read only stops the listener, like for holds.
+fn spawn_degraded_listener(proxy: PowerProfilesDaemonManagerProxy<'static>, sender: SessionSender) {
+    executor::spawn(async move {
+        let mut changes = proxy.receive_performance_degraded_changed().await;
+        while let Some(change) = changes.next().await {
+            let reason = match read_change("PerformanceDegraded", || change.get()).await {
+                Ok(r) => r,
+                Err(e) => {
+                    log::warn!("Could not read PerformanceDegraded: {e}");
+                    return;
+                }
+            };
+            if !sender.send(Event::DegradedChanged(reason)) {
+                return;
+            }
+        }
+    });
+}
+
+/// Time to let the system settle after resuming before re-applying, since firmware may
+/// still be restoring its own values right after wakeup.
+const RESUME_SETTLE_DELAY: Duration = Duration::from_secs(2);
+
+/// Forward resumes from suspend, reported by logind's PrepareForSleep signal, to the event
+/// channel once the system has had time to settle. Without logind the daemon simply does
+/// not notice resumes, so failing to subscribe is only logged.
+fn spawn_resume_listener(conn: &zbus::blocking::Connection, sender: SessionSender) {
+    let conn = conn.inner().clone();
+    executor::spawn(async move {
+        let signals = match LoginManagerProxy::new(&conn).await {
+            Ok(proxy) => proxy.receive_prepare_for_sleep().await,
+            Err(e) => Err(e),
+        };
+        let mut signals = match signals {
+            Ok(s) => s,
+            Err(e) => {
+                log::warn!("Could not watch for resume from suspend: {e}");
+                return;
+            }
+        };
+        while let Some(signal) = signals.next().await {
+            if signal.args().is_ok_and(|a| !a.start) {
+                executor::sleep(RESUME_SETTLE_DELAY).await;
+                if !sender.send(Event::Resumed) {
+                    return;
+                }
+            } else if !sender.is_live() {
+                return;
+            }
+        }
+    });
+}
+
+/// How often the power supplies are polled for a change of power source.
+const POWER_SOURCE_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Read the power source from UPower, or from the power supplies in sysfs if UPower is
+/// unavailable.
+pub fn fetch_power_source() -> Option<PowerSource> {
+    let on_battery = executor::block_on_call(async {
+        UPowerProxy::new(&executor::system_bus().await?)
+            .await?
+            .on_battery()
+            .await
+    });
+    match on_battery {
+        Ok(on_battery) => Some(upower::power_source(on_battery)),
+        Err(_) => power::read_power_source(path::Path::new(power::POWER_SUPPLY_PATH)),
+    }
+}
+
+/// Forward changes of power source to the event channel. UPower's `OnBattery` property is
+/// followed while UPower is available, and the power supplies in sysfs are polled
+/// otherwise.
+pub fn spawn_power_source_listener(sender: mpsc::Sender<Event>) {
+    executor::spawn(async move {
+        let proxy = async {
+            let proxy = UPowerProxy::new(&executor::system_bus().await?).await?;
+            executor::call(proxy.on_battery()).await.map(|_| proxy)
+        };
+        match proxy.await {
+            Ok(proxy) => {
+                log::info!("Tracking the power source through UPower.");
+                let mut changes = proxy.receive_on_battery_changed().await;
+                while let Some(change) = changes.next().await {
+                    match change.get().await {
+                        Ok(on_battery) => {
+                            let source = upower::power_source(on_battery);
+                            if sender.send(Event::PowerSourceChanged(source)).is_err() {
+                                return;
+                            }
+                        }
+                        Err(e) => log::warn!("Failed to read power source change: {e}"),
+                    }
+                }
+                log::warn!("UPower stopped reporting the power source, polling sysfs instead.");
+            }
+            Err(e) => log::info!("Polling sysfs for the power source, UPower is unavailable: {e}"),
+        }
+        poll_power_source(&sender).await;
+    });
+}
+
+/// Poll the power supplies in sysfs and forward changes of power source to the event
+/// channel.
+async fn poll_power_source(sender: &mpsc::Sender<Event>) {
+    let power_supply_path = path::Path::new(power::POWER_SUPPLY_PATH);
+    let mut last = power::read_power_source(power_supply_path);
+    let mut ticks = async_io::Timer::interval(POWER_SOURCE_POLL_INTERVAL);
+    while ticks.next().await.is_some() {
+        let current = power::read_power_source(power_supply_path);
+        if current == last {
+            continue;
+        }
+        last = current;
+        if let Some(source) = current {
+            if sender.send(Event::PowerSourceChanged(source)).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Send the event made by `tick` to the event channel every `interval`, until the event
+/// loop is gone.
+fn spawn_ticker(interval: Duration, sender: mpsc::Sender<Event>, tick: fn() -> Event) {
+    executor::spawn(async move {
+        let mut ticks = async_io::Timer::interval(interval);
+        while ticks.next().await.is_some() {
+            if sender.send(tick()).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+/// How often the temperature sensors are checked against the thermal rules.
+const THERMAL_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Periodically ask the event loop to check the temperature sensors.
+fn spawn_thermal_listener(sender: mpsc::Sender<Event>) {
+    spawn_ticker(THERMAL_POLL_INTERVAL, sender, || Event::ThermalTick);
+}
+
+/// How often the displays and the match path are checked for docking.
+const DOCK_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Periodically ask the event loop to check whether the laptop is docked.
+fn spawn_dock_listener(sender: mpsc::Sender<Event>) {
+    spawn_ticker(DOCK_POLL_INTERVAL, sender, || Event::DockTick);
+}
+
+/// How often the desktop sessions are checked for users who are not followed yet.
+const SESSION_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// `pstate_update <command>` child following a service in one desktop session.
+struct SessionWatcher {
+    uid: u32,
+    child: async_process::Child,
+    /// Last number the child printed.
+    value: u32,
+}
+
+/// Follow a service in every local desktop session, and forward `event(state)` to the event
+/// channel whenever `state` of the numbers printed by the sessions changes. Each session is
+/// followed by a `pstate_update <command>` child running as its user, until the user leaves
+/// the seat. A session whose watcher exits, e.g. because the service is not installed, is
+/// not followed again until the user logs in anew. `what` names the service in the log.
+fn spawn_session_watchers(
+    command: &'static str,
+    what: &'static str,
+    sender: mpsc::Sender<Event>,
+    state: fn(&[u32]) -> bool,
+    event: fn(bool) -> Event,
+) {
+    executor::spawn(async move {
+        let (values_sender, mut values) = futures_channel::mpsc::unbounded();
+        let mut watchers: Vec<SessionWatcher> = Vec::new();
+        let mut finished: Vec<u32> = Vec::new();
+        let mut current = false;
+        loop {
+            match logind::session_commands().await {
+                Ok(commands) => {
+                    let users: Vec<u32> = commands.iter().map(|(uid, _)| *uid).collect();
+                    // Children are reaped by async-process once killed and dropped.
+                    watchers.retain_mut(|w| {
+                        let present = users.contains(&w.uid);
+                        if !present {
+                            let _ = w.child.kill();
+                        }
+                        present
+                    });
+                    finished.retain(|uid| users.contains(uid));
+                    for (uid, mut command_for_user) in commands {
+                        if finished.contains(&uid) || watchers.iter().any(|w| w.uid == uid) {
+                            continue;
+                        }
+                        command_for_user
+                            .arg(command)
+                            .stdout(process::Stdio::piped());
+                        match async_process::Command::from(command_for_user).spawn() {
+                            Ok(mut child) => {
+                                let stdout = child.stdout.take();
+                                let (pid, values_sender) = (child.id(), values_sender.clone());
+                                executor::spawn(async move {
+                                    if let Some(stdout) = stdout {
+                                        let mut lines =
+                                            futures_util::io::BufReader::new(stdout).lines();
+                                        while let Some(Ok(line)) = lines.next().await {
+                                            let Ok(value) = line.parse() else {
+                                                break;
+                                            };
+                                            let _ =
+                                                values_sender.unbounded_send((pid, Some(value)));
+                                        }
+                                    }
+                                    let _ = values_sender.unbounded_send((pid, None));
+                                });
+                                watchers.push(SessionWatcher {
+                                    uid,
+                                    child,
+                                    value: 0,
+                                });
+                            }
+                            Err(e) => {
+                                log::warn!("Failed to follow {what} of user {uid}: {e}");
+                                finished.push(uid);
+                            }
+                        }
+                    }
+                }
+                Err(e) => log::warn!("Cannot follow {what}, {e}"),
+            }
+            let mut poll = async_io::Timer::after(SESSION_POLL_INTERVAL);
+            while let Either::Left((Some((pid, value)), _)) =
+                future::select(values.next(), &mut poll).await
+            {
+                let Some(i) = watchers.iter().position(|w| w.child.id() == pid) else {
+                    continue;
+                };
+                match value {
+                    Some(value) => watchers[i].value = value,
+                    None => finished.push(watchers.remove(i).uid),
+                }
+                let values: Vec<u32> = watchers.iter().map(|w| w.value).collect();
+                let now = state(&values);
+                if now != current {
+                    current = now;
+                    if sender.send(event(current)).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Follow GameMode's client count in every local desktop session, and forward whether
+/// any game has GameMode requested to the event channel.
+pub fn spawn_gamemode_listener(sender: mpsc::Sender<Event>) {
+    spawn_session_watchers(
+        "gamemode-watch",
+        "GameMode",
+        sender,
+        |clients| clients.iter().any(|c| *c > 0),
+        Event::GamingChanged,
+    );
+}
+
+/// Follow the screen saver in every local desktop session, and forward whether the
+/// screens of all of them are locked to the event channel.
+pub fn spawn_screen_lock_listener(sender: mpsc::Sender<Event>) {
+    spawn_session_watchers(
+        "screensaver-watch",
+        "the screen lock",
+        sender,
+        |locked| !locked.is_empty() && locked.iter().all(|l| *l > 0),
+        Event::ScreenLockChanged,
+    );
+}
+
+/// Ask the event loop to ping the systemd watchdog, if systemd watches the daemon. A hung
+/// event loop then stops the pings, so that systemd restarts the daemon.
+pub fn spawn_watchdog_listener(sender: mpsc::Sender<Event>) {
+    let Some(interval) = systemd::watchdog_interval() else {
+        return;
+    };
+    log::info!(
+        "Pinging the systemd watchdog every {} ms.",
+        interval.as_millis()
+    );
+    spawn_ticker(interval, sender, || Event::WatchdogTick);
+}
+
+/// How often the daemon checks whether it has been idle for long enough to exit.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Periodically ask the event loop to check whether the daemon has been idle.
+pub fn spawn_idle_listener(sender: mpsc::Sender<Event>) {
+    spawn_ticker(IDLE_POLL_INTERVAL, sender, || Event::IdleTick);
+}
+
+/// How often the clock is checked against the schedules.
+const SCHEDULE_POLL_INTERVAL: Duration = Duration::from_secs(20);
+
+/// Periodically ask the event loop to check the clock against the schedules.
+fn spawn_schedule_listener(sender: mpsc::Sender<Event>) {
+    spawn_ticker(SCHEDULE_POLL_INTERVAL, sender, || Event::ScheduleTick);
+}
+
+/// How often logind is asked how long every session has been idle. `[idle] after_s` is
+/// 300 seconds by default, so reacting a few seconds late is harmless.
+const SESSIONS_IDLE_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Time since logind last saw any session in use, or `None` if one is in use now.
+async fn fetch_idle_time(proxy: &LoginManagerProxy<'_>) -> zbus::Result<Option<Duration>> {
+    if !proxy.idle_hint().await? {
+        return Ok(None);
+    }
+    let since = UNIX_EPOCH + Duration::from_micros(proxy.idle_since_hint().await?);
+    Ok(Some(
+        SystemTime::now().duration_since(since).unwrap_or_default(),
+    ))
+}
+
+/// Periodically forward how long logind has seen every session idle to the event channel.
+pub fn spawn_sessions_idle_listener(sender: mpsc::Sender<Event>) {
+    executor::spawn(async move {
+        let mut proxy = None;
+        let mut ticks = async_io::Timer::interval(SESSIONS_IDLE_POLL_INTERVAL);
+        loop {
+            if proxy.is_none() {
+                // Polled anyway, so cached values would only go stale.
+                let built = async {
+                    LoginManagerProxy::builder(&executor::system_bus().await?)
+                        .cache_properties(zbus::CacheProperties::No)
+                        .build()
+                        .await
+                };
+                proxy = built
+                    .await
+                    .map_err(|e| log::warn!("Cannot follow session idleness: {e}"))
+                    .ok();
+            }
+            if let Some(p) = &proxy {
+                match executor::call(fetch_idle_time(p)).await {
+                    Ok(idle) => {
+                        if sender.send(Event::SessionsIdle(idle)).is_err() {
+                            return;
+                        }
+                    }
+                    Err(e) => {
+                        log::warn!("Could not read session idleness from logind: {e}");
+                        proxy = None;
+                    }
+                }
+            }
+            ticks.next().await;
+        }
+    });
+}
+
+/// Current local time of day.
+pub fn local_time_of_day() -> TimeOfDay {
+    let now = chrono::Local::now();
+    TimeOfDay(now.hour() * 60 + now.minute())
+}
+
+/// Read the battery level from UPower, logging failures.
+pub fn fetch_battery_level() -> Option<f64> {
+    let level = executor::block_on_call(async {
+        let proxy = DisplayDeviceProxy::new(&executor::system_bus().await?).await?;
+        upower::read_battery_level(&proxy).await
+    });
+    match level {
+        Ok(level) => level,
+        Err(e) => {
+            log::warn!("Could not read battery level from UPower: {e}");
+            None
+        }
+    }
+}
+
+/// Forward battery level changes reported by UPower to the event channel. Does nothing
+/// on systems without UPower or without a battery.
+pub fn spawn_battery_level_listener(sender: mpsc::Sender<Event>) {
+    executor::spawn(async move {
+        let proxy = async { DisplayDeviceProxy::new(&executor::system_bus().await?).await };
+        let proxy = match proxy.await {
+            Ok(p) => p,
+            Err(e) => {
+                log::info!("Not tracking the battery level, UPower is unavailable: {e}");
+                return;
+            }
+        };
+        if !executor::call(proxy.is_present()).await.unwrap_or(false) {
+            log::info!("Not tracking the battery level, UPower reports no battery.");
+            return;
+        }
+        let mut changes = proxy.receive_percentage_changed().await;
+        while let Some(change) = changes.next().await {
+            match change.get().await {
+                Ok(level) => {
+                    if sender.send(Event::BatteryLevelChanged(level)).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => log::warn!("Failed to read battery level change: {e}"),
+            }
+        }
+    });
+}
+
+/// Read the battery warning from UPower, logging failures.
+pub fn fetch_battery_warning() -> Option<BatteryWarning> {
+    let level = executor::block_on_call(async {
+        DisplayDeviceProxy::new(&executor::system_bus().await?)
+            .await?
+            .warning_level()
+            .await
+    });
+    match level {
+        Ok(level) => upower::battery_warning(level),
+        Err(e) => {
+            log::warn!("Could not read battery warning level from UPower: {e}");
+            None
+        }
+    }
+}
+
+/// Forward battery warning level changes reported by UPower to the event channel. Does
+/// nothing on systems without UPower.
+fn spawn_battery_warning_listener(sender: mpsc::Sender<Event>) {
+    executor::spawn(async move {
+        let proxy = async { DisplayDeviceProxy::new(&executor::system_bus().await?).await };
+        let proxy = match proxy.await {
+            Ok(p) => p,
+            Err(e) => {
+                log::info!("Not tracking battery warnings, UPower is unavailable: {e}");
+                return;
+            }
+        };
+        let mut changes = proxy.receive_warning_level_changed().await;
+        while let Some(change) = changes.next().await {
+            match change.get().await {
+                Ok(level) => {
+                    let warning = upower::battery_warning(level);
+                    if sender.send(Event::BatteryWarningChanged(warning)).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => log::warn!("Failed to read battery warning level change: {e}"),
+            }
+        }
+    });
+}
+
+/// Forward SIGHUP to the event channel as a config reload request.
+pub fn spawn_signal_listener(sender: mpsc::Sender<Event>) -> io::Result<()> {
+    // The handler writes a byte to the pipe per signal, which the task waits for.
+    let (pipe, handler_end) = UnixStream::pair()?;
+    signal_hook::low_level::pipe::register(signal_hook::consts::SIGHUP, handler_end)?;
+    let mut pipe = async_io::Async::new(pipe)?;
+    executor::spawn(async move {
+        let mut received = [0; 16];
+        while matches!(pipe.read(&mut received).await, Ok(n) if n > 0) {
+            log::info!("Received SIGHUP.");
+            if sender.send(Event::Reload).is_err() {
+                return;
+            }
+        }
+    });
+    Ok(())
+}
+
+impl EPPController {
+    /// Listen for `PowerProfiles` property changes on D-Bus and act on relvant changes.
+    ///
+    /// Property changes are forwarded to the `events` channel by listener tasks, so
+    /// that they can be handled together with events from other sources, like SIGHUP.
+    pub fn run(
+        &mut self,
+        location: &ConfigLocation,
+        sender: &mpsc::Sender<Event>,
+        events: &mpsc::Receiver<Event>,
+    ) -> Result<(), zbus::Error> {
+        let conn = zbus::blocking::Connection::system()?;
+        let session = BusSession::new(&conn);
+        // The control interface is optional, so the daemon keeps working on systems where
+        // the D-Bus policy for our service name has not been installed.
+        let signals = match Events::serve(&conn, sender.clone(), self.config.clone()) {
+            Ok(e) => Some(e),
+            Err(e) => {
+                log::warn!("Could not serve control interface on D-Bus: {e}");
+                None
+            }
+        };
+        spawn_disconnect_listener(&conn, session.sender(sender))?;
+        spawn_resume_listener(&conn, session.sender(sender));
+        spawn_client_listener(&conn, session.sender(sender))?;
+        // Locks did not survive losing the previous connection.
+        if !self.inhibitors.is_empty() {
+            log::info!("Releasing inhibitors from the previous bus connection.");
+            self.inhibitors.clear();
+        }
+        let ppd_wait = Duration::from_millis(self.config.retry.ppd_wait_ms);
+        if self.config.provider {
+            // Keep the profile that was active before a reconnect.
+            let active = self
+                .active_profile
+                .clone()
+                .unwrap_or_else(|| PPDPowerProfile::Balanced.to_string());
+            provider::serve(&conn, sender.clone(), &active)?;
+            self.process_active_profile_changed(&active, signals.as_ref())?;
+        } else if !wait_for_ppd(&conn, ppd_wait, session.sender(sender))? {
+            let Some(active) = self.config.fallback_profile.as_ref().map(|p| p.to_string()) else {
+                return Err(zbus::Error::Failure(format!(
+                    "power-profiles-daemon did not appear on the bus within {} ms",
+                    ppd_wait.as_millis()
+                )));
+            };
+            log::warn!(
+                "power-profiles-daemon did not appear on the bus. Applying the fallback \
+                 profile {active} until it does."
+            );
+            self.process_active_profile_changed(&active, signals.as_ref())?;
+        } else {
+            let proxy =
+                PowerProfilesDaemonManagerProxy::from(ppd_proxy(&conn)?.into_inner().into_inner());
+            // Versions before 0.10 have no holds.
+            self.held =
+                executor::block_on_call(proxy.active_profile_holds()).is_ok_and(|h| !h.is_empty());
+            self.degraded =
+                executor::block_on_call(proxy.performance_degraded()).is_ok_and(|r| !r.is_empty());
+            match executor::block_on_call(proxy.profiles()) {
+                Ok(profiles) => {
+                    let offered = offered_profiles(&profiles);
+                    for problem in self.config.check_offered_profiles(&offered) {
+                        log::warn!("{problem}");
+                    }
+                }
+                Err(e) => log::warn!("Could not read the profiles of power-profiles-daemon: {e}"),
+            }
+            let active = match (
+                executor::block_on_call(proxy.active_profile()),
+                &self.active_profile,
+            ) {
+                (Ok(active), _) => active,
+                // A hung power-profiles-daemon must not undo what was applied before.
+                (Err(e), Some(last)) if executor::timed_out(&e) => {
+                    log::warn!(
+                        "power-profiles-daemon did not report its ActiveProfile: {e}. Keeping \
+                         {last} until it reports a change."
+                    );
+                    last.clone()
+                }
+                (Err(e), _) => return Err(e),
+            };
+            // The general strategy is to fail early here, but not fail on later property
+            // changes. If we encounter errors on property changes, they will mainly be logged.
+            self.process_active_profile_changed(&active, signals.as_ref())?;
+
+            log::info!(
+                "Starting to listen for ActiveProfile changes on {}, {}.",
+                proxy.destination(),
+                proxy.path(),
+            );
+            spawn_holds_listener(proxy.clone(), session.sender(sender));
+            spawn_degraded_listener(proxy.clone(), session.sender(sender));
+            spawn_active_profile_listener(proxy, session.sender(sender));
+        }
+        // Repeated after reconnecting, which systemd ignores.
+        systemd::notify("READY=1");
+        // Profile change that is waiting for its apply delay to pass, and when to apply it.
+        let mut pending: Option<(String, Instant)> = None;
+        // When the last profile change or control request arrived.
+        let mut last_request = Instant::now();
+        loop {
+            let event = match &pending {
+                Some((_, deadline)) => {
+                    match events.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                        Ok(event) => event,
+                        Err(mpsc::RecvTimeoutError::Timeout) => {
+                            if let Some((val, _)) = pending.take() {
+                                self.try_process_active_profile_changed(&val, signals.as_ref());
+                            }
+                            continue;
+                        }
+                        Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+                None => match events.recv() {
+                    Ok(event) => event,
+                    Err(_) => break,
+                },
+            };
+            if matches!(
+                event,
+                Event::ActiveProfileChanged(_)
+                    | Event::Reload
+                    | Event::ProfileSelected(_)
+                    | Event::OverrideChanged(_)
+                    | Event::ApplyRequested(_)
+                    | Event::InhibitorsChanged(_)
+            ) {
+                last_request = Instant::now();
+            }
+            match event {
+                Event::ActiveProfileChanged(val) => {
+                    // A newer change replaces a pending one, so flapping profiles only
+                    // cause writes once they have settled.
+                    let delay = self.config.apply_delay(&val);
+                    if delay.is_zero() {
+                        pending = None;
+                        self.try_process_active_profile_changed(&val, signals.as_ref());
+                    } else {
+                        log::info!(
+                            "ActiveProfile changed to {val}. Applying in {} ms.",
+                            delay.as_millis()
+                        );
+                        pending = Some((val, Instant::now() + delay));
+                    }
+                }
+                Event::Reload => self.reload(location, sender, signals.as_ref()),
+                Event::PowerSourceChanged(source) => {
+                    self.process_power_source_changed(source, signals.as_ref())
+                }
+                Event::BatteryLevelChanged(level) => {
+                    self.process_battery_level_changed(level, signals.as_ref())
+                }
+                Event::BatteryWarningChanged(warning) => {
+                    self.process_battery_warning_changed(warning, signals.as_ref())
+                }
+                Event::GamingChanged(gaming) => {
+                    self.process_gaming_changed(gaming, signals.as_ref())
+                }
+                Event::ScreenLockChanged(locked) => {
+                    self.process_screen_lock_changed(locked, signals.as_ref())
+                }
+                Event::InhibitorsChanged(inhibitors) => {
+                    self.process_inhibitors_changed(inhibitors, signals.as_ref())
+                }
+                Event::ClientLeft(name) => {
+                    if let Some(signals) = &signals {
+                        signals.release_inhibitors_of(&name);
+                    }
+                }
+                Event::ThermalTick => self.process_thermal_tick(signals.as_ref()),
+                Event::DockTick => {
+                    if self.update_docked() {
+                        self.reapply(signals.as_ref());
+                    }
+                }
+                Event::ScheduleTick => self.process_schedule_tick(signals.as_ref()),
+                Event::SessionsIdle(idle) => self.process_sessions_idle(idle, signals.as_ref()),
+                Event::WatchdogTick => systemd::notify("WATCHDOG=1"),
+                Event::IdleTick => {
+                    let Some(timeout) = self.idle_timeout else {
+                        continue;
+                    };
+                    if pending.is_none()
+                        && self.inhibitors.is_empty()
+                        && last_request.elapsed() >= timeout
+                    {
+                        log::info!(
+                            "No requests for {} s, exiting until started again.",
+                            timeout.as_secs()
+                        );
+                        systemd::notify("STOPPING=1");
+                        process::exit(0);
+                    }
+                }
+                Event::HoldsChanged(applications) => {
+                    self.process_holds_changed(applications, signals.as_ref())
+                }
+                Event::DegradedChanged(reason) => {
+                    self.process_degraded_changed(&reason, signals.as_ref())
+                }
+                Event::Resumed => {
+                    log::info!("Resumed from suspend, re-applying the active profile.");
+                    self.reapply(signals.as_ref());
+                }
+                Event::ProfileSelected(name) => {
+                    self.process_profile_selected(name, signals.as_ref())
+                }
+                Event::OverrideChanged(user_override) => {
+                    match &user_override {
+                        Some(o) => log::info!("Override set: {o}."),
+                        None => log::info!("Override cleared."),
+                    }
+                    self.user_override = user_override;
+                    self.reapply(signals.as_ref());
+                }
+                Event::ApplyRequested(profile) => {
+                    log::info!("Applying {profile} once, as requested.");
+                    self.apply_profile(&profile, signals.as_ref());
+                }
+                Event::StatusRequested(reply) => {
+                    let _ = reply.send(rpc::Status {
+                        active_profile: self.active_profile.clone(),
+                        selected_profile: self.selected_profile.clone(),
+                        power_source: self.power_source.map(|s| s.to_string()),
+                    });
+                }
+                Event::ListenerFailed(e) => return Err(e),
+                Event::ListenerStopped => break,
+                Event::PpdAppeared => {
+                    log::info!("power-profiles-daemon appeared on the bus, following it again.");
+                    break;
+                }
+                Event::Disconnected(e) => {
+                    log::warn!("Lost the connection to the system bus.");
+                    return Err(e);
+                }
+            }
+        }
+        log::info!("Finished listening for property changes.");
+        Ok(())
+    }
+
+    /// Start the pollers the config needs that are not running yet.
+    pub fn start_pollers(&mut self, sender: &mpsc::Sender<Event>) {
+        if !self.config.thermal_rule.is_empty() && !self.pollers.thermal {
+            spawn_thermal_listener(sender.clone());
+            self.pollers.thermal = true;
+        }
+        if self.config.docked.is_some() && !self.pollers.dock {
+            spawn_dock_listener(sender.clone());
+            self.pollers.dock = true;
+        }
+        if !self.config.schedule.is_empty() && !self.pollers.schedule {
+            spawn_schedule_listener(sender.clone());
+            self.pollers.schedule = true;
+        }
+        if self.config.low_battery.is_some() && !self.pollers.battery_warning {
+            spawn_battery_warning_listener(sender.clone());
+            self.pollers.battery_warning = true;
+        }
+    }
+
+    /// Re-read the config and re-apply the active profile with it. The old config is kept
+    /// if the new one cannot be read.
+    fn reload(
+        &mut self,
+        location: &ConfigLocation,
+        sender: &mpsc::Sender<Event>,
+        signals: Option<&Events>,
+    ) {
+        log::info!("Reloading config.");
+        let config = match config::read_config(location) {
+            Ok(c) => c,
+            Err(e) => {
+                log::error!("Failed to reload config, keeping the current one: {e}");
+                return;
+            }
+        };
+        if config.exclude_cpus != self.config.exclude_cpus {
+            let cpufreq_path = path::Path::new(sysfs::CPUFREQ_PATH);
+            match sysfs::find_managed_files(cpufreq_path, &config.exclude_cpus.0) {
+                Ok((epp_files, governor_files)) if !governor_files.is_empty() => {
+                    self.epp_core_files = epp_files;
+                    self.governor_core_files = governor_files;
+                }
+                Ok(_) => {
+                    log::error!(
+                        "No policies left with the new exclude_cpus, keeping the current config."
+                    );
+                    return;
+                }
+                Err(e) => {
+                    log::error!("Failed to find cpufreq files, keeping the current config: {e}");
+                    return;
+                }
+            }
+        }
+        executor::set_call_timeout(config.retry.dbus_timeout());
+        if config.low_battery.is_some() && self.config.low_battery.is_none() {
+            self.battery_warning = fetch_battery_warning();
+        }
+        if config.provider != self.config.provider {
+            log::warn!("Switching provider mode on or off only takes effect after a restart.");
+        }
+        if config.gamemode.is_some() && self.config.gamemode.is_none() {
+            log::warn!("Following GameMode only starts after a restart.");
+        }
+        if config.screen_lock.is_some() && self.config.screen_lock.is_none() {
+            log::warn!("Following the screen lock only starts after a restart.");
+        }
+        if config.idle.is_some() && self.config.idle.is_none() {
+            log::warn!("Following session idleness only starts after a restart.");
+        }
+        self.ppd_owns_platform_profile =
+            config.uses_platform_profile() && !config.provider && ppd_owns_platform_profile();
+        if let Some(name) = &self.selected_profile {
+            if !config.profile.contains_key(name) {
+                log::warn!("Profile {name} is no longer in the config, clearing the selection.");
+                self.selected_profile = None;
+            }
+        }
+        self.thermal_active = vec![false; config.thermal_rule.len()];
+        self.schedule_active = config.schedules_at(local_time_of_day());
+        self.config = Arc::new(config);
+        if let Some(signals) = signals {
+            signals.set_config(self.config.clone());
+        }
+        self.start_pollers(sender);
+        self.docked = self.detect_docked();
+        self.warn_unmatched_overrides();
+        self.warn_missing_sensors();
+        self.reapply(signals);
+    }
+
+    /// Re-apply the most recently received profile, e.g. after the config or the
+    /// conditions it depends on changed.
+    fn reapply(&mut self, events: Option<&Events>) {
+        if let Some(name) = self.active_profile.clone() {
+            if let Err(e) = self.apply_named(&name, events) {
+                log::error!("Failed to re-apply profile {name}: {e}");
+                let body = format!("Could not re-apply profile {name}: {e}");
+                self.notifier.notify(&self.config.notify, &body);
+            }
+        }
+    }
+
+    /// Record the new power source, and re-apply the active profile if the mapping
+    /// depends on it.
+    fn process_power_source_changed(&mut self, source: PowerSource, events: Option<&Events>) {
+        if self.power_source == Some(source) {
+            return;
+        }
+        log::info!("Power source changed: {source}");
+        self.power_source = Some(source);
+        self.update_docked();
+        if self.config.uses_power_source() {
+            self.reapply(events);
+        }
+    }
+
+    /// Record whether any application holds a profile, and re-apply the active profile
+    /// if that changed and the config has a hold section.
+    fn process_holds_changed(&mut self, applications: Vec<String>, events: Option<&Events>) {
+        let held = !applications.is_empty();
+        if held == self.held {
+            return;
+        }
+        self.held = held;
+        if held {
+            log::info!("Profile held by {}.", applications.join(", "));
+        } else {
+            log::info!("Profile holds released.");
+        }
+        if self.config.hold.is_some() {
+            self.reapply(events);
+        }
+    }
+
+    /// Record whether performance is degraded, and re-apply the active profile if that
+    /// changed and the config has a degraded section.
+    fn process_degraded_changed(&mut self, reason: &str, events: Option<&Events>) {
+        let degraded = !reason.is_empty();
+        if degraded == self.degraded {
+            return;
+        }
+        self.degraded = degraded;
+        if degraded {
+            log::info!("Performance degraded: {reason}.");
+        } else {
+            log::info!("Performance no longer degraded.");
+        }
+        if !self.config.degraded.is_empty() {
+            self.reapply(events);
+        }
+    }
+
+    /// Record the new battery level, and re-apply the active profile if another battery
+    /// rule applies at the new level.
+    fn process_battery_level_changed(&mut self, level: f64, events: Option<&Events>) {
+        let threshold = |c: &Self| {
+            c.config
+                .battery_rule(c.power_source, c.battery_level)
+                .map(|r| r.below)
+        };
+        let before = threshold(self);
+        self.battery_level = Some(level);
+        let after = threshold(self);
+        if before == after {
+            return;
+        }
+        match after {
+            Some(below) => log::info!("Battery at {level}%, applying the rule for below {below}%."),
+            None => log::info!("Battery at {level}%, no battery rule applies any more."),
+        }
+        self.reapply(events);
+    }
+
+    /// Record the new battery warning, and re-apply the active profile if the config
+    /// reacts to it.
+    fn process_battery_warning_changed(
+        &mut self,
+        warning: Option<BatteryWarning>,
+        events: Option<&Events>,
+    ) {
+        if warning == self.battery_warning {
+            return;
+        }
+        self.battery_warning = warning;
+        if self.config.low_battery.is_none() {
+            return;
+        }
+        match warning {
+            Some(warning) => log::info!("Battery {warning}, forcing the power-saver mapping."),
+            None => log::info!("Battery no longer low, restoring the active profile."),
+        }
+        self.reapply(events);
+    }
+
+    /// Record whether a game runs, and re-apply the active profile if the config reacts to
+    /// it.
+    fn process_gaming_changed(&mut self, gaming: bool, events: Option<&Events>) {
+        if gaming == self.gaming {
+            return;
+        }
+        self.gaming = gaming;
+        let Some(gamemode) = &self.config.gamemode else {
+            return;
+        };
+        if gaming {
+            log::info!("A game requested GameMode, applying {}.", gamemode.profile);
+        } else {
+            log::info!("The last game using GameMode exited, restoring the active profile.");
+        }
+        self.reapply(events);
+    }
+
+    /// Record whether the sessions have been idle for long enough for the idle section, and
+    /// re-apply the active profile if that changed.
+    fn process_sessions_idle(&mut self, idle_for: Option<Duration>, events: Option<&Events>) {
+        let Some(section) = &self.config.idle else {
+            return;
+        };
+        let idle = idle_for.is_some_and(|d| d >= Duration::from_secs(section.after_s));
+        if idle == self.idle {
+            return;
+        }
+        self.idle = idle;
+        if idle {
+            log::info!("Idle for {} s, applying the idle section.", section.after_s);
+        } else {
+            log::info!("No longer idle, restoring the active profile.");
+        }
+        self.reapply(events);
+    }
+
+    /// Record whether the screen is locked, and re-apply the active profile if that changed
+    /// and the config has a screen lock section.
+    fn process_screen_lock_changed(&mut self, locked: bool, events: Option<&Events>) {
+        if locked == self.locked {
+            return;
+        }
+        self.locked = locked;
+        let Some(screen_lock) = &self.config.screen_lock else {
+            return;
+        };
+        if locked {
+            log::info!("The screen is locked, applying {}.", screen_lock.profile);
+        } else {
+            log::info!("The screen is unlocked, restoring the active profile.");
+        }
+        self.reapply(events);
+    }
+
+    /// Record the locks taken by applications, and re-apply if the most recent one changed.
+    fn process_inhibitors_changed(&mut self, inhibitors: Vec<Inhibitor>, events: Option<&Events>) {
+        let profile_of = |i: &[Inhibitor]| i.last().map(|i| i.profile.clone());
+        let changed = profile_of(&inhibitors) != profile_of(&self.inhibitors);
+        self.inhibitors = inhibitors;
+        if !changed {
+            return;
+        }
+        match self.inhibitors.last() {
+            Some(i) => log::info!("Locked to {} by {}: {}", i.profile, i.owner, i.reason),
+            None => log::info!("No longer inhibited, restoring the active profile."),
+        }
+        self.reapply(events);
+    }
+
+    /// Check the temperature sensors against the thermal rules, and re-apply the active
+    /// profile if any rule starts or stops applying. A rule applies from its `above`
+    /// temperature until the temperature drops `hysteresis` degrees below it.
+    fn process_thermal_tick(&mut self, events: Option<&Events>) {
+        let hwmon_path = path::Path::new(hwmon::HWMON_PATH);
+        let mut changed = false;
+        for (rule, active) in self
+            .config
+            .thermal_rule
+            .iter()
+            .zip(&mut self.thermal_active)
+        {
+            let Some(temp) = hwmon::read_temperature(hwmon_path, &rule.sensor) else {
+                log::debug!("Could not read temperature of {}.", rule.sensor);
+                continue;
+            };
+            let threshold = if *active {
+                rule.above - rule.hysteresis
+            } else {
+                rule.above
+            };
+            if (temp >= threshold) == *active {
+                continue;
+            }
+            *active = !*active;
+            changed = true;
+            if *active {
+                log::info!("{} at {temp}°C, applying its thermal rule.", rule.sensor);
+            } else {
+                log::info!("{} at {temp}°C, releasing its thermal rule.", rule.sensor);
+            }
+        }
+        if changed {
+            self.reapply(events);
+        }
+    }
+
+    /// Record the profile selected through the daemon interface, and re-apply the active
+    /// profile with it layered on top. Names without a `[profile]` section are rejected.
+    fn process_profile_selected(&mut self, name: Option<String>, events: Option<&Events>) {
+        match &name {
+            Some(name) if !self.config.profile.contains_key(name) => {
+                log::error!("Cannot select profile {name}, the config has no section for it.");
+                return;
+            }
+            Some(name) => log::info!("Selected profile {name}."),
+            None => log::info!("Cleared the selected profile."),
+        }
+        self.selected_profile = name;
+        self.reapply(events);
+    }
+
+    /// Name and custom profile to apply for a resolved profile: the selected profile if
+    /// there is one, so that its knobs take precedence over the built-in mapping of the
+    /// profile power-profiles-daemon reports, and the resolved ones otherwise.
+    fn layer_selected<'a>(
+        &self,
+        config: &'a Config,
+        name: &'a str,
+        custom: Option<&'a CustomProfile>,
+    ) -> (&'a str, Option<&'a CustomProfile>) {
+        let selected = self
+            .selected_profile
+            .as_ref()
+            .and_then(|s| config.profile.get_key_value(s));
+        match selected {
+            Some((selected, c)) => (selected, Some(c)),
+            None => (name, custom),
+        }
+    }
+
+    /// Profile of the most recent lock taken by an application, resolved like a reported
+    /// one.
+    fn inhibited_profile<'a>(
+        &self,
+        config: &'a Config,
+    ) -> Option<(PPDPowerProfile, Option<&'a CustomProfile>)> {
+        let inhibitor = self.inhibitors.last()?;
+        match config.resolve_profile(&inhibitor.profile) {
+            Ok(resolved) => Some(resolved),
+            Err(e) => {
+                log::warn!("Not applying the profile of {}: {e}", inhibitor.owner);
+                None
+            }
+        }
+    }
+
+    /// Profile from the `[screen_lock]` section, resolved like a reported one, while the
+    /// screen is locked.
+    fn screen_lock_profile<'a>(
+        &self,
+        config: &'a Config,
+    ) -> Option<(PPDPowerProfile, Option<&'a CustomProfile>)> {
+        let screen_lock = config.screen_lock.as_ref().filter(|_| self.locked)?;
+        match config.resolve_profile(&screen_lock.profile) {
+            Ok(resolved) => Some(resolved),
+            Err(e) => {
+                log::warn!("Not applying the screen lock profile: {e}");
+                None
+            }
+        }
+    }
+
+    /// Profile from the `[gamemode]` section, resolved like a reported one, while a game
+    /// has GameMode requested.
+    fn gaming_profile<'a>(
+        &self,
+        config: &'a Config,
+    ) -> Option<(PPDPowerProfile, Option<&'a CustomProfile>)> {
+        let gamemode = config.gamemode.as_ref().filter(|_| self.gaming)?;
+        match config.resolve_profile(&gamemode.profile) {
+            Ok(resolved) => Some(resolved),
+            Err(e) => {
+                log::warn!("Not applying the GameMode profile: {e}");
+                None
+            }
+        }
+    }
+
+    /// Whether the laptop counts as docked with the current config and power source.
+    pub fn detect_docked(&self) -> bool {
+        let Some(docked) = &self.config.docked else {
+            return false;
+        };
+        self.power_source == Some(PowerSource::Ac)
+            && ((docked.external_display
+                && drm::external_display_connected(path::Path::new(drm::DRM_PATH)))
+                || docked.match_path.as_ref().is_some_and(|p| p.exists()))
+    }
+
+    /// Detect whether the laptop is docked, and return whether that changed.
+    fn update_docked(&mut self) -> bool {
+        let docked = self.detect_docked();
+        if docked == self.docked {
+            return false;
+        }
+        log::info!("{}", if docked { "Docked." } else { "Undocked." });
+        self.docked = docked;
+        true
+    }
+
+    /// Check the clock against the schedules, and re-apply the active profile if any
+    /// schedule starts or stops applying.
+    fn process_schedule_tick(&mut self, events: Option<&Events>) {
+        let time = local_time_of_day();
+        let active = self.config.schedules_at(time);
+        if active == self.schedule_active {
+            return;
+        }
+        for (schedule, (now, before)) in self
+            .config
+            .schedule
+            .iter()
+            .zip(active.iter().zip(&self.schedule_active))
+        {
+            if now != before {
+                let action = if *now { "Entering" } else { "Leaving" };
+                log::info!(
+                    "{action} schedule {}-{} at {time}.",
+                    schedule.from,
+                    schedule.to
+                );
+            }
+        }
+        self.schedule_active = active;
+        self.reapply(events);
+    }
+
+    /// Schedules that currently apply, in config order.
+    fn active_schedules(&self) -> Vec<&Schedule> {
+        self.config
+            .schedule
+            .iter()
+            .zip(&self.schedule_active)
+            .filter(|(_, active)| **active)
+            .map(|(schedule, _)| schedule)
+            .collect()
+    }
+
+    /// Thermal rules that currently apply, in config order.
+    fn active_thermal_rules(&self) -> Vec<&ThermalRule> {
+        self.config
+            .thermal_rule
+            .iter()
+            .zip(&self.thermal_active)
+            .filter(|(_, active)| **active)
+            .map(|(rule, _)| rule)
+            .collect()
+    }
+
+    /// What values of the given policy are looked up for under the current conditions,
+    /// on the given power source and refined by a custom profile if given.
+    fn scope<'a>(
+        &'a self,
+        policy: &'a str,
+        cpus: &'a [u32],
+        power_source: Option<PowerSource>,
+        custom: Option<&'a CustomProfile>,
+    ) -> Scope<'a> {
+        Scope {
+            policy,
+            cpus,
+            power_source,
+            custom,
+            user_override: self.user_override.as_ref(),
+            battery_level: self.battery_level,
+            thermal_rules: self.active_thermal_rules(),
+            schedules: self.active_schedules(),
+            docked: self.docked,
+            held: self.held,
+            idle: self.idle,
+            degraded: self.degraded,
+            battery_warning: self.battery_warning,
+        }
+    }
+
+    /// Warn about thermal rules whose sensor cannot be read, since they never apply.
+    pub fn warn_missing_sensors(&self) {
+        let hwmon_path = path::Path::new(hwmon::HWMON_PATH);
+        for rule in &self.config.thermal_rule {
+            if hwmon::read_temperature(hwmon_path, &rule.sensor).is_none() {
+                log::warn!(
+                    "Could not read temperature sensor {} of a thermal rule.",
+                    rule.sensor
+                );
+            }
+        }
+    }
+
+    /// Process a property change value, logging failures instead of returning them.
+    fn try_process_active_profile_changed(&mut self, value: &str, events: Option<&Events>) {
+        if let Err(e) = self.process_active_profile_changed(value, events) {
+            log::error!("Failed to process ActiveProfile change ({value}): {e}.");
+            let body = format!("Could not apply profile {value}: {e}");
+            self.notifier.notify(&self.config.notify, &body);
+        }
+    }
+
+    /// Process the provided property change value and write EPPs from it.
+    fn process_active_profile_changed(
+        &mut self,
+        value: &str,
+        events: Option<&Events>,
+    ) -> Result<(), zbus::Error> {
+        if let Some(events) = events {
+            events.profile_received(value);
+        }
+        log::info!("ActiveProfile changed: {value}");
+        let config = Arc::clone(&self.config);
+        let (profile, custom) = config
+            .resolve_profile(value)
+            .map_err(zbus::Error::Failure)?;
+        let source = self.power_source.map(|s| s.to_string()).unwrap_or_default();
+        let old_profile = self.active_profile.clone().unwrap_or_default();
+        let env = [
+            ("PSTATE_UPDATE_OLD_PROFILE", old_profile.as_str()),
+            ("PSTATE_UPDATE_NEW_PROFILE", value),
+            ("PSTATE_UPDATE_POWER_SOURCE", &source),
+        ];
+        let (name, custom) = self.layer_selected(&config, value, custom);
+        let before = config.exec_before_for(&profile, custom);
+        hooks::run_hooks("exec_before", before, &env, self.dry_run);
+        let applied = self.apply(name, &profile, custom, events);
+        if config.notify.profile_change && !self.dry_run {
+            notify::notify_desktops(
+                "Power profile changed",
+                &format!(
+                    "Switched to {name}: EPP={}, governor={}",
+                    or_unchanged(&applied.epp),
+                    or_unchanged(&applied.governor)
+                ),
+                config.notify.urgency,
+            );
+        }
+        let after = config.exec_after_for(&profile, custom);
+        hooks::run_hooks("exec_after", after, &env, self.dry_run);
+        self.active_profile = Some(value.to_string());
+        Ok(())
+    }
+
+    /// Write the values mapped to the given built-in profile to all cores.
+    pub fn apply_profile(&mut self, profile: &PPDPowerProfile, events: Option<&Events>) {
+        self.apply(&profile.to_string(), profile, None, events);
+    }
+
+    /// Write the values mapped to a profile name reported by power-profiles-daemon to
+    /// all cores. Fails if the config has no mapping for the name.
+    fn apply_named(&mut self, name: &str, events: Option<&Events>) -> Result<(), String> {
+        let config = Arc::clone(&self.config);
+        let (profile, custom) = config.resolve_profile(name)?;
+        let (name, custom) = self.layer_selected(&config, name, custom);
+        self.apply(name, &profile, custom, events);
+        Ok(())
+    }
+
+    fn apply(
+        &mut self,
+        name: &str,
+        profile: &PPDPowerProfile,
+        custom: Option<&CustomProfile>,
+        events: Option<&Events>,
+    ) -> AppliedValues {
+        let config = Arc::clone(&self.config);
+        let inhibited = self.inhibited_profile(&config);
+        let locked = self.screen_lock_profile(&config);
+        let gaming = self.gaming_profile(&config);
+        let (profile, custom) = if config.forces_power_saver(self.battery_warning) {
+            (&PPDPowerProfile::PowerSaver, None)
+        } else if let Some((profile, custom)) = &inhibited {
+            (profile, *custom)
+        } else if let Some((profile, custom)) = &locked {
+            (profile, *custom)
+        } else if let Some((profile, custom)) = &gaming {
+            (profile, *custom)
+        } else {
+            (profile, custom)
+        };
+        let mut plan = self.plan_for(profile, custom, self.power_source);
+        match self.power_source {
+            Some(source) => log::info!("Applying {name} on {source} with {} writes.", plan.len()),
+            None => log::info!("Applying {name} with {} writes.", plan.len()),
+        }
+        // Switching the amd_pstate mode, SMT or CPUs changes which policy files exist, so
+        // the rest of the profile is planned again against the new ones after each. SMT
+        // decides which CPUs can come online, so they are switched one kind at a time. Knobs
+        // already switched are left out of the new plan, so failed writes are not retried.
+        let mut switched = Vec::new();
+        let mut attempted = 0;
+        let mut done = Vec::new();
+        for _ in 0..TOPOLOGY_KNOBS.len() {
+            let Some(first) = plan.first().filter(|_| !self.dry_run) else {
+                break;
+            };
+            if !TOPOLOGY_KNOBS.contains(&first.knob.as_str()) {
+                break;
+            }
+            let knob = first.knob.clone();
+            let count = plan.iter().take_while(|w| w.knob == knob).count();
+            let batch: Vec<_> = plan.drain(..count).collect();
+            attempted += batch.len();
+            let ok = apply_plan(&batch, self.dry_run, &self.config.retry, events);
+            if ok.is_empty() {
+                break;
+            }
+            switched.extend(ok.into_iter().cloned());
+            done.push(knob);
+            self.refresh_policy_files();
+            plan = self.plan_for(profile, custom, self.power_source);
+            plan.retain(|w| !done.contains(&w.knob));
+        }
+        let mut written: Vec<_> = switched.iter().collect();
+        written.extend(apply_plan(&plan, self.dry_run, &self.config.retry, events));
+        if !self.dry_run {
+            self.track_offlined_cpus(&written);
+        }
+        let total = attempted + plan.len();
+        let failures = total - written.len();
+        if failures > 0 {
+            let body = format!(
+                "Failed to write {failures} of {total} values for profile {name}. See the \
+                 journal for details."
+            );
+            self.notifier.notify(&self.config.notify, &body);
+        }
+        let applied = AppliedValues {
+            epp: distinct_values(&written, EPP_KNOB),
+            governor: distinct_values(&written, GOVERNOR_KNOB),
+        };
+        systemd::notify(&format!(
+            "STATUS=Applied {name}: EPP={}, governor={}",
+            or_unchanged(&applied.epp),
+            or_unchanged(&applied.governor)
+        ));
+        #[cfg(feature = "mqtt")]
+        if let Some(mqtt) = &self.mqtt {
+            mqtt.profile_applied(name, &applied, self.power_source);
+        }
+        if let Some(events) = events {
+            events.profile_applied(name, &written, failures as u32);
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            events.update_state(|state| {
+                state.active_profile = name.to_string();
+                state.applied_epp = applied.epp.clone();
+                state.applied_governor = applied.governor.clone();
+                state.last_apply_timestamp = timestamp;
+                state.managed_core_count = self.epp_core_files.len() as u32;
+            });
+        }
+        applied
+    }
+
+    /// Remember which CPUs the given writes took offline or brought back online.
+    fn track_offlined_cpus(&mut self, written: &[&PlannedWrite]) {
+        let offlined = &mut self.offlined_cpus;
+        for write in written.iter().filter(|w| w.knob == OFFLINE_CPUS_KNOB) {
+            let Some(cpu) = online_cpu(write) else {
+                continue;
+            };
+            offlined.retain(|c| *c != cpu);
+            if write.value == "0" {
+                offlined.push(cpu);
+            }
+        }
+    }
+
+    /// Find the policy files again after switching the amd_pstate mode, SMT or CPUs. The
+    /// change is done by the time the write returns, but files may take a moment to
+    /// appear.
+    fn refresh_policy_files(&mut self) {
+        let cpufreq_path = path::Path::new(sysfs::CPUFREQ_PATH);
+        for _ in 0..TOPOLOGY_SETTLE_ATTEMPTS {
+            match sysfs::find_managed_files(cpufreq_path, &self.config.exclude_cpus.0) {
+                Ok((epp_files, governor_files)) if !governor_files.is_empty() => {
+                    self.epp_core_files = epp_files;
+                    self.governor_core_files = governor_files;
+                    return;
+                }
+                Ok(_) => thread::sleep(TOPOLOGY_SETTLE_DELAY),
+                Err(e) => {
+                    log::error!("Failed to find cpufreq files after a topology change: {e}");
+                    return;
+                }
+            }
+        }
+        log::error!("No active cpufreq policies left after a topology change.");
+    }
+
+    /// List every sysfs write needed to apply the given profile on the current power
+    /// source, in the order they should be made.
+    pub fn plan(&self, profile: &PPDPowerProfile) -> Vec<PlannedWrite> {
+        self.plan_for(profile, None, self.power_source)
+    }
+
+    /// List every sysfs write needed to apply the given profile, refined by a custom
+    /// profile if given, on the given power source. Knobs mapped to `keep` are left out.
+    /// Changes of the amd_pstate mode, SMT and online CPUs come first, since they change
+    /// which policy files exist.
+    /// The system-wide platform profile, vendor knobs, cpuidle governor, idle states,
+    /// power limits, intel_pstate knobs, GPU settings, sysctls and NVMe latency are
+    /// written next. Then come the scaling governors, since the governor may restrict
+    /// which EPPs are accepted, followed by boost, frequency limits, EPPs and finally EPBs.
+    fn plan_for(
+        &self,
+        profile: &PPDPowerProfile,
+        custom: Option<&CustomProfile>,
+        power_source: Option<PowerSource>,
+    ) -> Vec<PlannedWrite> {
+        let (epp_files, governor_files) = (&self.epp_core_files, &self.governor_core_files);
+        let governors = governor_files.iter().filter_map(|f| {
+            let (policy, cpus) = policy_of(f);
+            let scope = self.scope(&policy, &cpus, power_source, custom);
+            match self.config.governor_for(&scope, profile) {
+                ScalingGovernor::Keep => None,
+                gov => Some(PlannedWrite::new(GOVERNOR_KNOB, f, &gov.to_string())),
+            }
+        });
+        let epps = epp_files.iter().filter_map(|f| {
+            let (policy, cpus) = policy_of(f);
+            let scope = self.scope(&policy, &cpus, power_source, custom);
+            match self.config.epp_for(&scope, profile) {
+                EnergyPerformancePreference::Keep => None,
+                epp => Some(PlannedWrite::new(EPP_KNOB, f, &epp.to_string())),
+            }
+        });
+        let epbs = self
+            .config
+            .epb_for(profile, custom)
+            .map(|epb| self.plan_epb(epb))
+            .unwrap_or_default();
+        let boost = self
+            .config
+            .boost_for(profile, custom)
+            .map(|on| plan_boost(governor_files, on))
+            .unwrap_or_default();
+        let limits = governor_files
+            .iter()
+            .flat_map(|f| self.plan_freq_limits(f, profile, custom, power_source));
+        let cpuidle = self
+            .config
+            .cpuidle_governor_for(profile, custom)
+            .map(|gov| {
+                let file = path::Path::new(sysfs::CPUIDLE_PATH).join("current_governor");
+                PlannedWrite::new(CPUIDLE_GOVERNOR_KNOB, &file, gov)
+            });
+        let vendor_knobs = self.config.vendor_knob.iter().filter_map(|knob| {
+            let value = knob.values.for_profile(profile).as_ref()?;
+            let file = &knob.path.0;
+            if !file.exists() {
+                log::warn!("Vendor knob {file:?} does not exist, skipping it.");
+                return None;
+            }
+            Some(PlannedWrite::new(VENDOR_KNOB, file, &value.0))
+        });
+        let max_cstate = self
+            .config
+            .max_cstate_for(profile, custom)
+            .map(|max| self.plan_max_cstate(max))
+            .unwrap_or_default();
+        let platform_profile = self
+            .config
+            .platform_profile_for(profile, custom)
+            .filter(|_| !self.ppd_owns_platform_profile)
+            .map(|p| {
+                let file = path::Path::new(sysfs::PLATFORM_PROFILE_PATH);
+                PlannedWrite::new(PLATFORM_PROFILE_KNOB, file, p)
+            });
+        let rapl = self
+            .config
+            .rapl_for(profile, custom)
+            .map(plan_power_limits)
+            .unwrap_or_default();
+        let intel_pstate = self
+            .config
+            .intel_pstate_for(profile, custom)
+            .map(plan_intel_pstate)
+            .unwrap_or_default();
+        let gpu = self
+            .config
+            .gpu_level_for(profile, custom)
+            .map(|level| plan_gpu_level(level, &self.config.gpu_target))
+            .unwrap_or_default();
+        let gpu_power_cap = self
+            .config
+            .gpu_power_cap_for(profile, custom)
+            .map(|watts| plan_gpu_power_cap(watts, &self.config.gpu_target))
+            .unwrap_or_default();
+        let gpu_power_profile = self
+            .config
+            .gpu_power_profile_for(profile, custom)
+            .map(|name| plan_gpu_power_profile(name, &self.config.gpu_target))
+            .unwrap_or_default();
+        let i915 = self
+            .config
+            .i915_for(profile, custom)
+            .map(plan_i915_caps)
+            .unwrap_or_default();
+        let sysctls = self
+            .config
+            .sysctls_for(profile, custom)
+            .map(plan_sysctls)
+            .unwrap_or_default();
+        let nvme_latency = self
+            .config
+            .nvme_latency_us_for(profile, custom)
+            .map(plan_nvme_latency)
+            .unwrap_or_default();
+        let amd_pstate_mode = self
+            .config
+            .amd_pstate_mode_for(profile, custom)
+            .map(plan_amd_pstate_mode)
+            .unwrap_or_default();
+        let smt = self
+            .config
+            .smt_for(profile, custom)
+            .map(plan_smt)
+            .unwrap_or_default();
+        let cpu_online = self
+            .config
+            .offline_cpus_for(profile, custom)
+            .map(|offline| {
+                plan_cpu_online(offline, &self.config.exclude_cpus.0, &self.offlined_cpus)
+            })
+            .unwrap_or_default();
+        amd_pstate_mode
+            .into_iter()
+            .chain(smt)
+            .chain(cpu_online)
+            .chain(platform_profile)
+            .chain(vendor_knobs)
+            .chain(cpuidle)
+            .chain(max_cstate)
+            .chain(rapl)
+            .chain(intel_pstate)
+            .chain(gpu)
+            .chain(gpu_power_profile)
+            .chain(gpu_power_cap)
+            .chain(i915)
+            .chain(sysctls)
+            .chain(nvme_latency)
+            .chain(governors)
+            .chain(boost)
+            .chain(limits)
+            .chain(epps)
+            .chain(epbs)
+            .collect()
+    }
+
+    /// Idle state writes that disable every state deeper than the named one and enable
+    /// the others, on every CPU that is not excluded. `all` enables every state.
+    fn plan_max_cstate(&self, max: &str) -> Vec<PlannedWrite> {
+        let cpu_path = path::Path::new(sysfs::CPU_PATH);
+        let cpus = sysfs::find_idle_states(cpu_path, &self.config.exclude_cpus.0);
+        let mut plan = Vec::new();
+        for (_, states) in &cpus {
+            let allowed = match states.iter().position(|(name, _)| name == max) {
+                Some(index) => index,
+                None if max == sysfs::ALL_IDLE_STATES => states.len(),
+                None => continue,
+            };
+            for (index, (_, dir)) in states.iter().enumerate() {
+                let disable = if index > allowed { "1" } else { "0" };
+                plan.push(PlannedWrite::new(
+                    MAX_CSTATE_KNOB,
+                    &dir.join("disable"),
+                    disable,
+                ));
+            }
+        }
+        if plan.is_empty() {
+            log::warn!("No CPU has an idle state named {max}, skipping the C-state limit.");
+        }
+        plan
+    }
+
+    /// EPB writes for every CPU that exposes `energy_perf_bias` and is not excluded.
+    fn plan_epb(&self, epb: EnergyPerfBias) -> Vec<PlannedWrite> {
+        let cpu_path = path::Path::new(sysfs::CPU_PATH);
+        let files = sysfs::find_epb_files(cpu_path, &self.config.exclude_cpus.0);
+        if files.is_empty() {
+            log::warn!("An EPB is configured, but no CPU exposes energy_perf_bias.");
+        }
+        files
+            .iter()
+            .map(|f| PlannedWrite::new(EPB_KNOB, f, &epb.to_string()))
+            .collect()
+    }
+
+    /// Frequency limit writes for the policy of the given governor file. The upper limit is
+    /// written before the lower one, so that raising both never puts the lower limit
+    /// above the current upper limit.
+    fn plan_freq_limits(
+        &self,
+        governor_file: &path::Path,
+        profile: &PPDPowerProfile,
+        custom: Option<&CustomProfile>,
+        power_source: Option<PowerSource>,
+    ) -> Vec<PlannedWrite> {
+        let Some(dir) = governor_file.parent() else {
+            return Vec::new();
+        };
+        let (policy, cpus) = policy_of(governor_file);
+        let scope = self.scope(&policy, &cpus, power_source, custom);
+        let read_khz = |attr: &str| sysfs::read_value(&dir.join(attr)).ok()?.parse().ok();
+        let (cpuinfo_min, cpuinfo_max) =
+            (read_khz("cpuinfo_min_freq"), read_khz("cpuinfo_max_freq"));
+        // A limit that is set for some profiles is reset to the hardware limit for the
+        // others, so that it does not stick around after switching away from them.
+        let min_default = cpuinfo_min.filter(|_| self.config.manages_min_freq());
+        let max_default = cpuinfo_max.filter(|_| self.config.manages_max_freq());
+        let limits = [
+            (
+                MAX_FREQ_KNOB,
+                "scaling_max_freq",
+                self.config
+                    .max_freq_for(&scope, profile)
+                    .or(max_default.map(Frequency::KHz)),
+            ),
+            (
+                MIN_FREQ_KNOB,
+                "scaling_min_freq",
+                self.config
+                    .min_freq_for(&scope, profile)
+                    .or(min_default.map(Frequency::KHz)),
+            ),
+        ];
+        let mut plan = Vec::new();
+        for (knob, attr, freq) in limits {
+            let Some(freq) = freq else {
+                continue;
+            };
+            match freq.to_khz(cpuinfo_max) {
+                Some(khz) => plan.push(PlannedWrite::new(knob, &dir.join(attr), &khz.to_string())),
+                None => log::warn!(
+                    "Could not read cpuinfo_max_freq of {policy}, skipping {knob} {freq}."
+                ),
+            }
+        }
+        plan
+    }
+
+    /// Capture the current value of every file any profile, built-in or custom, would
+    /// write on any power source, in plan order.
+    pub fn snapshot(&self) -> Vec<PlannedWrite> {
+        let mut entries: Vec<PlannedWrite> = Vec::new();
+        let builtin = PPDPowerProfile::ALL.iter().map(|p| (p, None));
+        let custom = self.config.profile.values().map(|c| (&c.base, Some(c)));
+        let plans = builtin.chain(custom).flat_map(|(profile, custom)| {
+            PowerSource::ALL
+                .iter()
+                .map(move |source| self.plan_for(profile, custom, Some(*source)))
+        });
+        for plan in plans {
+            for w in plan {
+                if entries.iter().any(|e| e.path == w.path) {
+                    continue;
+                }
+                match sysfs::read_value(&w.path) {
+                    Ok(value) => entries.push(PlannedWrite { value, ..w }),
+                    Err(e) => log::warn!("Could not read {:?}, skipping it: {e}", w.path),
+                }
+            }
+        }
+        entries
+    }
+
+    /// Warn about per-policy overrides that do not apply cleanly to the managed policies,
+    /// which is most likely a mistake in the config.
+    pub fn warn_unmatched_overrides(&self) {
+        let policies: Vec<_> = self
+            .governor_core_files
+            .iter()
+            .map(|f| policy_of(f))
+            .collect();
+        for problem in self.config.check_overrides(&policies) {
+            log::warn!("{problem}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zbus::zvariant::{OwnedValue, Value};
+
+    fn hold(entries: &[(&str, Value)]) -> HashMap<String, OwnedValue> {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone().into()))
+            .collect()
+    }
+
+    #[test]
+    fn hold_applications_lists_application_ids() {
+        let holds = [
+            hold(&[
+                ("ApplicationId", Value::from("org.gnome.Shell")),
+                ("Profile", Value::from("power-saver")),
+                ("Reason", Value::from("Low battery")),
+            ]),
+            hold(&[("Profile", Value::from("performance"))]),
+            hold(&[("ApplicationId", Value::from(42u32))]),
+        ];
+        assert_eq!(
+            hold_applications(&holds),
+            ["org.gnome.Shell", "unknown", "unknown"]
+        );
+        assert!(hold_applications(&[]).is_empty());
+    }
+}
@@ -0,0 +1,356 @@
+use std::fmt;
+use std::fs;
+use std::path;
+
+use crate::{sysfs, PPD_SERVICES};
+
+const KERNEL_CMDLINE_PATH: &str = "/proc/cmdline";
+/// systemd units of tools that also write EPP and/or governors.
+const CONFLICTING_UNITS: [&str; 3] = ["tlp.service", "auto-cpufreq.service", "tuned.service"];
+
+#[zbus::dbus_proxy(
+    interface = "org.freedesktop.systemd1.Manager",
+    default_service = "org.freedesktop.systemd1",
+    default_path = "/org/freedesktop/systemd1"
+)]
+trait SystemdManager {
+    fn get_unit(&self, name: &str) -> zbus::Result<zbus::zvariant::OwnedObjectPath>;
+}
+
+#[zbus::dbus_proxy(
+    interface = "org.freedesktop.systemd1.Unit",
+    default_service = "org.freedesktop.systemd1"
+)]
+trait SystemdUnit {
+    #[dbus_proxy(property)]
+    fn active_state(&self) -> zbus::Result<String>;
+}
+
+#[derive(serde::Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Ok,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Ok => f.pad("OK"),
+            Severity::Warning => f.pad("WARN"),
+            Severity::Error => f.pad("ERROR"),
+        }
+    }
+}
+
+/// Result of a single diagnostic check, with a hint on how to fix it if relevant.
+#[derive(serde::Serialize)]
+pub struct Finding {
+    pub check: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    pub hint: Option<String>,
+}
+
+impl Finding {
+    fn ok(check: &'static str, message: String) -> Self {
+        Finding {
+            check,
+            severity: Severity::Ok,
+            message,
+            hint: None,
+        }
+    }
+
+    fn warning(check: &'static str, message: String, hint: &str) -> Self {
+        Finding {
+            check,
+            severity: Severity::Warning,
+            message,
+            hint: Some(hint.to_string()),
+        }
+    }
+
+    fn error(check: &'static str, message: String, hint: &str) -> Self {
+        Finding {
+            check,
+            severity: Severity::Error,
+            message,
+            hint: Some(hint.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{:<5}] {}: {}", self.severity, self.check, self.message)?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\n        -> {hint}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Check which mode the amd_pstate or intel_pstate driver runs in. EPP is only available
+/// in active mode.
+fn check_driver_mode() -> Finding {
+    let check = "driver mode";
+    let intel_status = path::Path::new(sysfs::INTEL_PSTATE_PATH).join("status");
+    if intel_status.exists() {
+        return match sysfs::read_value(&intel_status) {
+            Ok(mode) if mode == "active" => {
+                Finding::ok(check, "intel_pstate is in active mode.".into())
+            }
+            Ok(mode) => Finding::error(
+                check,
+                format!("intel_pstate is in {mode} mode. EPP is only available in active mode."),
+                "Write 'active' to /sys/devices/system/cpu/intel_pstate/status or boot without \
+                 intel_pstate=passive.",
+            ),
+            Err(e) => Finding::warning(
+                check,
+                format!("Could not read {intel_status:?}: {e}"),
+                "Check the intel_pstate status manually.",
+            ),
+        };
+    }
+    match sysfs::read_value(path::Path::new(sysfs::AMD_PSTATE_STATUS_PATH)) {
+        Ok(mode) if mode == "active" => Finding::ok(check, "amd_pstate is in active mode.".into()),
+        Ok(mode) => Finding::error(
+            check,
+            format!("amd_pstate is in {mode} mode. EPP is only available in active mode."),
+            "Write 'active' to /sys/devices/system/cpu/amd_pstate/status or boot with \
+             amd_pstate=active.",
+        ),
+        Err(_) => Finding::warning(
+            check,
+            format!(
+                "{} does not exist. amd_pstate is not loaded.",
+                sysfs::AMD_PSTATE_STATUS_PATH
+            ),
+            "Boot with amd_pstate=active on a supported AMD CPU, or use intel_pstate on Intel.",
+        ),
+    }
+}
+
+/// Check the scaling driver reported by the first policy.
+fn check_scaling_driver(policies: &[path::PathBuf]) -> Finding {
+    let check = "scaling driver";
+    let Some(first) = policies.first() else {
+        return Finding::error(
+            check,
+            "No cpufreq policies found.".into(),
+            "Make sure a cpufreq driver is loaded for this CPU.",
+        );
+    };
+    match sysfs::read_value(&first.join("scaling_driver")) {
+        Ok(driver) if driver == "amd-pstate-epp" || driver == sysfs::INTEL_PSTATE_DRIVER => {
+            Finding::ok(check, format!("Scaling driver is {driver}."))
+        }
+        Ok(driver) => Finding::warning(
+            check,
+            format!("Scaling driver is {driver}, not amd-pstate-epp or intel_pstate."),
+            "EPP writes require amd-pstate-epp (amd_pstate=active) or intel_pstate in active mode.",
+        ),
+        Err(e) => Finding::error(
+            check,
+            format!("Could not read scaling driver: {e}"),
+            "Make sure a cpufreq driver is loaded for this CPU.",
+        ),
+    }
+}
+
+/// Check that every policy exposes an EPP file.
+fn check_epp_files(policies: &[path::PathBuf]) -> Finding {
+    let check = "EPP files";
+    let with_epp = policies
+        .iter()
+        .filter(|p| p.join("energy_performance_preference").exists())
+        .count();
+    if with_epp == 0 {
+        Finding::warning(
+            check,
+            "No policy exposes energy_performance_preference, so only governors are managed."
+                .into(),
+            "Enable amd_pstate or intel_pstate in active mode to get EPP support.",
+        )
+    } else if with_epp < policies.len() {
+        Finding::warning(
+            check,
+            format!("Only {with_epp} of {} policies expose EPP.", policies.len()),
+            "Some cores will not be managed.",
+        )
+    } else {
+        Finding::ok(check, format!("All {with_epp} policies expose EPP."))
+    }
+}
+
+/// Check whether the kernel command line contains any cpufreq driver parameters.
+fn check_kernel_cmdline() -> Finding {
+    let check = "kernel cmdline";
+    let cmdline = match sysfs::read_value(path::Path::new(KERNEL_CMDLINE_PATH)) {
+        Ok(c) => c,
+        Err(e) => {
+            return Finding::warning(
+                check,
+                format!("Could not read {KERNEL_CMDLINE_PATH}: {e}"),
+                "Check the kernel command line manually.",
+            )
+        }
+    };
+    let params: Vec<&str> = cmdline
+        .split_whitespace()
+        .filter(|p| {
+            p.starts_with("amd_pstate")
+                || p.starts_with("intel_pstate")
+                || p.starts_with("cpufreq.")
+        })
+        .collect();
+    if let Some(param) = params
+        .iter()
+        .find(|p| **p == "amd_pstate=disable" || **p == "intel_pstate=disable")
+    {
+        let driver = param.split('=').next().unwrap_or_default();
+        Finding::error(
+            check,
+            format!("{driver} is disabled on the kernel command line."),
+            &format!("Remove {param} from the kernel command line."),
+        )
+    } else if params.is_empty() {
+        Finding::ok(check, "No cpufreq driver parameters set.".into())
+    } else {
+        Finding::ok(check, format!("Driver parameters: {}", params.join(" ")))
+    }
+}
+
+/// Check that the EPP and governor files can be opened for writing.
+fn check_write_permissions(policies: &[path::PathBuf]) -> Finding {
+    let check = "write permissions";
+    let denied: Vec<String> = policies
+        .iter()
+        .flat_map(|p| {
+            [
+                p.join("energy_performance_preference"),
+                p.join("scaling_governor"),
+            ]
+        })
+        .filter(|f| f.exists())
+        .filter(|f| fs::OpenOptions::new().write(true).open(f).is_err())
+        .map(|f| f.display().to_string())
+        .collect();
+    if denied.is_empty() {
+        Finding::ok(check, "All EPP and governor files are writable.".into())
+    } else {
+        Finding::error(
+            check,
+            format!(
+                "{} files are not writable, e.g. {}.",
+                denied.len(),
+                denied[0]
+            ),
+            "Run pstate_update as root.",
+        )
+    }
+}
+
+/// Check that power-profiles-daemon owns one of its names on the system bus.
+fn check_ppd_running(conn: Option<&zbus::blocking::Connection>) -> Finding {
+    let check = "power-profiles-daemon";
+    let Some(conn) = conn else {
+        return Finding::error(
+            check,
+            "Could not connect to the system bus.".into(),
+            "Make sure dbus is running.",
+        );
+    };
+    let owned = zbus::blocking::fdo::DBusProxy::new(conn).and_then(|proxy| {
+        for (name, _) in PPD_SERVICES {
+            if proxy.name_has_owner(name.try_into()?)? {
+                return Ok(Some(name));
+            }
+        }
+        Ok(None)
+    });
+    match owned {
+        Ok(Some(name)) => Finding::ok(check, format!("{name} is available on D-Bus.")),
+        Ok(None) => Finding::error(
+            check,
+            format!(
+                "Neither {} nor {} is available on D-Bus.",
+                PPD_SERVICES[0].0, PPD_SERVICES[1].0
+            ),
+            "Start it with `systemctl start power-profiles-daemon.service`.",
+        ),
+        Err(e) => Finding::warning(
+            check,
+            format!("Could not check for power-profiles-daemon: {e}"),
+            "Make sure dbus is running.",
+        ),
+    }
+}
+
+/// Return the systemd `ActiveState` of the given unit, or `None` if it is not loaded.
+fn unit_active_state(conn: &zbus::blocking::Connection, unit: &str) -> Option<String> {
+    let manager = SystemdManagerProxyBlocking::new(conn).ok()?;
+    let path = manager.get_unit(unit).ok()?;
+    let unit = SystemdUnitProxyBlocking::builder(conn)
+        .path(path)
+        .ok()?
+        .build()
+        .ok()?;
+    unit.active_state().ok()
+}
+
+/// Check whether other power management tools that write EPP or governors are active.
+fn check_conflicting_tools(conn: Option<&zbus::blocking::Connection>) -> Finding {
+    let check = "conflicting tools";
+    let Some(conn) = conn else {
+        return Finding::warning(
+            check,
+            "Could not connect to the system bus to query systemd.".into(),
+            "Check manually that TLP, auto-cpufreq and tuned are not running.",
+        );
+    };
+    let active: Vec<&str> = CONFLICTING_UNITS
+        .iter()
+        .copied()
+        .filter(|u| unit_active_state(conn, u).as_deref() == Some("active"))
+        .collect();
+    if active.is_empty() {
+        Finding::ok(check, "No conflicting tools are active.".into())
+    } else {
+        Finding::warning(
+            check,
+            format!("Active: {}.", active.join(", ")),
+            "These tools also write EPP/governors and will fight pstate_update. Disable them \
+             with `systemctl disable --now <unit>`.",
+        )
+    }
+}
+
+/// Run all diagnostic checks.
+pub fn run_checks(cpufreq_path: &path::Path) -> Vec<Finding> {
+    let policies = sysfs::find_policy_dirs(cpufreq_path).unwrap_or_default();
+    let conn = match zbus::blocking::Connection::system() {
+        Ok(c) => Some(c),
+        Err(e) => {
+            log::debug!("Could not connect to system bus: {e}");
+            None
+        }
+    };
+    vec![
+        check_driver_mode(),
+        check_scaling_driver(&policies),
+        check_epp_files(&policies),
+        check_kernel_cmdline(),
+        check_write_permissions(&policies),
+        check_ppd_running(conn.as_ref()),
+        check_conflicting_tools(conn.as_ref()),
+    ]
+}
+
+/// Whether any of the findings is an error.
+pub fn has_errors(findings: &[Finding]) -> bool {
+    findings.iter().any(|f| f.severity == Severity::Error)
+}
@@ -0,0 +1,225 @@
+use std::fs;
+use std::path;
+
+use crate::sysfs;
+
+/// Root of the DRM class in sysfs.
+pub const DRM_PATH: &str = "/sys/class/drm";
+
+/// File selecting the amdgpu DPM performance level, relative to the device directory.
+pub const DPM_LEVEL_FILE: &str = "power_dpm_force_performance_level";
+
+/// Values accepted by `power_dpm_force_performance_level`.
+pub const DPM_LEVELS: [&str; 8] = [
+    "auto",
+    "low",
+    "high",
+    "manual",
+    "profile_standard",
+    "profile_min_sclk",
+    "profile_min_mclk",
+    "profile_peak",
+];
+
+/// Table of amdgpu power profile modes, relative to the device directory. A mode is
+/// selected by writing its index.
+pub const POWER_PROFILE_MODE_FILE: &str = "pp_power_profile_mode";
+
+/// Power cap of a GPU in microwatts and the range it accepts, relative to its hwmon
+/// directory.
+pub const POWER_CAP_FILE: &str = "power1_cap";
+pub const POWER_CAP_MIN_FILE: &str = "power1_cap_min";
+pub const POWER_CAP_MAX_FILE: &str = "power1_cap_max";
+
+/// Frequency caps of i915 GPUs in MHz, relative to the card directory.
+pub const I915_MIN_FREQ_FILE: &str = "gt_min_freq_mhz";
+pub const I915_MAX_FREQ_FILE: &str = "gt_max_freq_mhz";
+pub const I915_BOOST_FREQ_FILE: &str = "gt_boost_freq_mhz";
+
+/// Lowest and highest frequencies of i915 GPUs in MHz, relative to the card directory.
+pub const I915_HW_MIN_FREQ_FILE: &str = "gt_RPn_freq_mhz";
+pub const I915_HW_MAX_FREQ_FILE: &str = "gt_RP0_freq_mhz";
+
+/// Connector types built into the laptop itself, which do not count as external displays.
+const INTERNAL_CONNECTORS: [&str; 3] = ["eDP", "LVDS", "DSI"];
+
+/// Whether any external display is connected, judging by the status of the DRM
+/// connectors like `card0-DP-1` that are not built-in panels.
+pub fn external_display_connected(drm_path: &path::Path) -> bool {
+    let Ok(entries) = drm_path.read_dir() else {
+        return false;
+    };
+    entries.filter_map(|e| e.ok()).any(|e| {
+        let name = e.file_name().to_string_lossy().into_owned();
+        let Some((_, connector)) = name.split_once('-') else {
+            return false;
+        };
+        !INTERNAL_CONNECTORS.iter().any(|c| connector.starts_with(c))
+            && sysfs::read_value(&e.path().join("status")).is_ok_and(|s| s == "connected")
+    })
+}
+
+/// Card directories like `/sys/class/drm/card0`, leaving out connectors like
+/// `card0-eDP-1`.
+fn find_cards(drm_path: &path::Path) -> Vec<path::PathBuf> {
+    let Ok(entries) = drm_path.read_dir() else {
+        return Vec::new();
+    };
+    let mut cards: Vec<_> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.strip_prefix("card"))
+                .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+        })
+        .collect();
+    cards.sort();
+    cards
+}
+
+/// Find the card directories of i915 GPUs with frequency caps, e.g. `/sys/class/drm/card1`.
+pub fn find_i915_cards(drm_path: &path::Path) -> Vec<path::PathBuf> {
+    find_cards(drm_path)
+        .into_iter()
+        .filter(|c| c.join(I915_MAX_FREQ_FILE).exists())
+        .collect()
+}
+
+/// Power profile modes of an amdgpu device as index and name, e.g. `(2, "POWER_SAVING")`.
+pub fn read_power_profile_modes(device: &path::Path) -> Vec<(u32, String)> {
+    fs::read_to_string(device.join(POWER_PROFILE_MODE_FILE))
+        .map(|table| parse_power_profile_modes(&table))
+        .unwrap_or_default()
+}
+
+/// Parse a `pp_power_profile_mode` table. The layout differs between GPU generations,
+/// but every mode starts a line with its index and name, which may be followed by `*`
+/// for the active mode and `:`. Header lines and the clock settings listed below each
+/// mode are skipped.
+fn parse_power_profile_modes(table: &str) -> Vec<(u32, String)> {
+    table
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let index = fields.next()?.parse().ok()?;
+            let name = fields.next()?.trim_end_matches([':', '*']);
+            Some((index, name.to_string()))
+        })
+        .collect()
+}
+
+/// Find the hwmon directory of a GPU with a power cap, e.g.
+/// `/sys/class/drm/card0/device/hwmon/hwmon3`.
+pub fn find_power_cap_hwmon(device: &path::Path) -> Option<path::PathBuf> {
+    let mut hwmons: Vec<_> = device
+        .join("hwmon")
+        .read_dir()
+        .ok()?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|h| h.join(POWER_CAP_FILE).exists())
+        .collect();
+    hwmons.sort();
+    hwmons.into_iter().next()
+}
+
+/// Range of power caps a GPU accepts in microwatts, from its hwmon directory. Bounds that
+/// are not reported are left open.
+pub fn read_power_cap_range(hwmon: &path::Path) -> (u64, u64) {
+    let read = |file| sysfs::read_value(&hwmon.join(file)).ok()?.parse().ok();
+    (
+        read(POWER_CAP_MIN_FILE).unwrap_or(0),
+        read(POWER_CAP_MAX_FILE).unwrap_or(u64::MAX),
+    )
+}
+
+/// Whether the amdgpu device in the given device directory is integrated into the CPU.
+/// Integrated GPUs share system memory, so they report no VRAM vendor.
+pub fn is_integrated(device: &path::Path) -> bool {
+    !device.join("mem_info_vram_vendor").exists()
+}
+
+/// Find the device directories of amdgpu cards, e.g. `/sys/class/drm/card0/device`.
+/// Connectors like `card0-eDP-1` and cards driven by other drivers are left out.
+pub fn find_amdgpu_devices(drm_path: &path::Path) -> Vec<path::PathBuf> {
+    let mut devices: Vec<_> = find_cards(drm_path)
+        .into_iter()
+        .map(|p| p.join("device"))
+        .filter(|d| {
+            d.join("driver")
+                .read_link()
+                .is_ok_and(|l| l.file_name().is_some_and(|n| n == "amdgpu"))
+        })
+        .filter(|d| d.join(DPM_LEVEL_FILE).exists())
+        .collect();
+    devices.sort();
+    devices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(modes: &[(u32, String)]) -> Vec<(u32, &str)> {
+        modes.iter().map(|(i, n)| (*i, n.as_str())).collect()
+    }
+
+    #[test]
+    fn power_profile_modes_of_vega() {
+        let modes = parse_power_profile_modes(
+            "NUM        MODE_NAME BUSY_SET_POINT FPS USE_RLC_BUSY MIN_ACTIVE_LEVEL\n  \
+               0 BOOTUP_DEFAULT :             70  60          0              0\n  \
+               1 3D_FULL_SCREEN*:             70  60          1              3\n  \
+               2   POWER_SAVING :             90  60          0              0\n  \
+               3          VIDEO :             70  60          0              0\n  \
+               4             VR :             70  90          0              0\n  \
+               5        COMPUTE :             30  60          0              6\n  \
+               6         CUSTOM :              0   0          0              0\n",
+        );
+        assert_eq!(
+            names(&modes),
+            [
+                (0, "BOOTUP_DEFAULT"),
+                (1, "3D_FULL_SCREEN"),
+                (2, "POWER_SAVING"),
+                (3, "VIDEO"),
+                (4, "VR"),
+                (5, "COMPUTE"),
+                (6, "CUSTOM"),
+            ]
+        );
+    }
+
+    #[test]
+    fn power_profile_modes_of_navi() {
+        let modes = parse_power_profile_modes(
+            "PROFILE_INDEX(NAME) CLOCK_TYPE(NAME) FPS MinFreqType MinActiveFreqType \
+             MinActiveFreq BoosterFreqType BoosterFreq PD_Data_limit_c PD_Data_error_coeff \
+             PD_Data_error_rate_coeff\n \
+             0 BOOTUP_DEFAULT*:\n\
+             \x20                       0(       GFXCLK)       0       5       1       0       4     800 4587520  -65536       0\n\
+             \x20                       1(       SOCCLK)       0       5       1       0       1       0 3276800   -6553   -65536\n\
+             \x20                       2(       MEMLCK)       0       5       1       0       4     800  327680  -65536       0\n \
+             1 3D_FULL_SCREEN :\n\
+             \x20                       0(       GFXCLK)       0       5       1       0       4     650 2949120  -65536       0\n \
+             2   POWER_SAVING :\n\
+             \x20                       0(       GFXCLK)       0       5       1       0       3       0       0       0       0\n \
+             6         CUSTOM :\n\
+             \x20                       0(       GFXCLK)       0       0       0       0       0       0       0       0       0\n",
+        );
+        assert_eq!(
+            names(&modes),
+            [
+                (0, "BOOTUP_DEFAULT"),
+                (1, "3D_FULL_SCREEN"),
+                (2, "POWER_SAVING"),
+                (6, "CUSTOM"),
+            ]
+        );
+    }
+
+    #[test]
+    fn power_profile_modes_of_missing_table() {
+        assert!(read_power_profile_modes(path::Path::new("/nonexistent")).is_empty());
+    }
+}
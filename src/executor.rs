@@ -0,0 +1,85 @@
+use std::future::Future;
+use std::io;
+use std::pin::pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Once};
+use std::thread;
+use std::time::Duration;
+
+use futures_util::future::{self, Either};
+
+/// Executor running the daemon's bus listeners and timers as tasks on a single thread.
+static EXECUTOR: async_executor::Executor<'static> = async_executor::Executor::new();
+
+/// Run a task on the executor until it finishes. The executor thread is started on first
+/// use. Tasks must not block, since they share the thread.
+pub fn spawn(task: impl Future<Output = ()> + Send + 'static) {
+    static STARTED: Once = Once::new();
+    STARTED.call_once(|| {
+        thread::spawn(|| async_io::block_on(EXECUTOR.run(std::future::pending::<()>())));
+    });
+    EXECUTOR.spawn(task).detach();
+}
+
+/// Wait without holding up the other tasks.
+pub async fn sleep(duration: Duration) {
+    async_io::Timer::after(duration).await;
+}
+
+/// Longest wait in milliseconds for a D-Bus call made through `call`.
+static CALL_TIMEOUT_MS: AtomicU64 = AtomicU64::new(5000);
+
+/// Set how long `call` waits for a reply, from `dbus_timeout_ms` in the config.
+pub fn set_call_timeout(timeout: Duration) {
+    CALL_TIMEOUT_MS.store(timeout.as_millis() as u64, Ordering::Relaxed);
+}
+
+/// Wait for a D-Bus call, giving up with a `TimedOut` error once the call timeout has
+/// passed, so that an unresponsive service cannot hold up the caller forever. A timeout of
+/// zero waits forever.
+pub async fn call<T>(call: impl Future<Output = zbus::Result<T>>) -> zbus::Result<T> {
+    let timeout = CALL_TIMEOUT_MS.load(Ordering::Relaxed);
+    call_within(Duration::from_millis(timeout), call).await
+}
+
+/// Wait for a D-Bus call like `call`, but with the given timeout instead of the configured
+/// one.
+pub async fn call_within<T>(
+    timeout: Duration,
+    call: impl Future<Output = zbus::Result<T>>,
+) -> zbus::Result<T> {
+    if timeout.is_zero() {
+        return call.await;
+    }
+    let timer = async_io::Timer::after(timeout);
+    match future::select(pin!(call), timer).await {
+        Either::Left((result, _)) => result,
+        Either::Right(_) => Err(zbus::Error::InputOutput(Arc::new(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("no reply within {} ms", timeout.as_millis()),
+        )))),
+    }
+}
+
+/// Make a D-Bus call from blocking code, with the timeout of `call`.
+pub fn block_on_call<T>(call: impl Future<Output = zbus::Result<T>>) -> zbus::Result<T> {
+    zbus::block_on(self::call(call))
+}
+
+/// Whether a D-Bus call failed by running into the timeout of `call`.
+pub fn timed_out(error: &zbus::Error) -> bool {
+    matches!(error, zbus::Error::InputOutput(e) if e.kind() == io::ErrorKind::TimedOut)
+}
+
+/// Connection to the system bus shared by the tasks that follow UPower and logind. Those
+/// do not depend on a bus session of the event loop, so one connection serves them all
+/// instead of each opening its own.
+pub async fn system_bus() -> zbus::Result<zbus::Connection> {
+    static BUS: Mutex<Option<zbus::Connection>> = Mutex::new(None);
+    if let Some(conn) = BUS.lock().unwrap().clone() {
+        return Ok(conn);
+    }
+    let conn = zbus::Connection::system().await?;
+    *BUS.lock().unwrap() = Some(conn.clone());
+    Ok(conn)
+}
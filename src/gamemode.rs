@@ -0,0 +1,23 @@
+/// Feral GameMode daemon of a desktop session.
+#[zbus::dbus_proxy(
+    interface = "com.feralinteractive.GameMode",
+    default_service = "com.feralinteractive.GameMode",
+    default_path = "/com/feralinteractive/GameMode"
+)]
+pub trait GameMode {
+    /// Number of games that currently have GameMode requested.
+    #[dbus_proxy(property)]
+    fn client_count(&self) -> zbus::Result<i32>;
+}
+
+/// Print GameMode's client count in the current session, and again on every change, one
+/// number per line. Runs until the session bus goes away or stdout is closed.
+pub fn watch() -> zbus::Result<()> {
+    let conn = zbus::blocking::Connection::session()?;
+    let proxy = GameModeProxyBlocking::new(&conn)?;
+    println!("{}", proxy.client_count()?);
+    for change in proxy.receive_client_count_changed() {
+        println!("{}", change.get()?);
+    }
+    Ok(())
+}
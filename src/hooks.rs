@@ -0,0 +1,24 @@
+use std::process;
+
+/// Run the commands configured for a hook with `sh -c`, one after another, with the given
+/// variables added to their environment. Failing commands are logged, but do not stop the
+/// remaining ones.
+pub fn run_hooks(hook: &str, commands: &[String], env: &[(&str, &str)], dry_run: bool) {
+    for command in commands {
+        if dry_run {
+            log::info!("Would run {hook} command: {command}");
+            continue;
+        }
+        log::info!("Running {hook} command: {command}");
+        let status = process::Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .envs(env.iter().copied())
+            .status();
+        match status {
+            Ok(s) if s.success() => {}
+            Ok(s) => log::warn!("{hook} command {command:?} failed with {s}."),
+            Err(e) => log::error!("Failed to run {hook} command {command:?}: {e}"),
+        }
+    }
+}
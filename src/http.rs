@@ -0,0 +1,259 @@
+use std::io::{self, BufRead, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::config::HttpConfig;
+use crate::controller::Event;
+use crate::rpc;
+use crate::PPDPowerProfile;
+
+/// How long to wait for a client to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Largest request body accepted, which is plenty for `POST /profile`.
+const MAX_BODY_LEN: usize = 4096;
+
+/// Longest request or header line accepted.
+const MAX_LINE_LEN: u64 = 8192;
+
+/// Most headers accepted in a request.
+const MAX_HEADERS: usize = 64;
+
+/// Most connections served at once.
+const MAX_CONNECTIONS: usize = 16;
+
+/// A parsed HTTP request.
+struct Request {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+/// Serve `GET /status` and `POST /profile` over HTTP on the configured address. Profile
+/// changes are forwarded to the event loop through `sender`, and need the configured
+/// bearer token.
+pub fn serve(config: &HttpConfig, sender: mpsc::Sender<Event>) -> io::Result<()> {
+    let listener = TcpListener::bind(&config.listen)?;
+    log::info!("Serving the HTTP API on {}.", config.listen);
+    if config.token.is_none() {
+        log::warn!("No token is set for the HTTP API, so it refuses to change the profile.");
+    }
+    let token = config.token.clone();
+    let limit = rpc::ConnectionLimit::new(MAX_CONNECTIONS);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let Some(slot) = limit.acquire() else {
+                        log::warn!("Too many HTTP connections, closing a new one.");
+                        continue;
+                    };
+                    let (sender, token) = (sender.clone(), token.clone());
+                    thread::spawn(move || {
+                        handle_connection(stream, token.as_deref(), &sender);
+                        drop(slot);
+                    });
+                }
+                Err(e) => log::warn!("Failed to accept HTTP connection: {e}"),
+            }
+        }
+    });
+    Ok(())
+}
+
+fn handle_connection(mut stream: TcpStream, token: Option<&str>, sender: &mpsc::Sender<Event>) {
+    let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+    let (status, body) = match read_request(&stream) {
+        Ok(request) => route(&request, token, sender),
+        Err(e) => (400, json!({"error": e.to_string()})),
+    };
+    let body = body.to_string();
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    };
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    );
+}
+
+/// Read the request line, the headers that matter here, and the body.
+fn read_request(stream: &TcpStream) -> io::Result<Request> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+    let mut reader = io::BufReader::new(stream);
+    let mut line = String::new();
+    read_line(&mut reader, &mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(invalid("malformed request line"));
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+    let mut authorization = None;
+    let mut content_length = 0;
+    for headers in 0.. {
+        line.clear();
+        if read_line(&mut reader, &mut line)? == 0 {
+            return Err(invalid("unexpected end of headers"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if headers == MAX_HEADERS {
+            return Err(invalid("too many headers"));
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            return Err(invalid("malformed header"));
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value
+                .parse()
+                .map_err(|_| invalid("invalid Content-Length"))?;
+        } else if name.eq_ignore_ascii_case("authorization") {
+            authorization = Some(value.to_string());
+        }
+    }
+    if content_length > MAX_BODY_LEN {
+        return Err(invalid("request body too large"));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(Request {
+        method,
+        path,
+        authorization,
+        body,
+    })
+}
+
+/// Read a line of at most `MAX_LINE_LEN` bytes, returning its length.
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> io::Result<usize> {
+    let len = reader.take(MAX_LINE_LEN).read_line(line)?;
+    if len as u64 == MAX_LINE_LEN && !line.ends_with('\n') {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "line too long"));
+    }
+    Ok(len)
+}
+
+/// Whether the Authorization header carries the bearer token. The comparison takes the
+/// same time wherever the header first differs, so that it does not leak the token.
+fn authorized(authorization: Option<&str>, token: &str) -> bool {
+    let expected = format!("Bearer {token}");
+    let given = authorization.unwrap_or_default();
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Handle a request, returning the status code and JSON body of the response.
+fn route(request: &Request, token: Option<&str>, sender: &mpsc::Sender<Event>) -> (u16, Value) {
+    let error = |status, message: String| (status, json!({ "error": message }));
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/status") => match rpc::request_status(sender) {
+            Ok(status) => (200, json!(status)),
+            Err(e) => error(500, e),
+        },
+        ("POST", "/profile") => {
+            let Some(token) = token else {
+                return error(
+                    403,
+                    "No token is configured for changing the profile.".into(),
+                );
+            };
+            if !authorized(request.authorization.as_deref(), token) {
+                return error(401, "Missing or wrong bearer token.".into());
+            }
+            let body: Value = match serde_json::from_slice(&request.body) {
+                Ok(b) => b,
+                Err(e) => return error(400, format!("Invalid JSON: {e}")),
+            };
+            let Some(profile) = body.get("profile").and_then(|p| p.as_str()) else {
+                return error(400, "Missing profile.".into());
+            };
+            let profile: PPDPowerProfile = match profile.parse() {
+                Ok(p) => p,
+                Err(e) => return error(400, e),
+            };
+            match sender.send(Event::ApplyRequested(profile.clone())) {
+                Ok(()) => (200, json!({ "profile": profile.to_string() })),
+                Err(_) => error(500, "The daemon is shutting down.".into()),
+            }
+        }
+        (_, "/status" | "/profile") => error(405, "Method not allowed.".into()),
+        (_, path) => error(404, format!("No such endpoint {path}.")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Shutdown;
+
+    /// Send `request` over a loopback connection and parse it on the other end.
+    fn parse(request: &[u8]) -> io::Result<Request> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        client.write_all(request).unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+        read_request(&server)
+    }
+
+    #[test]
+    fn read_request_parses_line_headers_and_body() {
+        let request = parse(
+            b"POST /profile HTTP/1.1\r\nHost: localhost\r\ncontent-length: 11\r\n\
+              AUTHORIZATION: Bearer secret\r\n\r\nperformance",
+        )
+        .unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/profile");
+        assert_eq!(request.authorization.as_deref(), Some("Bearer secret"));
+        assert_eq!(request.body, b"performance");
+
+        let request = parse(b"GET /status HTTP/1.1\n\n").unwrap();
+        assert_eq!(
+            (request.method.as_str(), request.path.as_str()),
+            ("GET", "/status")
+        );
+        assert!(request.authorization.is_none() && request.body.is_empty());
+    }
+
+    #[test]
+    fn read_request_rejects_malformed_requests() {
+        let too_many_headers = format!("GET / HTTP/1.1\r\n{}\r\n", "X: y\r\n".repeat(65));
+        let long_line = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(9000));
+        let cases: [&[u8]; 7] = [
+            b"GET\r\n\r\n",
+            b"GET / HTTP/1.1\r\nHost: localhost\r\n",
+            b"GET / HTTP/1.1\r\nno colon\r\n\r\n",
+            b"POST /profile HTTP/1.1\r\nContent-Length: many\r\n\r\n",
+            b"POST /profile HTTP/1.1\r\nContent-Length: 5000\r\n\r\n",
+            too_many_headers.as_bytes(),
+            long_line.as_bytes(),
+        ];
+        for case in cases {
+            let error = parse(case).err().expect("request should be rejected");
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{error}");
+        }
+        // A body shorter than its Content-Length.
+        assert!(parse(b"POST /profile HTTP/1.1\r\nContent-Length: 8\r\n\r\npower").is_err());
+    }
+}
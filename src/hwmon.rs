@@ -0,0 +1,37 @@
+use std::path;
+
+use crate::sysfs;
+
+/// Root of the hwmon class in sysfs.
+pub const HWMON_PATH: &str = "/sys/class/hwmon";
+
+/// Read a temperature in degrees Celsius from the sensor given as `<chip>` or
+/// `<chip>/<label>`, e.g. `k10temp/Tctl` or `coretemp/Package id 0`. The chip is matched
+/// against the hwmon `name`, and the label against the `temp*_label` files. Without a
+/// label, the chip's first temperature is used. Returns `None` if the sensor cannot be
+/// found or read.
+pub fn read_temperature(hwmon_path: &path::Path, sensor: &str) -> Option<f64> {
+    let (chip, label) = match sensor.split_once('/') {
+        Some((chip, label)) => (chip, Some(label)),
+        None => (sensor, None),
+    };
+    let mut dirs: Vec<_> = hwmon_path
+        .read_dir()
+        .ok()?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .collect();
+    dirs.sort();
+    let dir = dirs
+        .into_iter()
+        .find(|d| sysfs::read_value(&d.join("name")).is_ok_and(|n| n == chip))?;
+    let input = match label {
+        Some(label) => (1..=64)
+            .find(|i| {
+                sysfs::read_value(&dir.join(format!("temp{i}_label"))).is_ok_and(|l| l == label)
+            })
+            .map(|i| dir.join(format!("temp{i}_input")))?,
+        None => dir.join("temp1_input"),
+    };
+    let millidegrees: f64 = sysfs::read_value(&input).ok()?.parse().ok()?;
+    Some(millidegrees / 1000.0)
+}
@@ -0,0 +1,366 @@
+use std::{fs, path};
+
+use crate::config::DEFAULT_CONFIG;
+
+/// Power management tools whose configuration can be imported.
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum ImportSource {
+    AutoCpufreq,
+    Tlp,
+}
+
+impl ImportSource {
+    /// Where the tool keeps its configuration by default.
+    pub fn default_path(self) -> &'static str {
+        match self {
+            ImportSource::AutoCpufreq => "/etc/auto-cpufreq.conf",
+            ImportSource::Tlp => "/etc/tlp.conf",
+        }
+    }
+}
+
+/// Knobs read from the other tool for one power source.
+#[derive(Default)]
+struct SourceKnobs {
+    epp: Option<String>,
+    scaling_governor: Option<String>,
+    min_freq: Option<u64>,
+    max_freq: Option<u64>,
+    platform_profile: Option<String>,
+    boost: Option<bool>,
+}
+
+/// Settings read from the other tool's configuration.
+#[derive(Default)]
+struct Imported {
+    on_ac: SourceKnobs,
+    on_battery: SourceKnobs,
+    /// Battery charge thresholds as the sysfs file and the percentage to write to it.
+    charge_thresholds: Vec<(String, u8)>,
+    /// Settings that have no counterpart here, as `key = value`.
+    skipped: Vec<String>,
+}
+
+impl Imported {
+    fn source_mut(&mut self, on_battery: bool) -> &mut SourceKnobs {
+        if on_battery {
+            &mut self.on_battery
+        } else {
+            &mut self.on_ac
+        }
+    }
+
+    /// Store a setting for the given power source, or remember it as skipped.
+    fn set(&mut self, on_battery: bool, knob: &str, key: &str, value: &str) -> Result<(), String> {
+        let knobs = self.source_mut(on_battery);
+        match knob {
+            "epp" => knobs.epp = Some(value.to_string()),
+            "scaling_governor" => knobs.scaling_governor = Some(value.to_string()),
+            "platform_profile" => knobs.platform_profile = Some(value.to_string()),
+            "boost" => match value {
+                "1" => knobs.boost = Some(true),
+                "0" => knobs.boost = Some(false),
+                _ => self.skipped.push(format!("{key} = {value}")),
+            },
+            "min_freq" | "max_freq" => {
+                let khz = value
+                    .parse()
+                    .map_err(|_| format!("{key} = {value} is not a frequency in kHz."))?;
+                if knob == "min_freq" {
+                    knobs.min_freq = Some(khz);
+                } else {
+                    knobs.max_freq = Some(khz);
+                }
+            }
+            _ => self.skipped.push(format!("{key} = {value}")),
+        }
+        Ok(())
+    }
+
+    /// Store a charge threshold of the given battery, e.g. `BAT0`, where `file` is
+    /// `charge_control_start_threshold` or `charge_control_end_threshold`.
+    fn set_charge_threshold(
+        &mut self,
+        battery: &str,
+        file: &str,
+        key: &str,
+        value: &str,
+    ) -> Result<(), String> {
+        let percent = value
+            .parse()
+            .ok()
+            .filter(|p| *p <= 100)
+            .ok_or_else(|| format!("{key} = {value} is not a percentage."))?;
+        let path = format!("{POWER_SUPPLY_PATH}/{battery}/{file}");
+        self.charge_thresholds.retain(|(p, _)| *p != path);
+        self.charge_thresholds.push((path, percent));
+        Ok(())
+    }
+}
+
+/// Batteries and other power supplies in sysfs.
+const POWER_SUPPLY_PATH: &str = "/sys/class/power_supply";
+const START_THRESHOLD_FILE: &str = "charge_control_start_threshold";
+const END_THRESHOLD_FILE: &str = "charge_control_end_threshold";
+
+/// Strip a trailing comment and surrounding quotes from a value.
+fn clean_value(value: &str) -> &str {
+    let value = value.split('#').next().unwrap_or_default().trim();
+    value.trim_matches('"').trim_matches('\'').trim()
+}
+
+/// Parse an auto-cpufreq config, an INI file with `[charger]` and `[battery]` sections.
+fn parse_auto_cpufreq(text: &str, imported: &mut Imported) -> Result<(), String> {
+    let mut section = String::new();
+    let (mut thresholds, mut thresholds_enabled) = (Vec::new(), false);
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name.trim().to_string();
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("Could not parse auto-cpufreq line `{line}`."));
+        };
+        let (key, value) = (key.trim(), clean_value(value));
+        let on_battery = match section.as_str() {
+            "charger" => false,
+            "battery" => true,
+            _ => {
+                imported
+                    .skipped
+                    .push(format!("[{section}] {key} = {value}"));
+                continue;
+            }
+        };
+        let full_key = format!("[{section}] {key}");
+        let threshold = match key {
+            "start_threshold" => Some(START_THRESHOLD_FILE),
+            "stop_threshold" => Some(END_THRESHOLD_FILE),
+            _ => None,
+        };
+        if let Some(file) = threshold.filter(|_| on_battery) {
+            thresholds.push((file, full_key, value.to_string()));
+            continue;
+        }
+        if key == "enable_thresholds" && on_battery {
+            thresholds_enabled = value == "true";
+            continue;
+        }
+        // auto-cpufreq's `auto` turbo has no counterpart, and is skipped by `set`.
+        let (knob, value) = match (key, value) {
+            ("turbo", "always") => ("boost", "1"),
+            ("turbo", "never") => ("boost", "0"),
+            ("turbo", _) => ("boost", value),
+            ("governor", _) => ("scaling_governor", value),
+            ("energy_performance_preference", _) => ("epp", value),
+            ("scaling_min_freq", _) => ("min_freq", value),
+            ("scaling_max_freq", _) => ("max_freq", value),
+            ("platform_profile", _) => ("platform_profile", value),
+            _ => ("", value),
+        };
+        imported.set(on_battery, knob, &full_key, value)?;
+    }
+    // auto-cpufreq only sets the thresholds when enabled, and does so on the first battery.
+    for (file, key, value) in thresholds {
+        if thresholds_enabled {
+            imported.set_charge_threshold("BAT0", file, &key, &value)?;
+        } else {
+            imported.skipped.push(format!("{key} = {value}"));
+        }
+    }
+    Ok(())
+}
+
+/// Parse a TLP config made of `KEY=VALUE` lines, where per-source settings end in `_ON_AC`
+/// or `_ON_BAT`.
+fn parse_tlp(text: &str, imported: &mut Imported) -> Result<(), String> {
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("Could not parse TLP line `{line}`."));
+        };
+        let (key, value) = (key.trim(), clean_value(value));
+        let threshold = [
+            ("START_CHARGE_THRESH_", START_THRESHOLD_FILE),
+            ("STOP_CHARGE_THRESH_", END_THRESHOLD_FILE),
+        ]
+        .into_iter()
+        .find_map(|(prefix, file)| Some((key.strip_prefix(prefix)?, file)));
+        if let Some((battery, file)) = threshold {
+            imported.set_charge_threshold(battery, file, key, value)?;
+            continue;
+        }
+        let (base, on_battery) = match (key.strip_suffix("_ON_AC"), key.strip_suffix("_ON_BAT")) {
+            (Some(base), _) => (base, false),
+            (_, Some(base)) => (base, true),
+            _ => {
+                imported.skipped.push(format!("{key} = {value}"));
+                continue;
+            }
+        };
+        let knob = match base {
+            "CPU_SCALING_GOVERNOR" => "scaling_governor",
+            "CPU_ENERGY_PERF_POLICY" => "epp",
+            "CPU_SCALING_MIN_FREQ" => "min_freq",
+            "CPU_SCALING_MAX_FREQ" => "max_freq",
+            "CPU_BOOST" => "boost",
+            "PLATFORM_PROFILE" => "platform_profile",
+            _ => "",
+        };
+        imported.set(on_battery, knob, key, value)?;
+    }
+    Ok(())
+}
+
+/// Read a TLP config together with its drop-ins in `tlp.d` next to it, which override the
+/// main file in lexical order.
+fn read_tlp(path: &path::Path) -> Result<String, String> {
+    let mut text = fs::read_to_string(path).map_err(|e| format!("Could not read {path:?}: {e}"))?;
+    let drop_in_dir = path.with_file_name("tlp.d");
+    if let Ok(entries) = drop_in_dir.read_dir() {
+        let mut drop_ins: Vec<_> = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|e| e == "conf"))
+            .collect();
+        drop_ins.sort();
+        for drop_in in drop_ins {
+            let extra = fs::read_to_string(&drop_in)
+                .map_err(|e| format!("Could not read {drop_in:?}: {e}"))?;
+            text.push('\n');
+            text.push_str(&extra);
+        }
+    }
+    Ok(text)
+}
+
+/// Set the balanced profile's value of a knob in the given table, e.g. `[on_ac.epp]`.
+fn set_balanced(table: &mut toml_edit::Table, knob: &str, value: toml_edit::Value) {
+    let knob = table.entry(knob).or_insert(toml_edit::table());
+    if let Some(knob) = knob.as_table_mut() {
+        knob["balanced"] = toml_edit::value(value);
+    }
+}
+
+/// Add the knobs of one power source to the `on_ac` or `on_battery` tables of `doc`.
+fn render_source(doc: &mut toml_edit::Document, name: &str, knobs: &SourceKnobs) {
+    let strings = [
+        ("epp", &knobs.epp),
+        ("scaling_governor", &knobs.scaling_governor),
+    ];
+    let frequencies = [("min_freq", knobs.min_freq), ("max_freq", knobs.max_freq)];
+    let mut source = toml_edit::Table::new();
+    source.set_implicit(true);
+    for (knob, value) in strings {
+        if let Some(value) = value {
+            set_balanced(&mut source, knob, value.as_str().into());
+        }
+    }
+    for (knob, value) in frequencies {
+        if let Some(khz) = value.and_then(|khz| i64::try_from(khz).ok()) {
+            set_balanced(&mut source, knob, khz.into());
+        }
+    }
+    if !source.is_empty() {
+        doc[name] = toml_edit::Item::Table(source);
+    }
+}
+
+/// Add a knob that is set per profile rather than per power source to `doc`, if both
+/// sources agree on it or only one sets it. Differing values are listed as skipped.
+fn render_per_profile<T: Clone + PartialEq + std::fmt::Display>(
+    doc: &mut toml_edit::Document,
+    skipped: &mut Vec<String>,
+    knob: &str,
+    (on_ac, on_battery): (&Option<T>, &Option<T>),
+    into_value: impl Fn(T) -> toml_edit::Value,
+) {
+    match (on_ac, on_battery) {
+        (Some(ac), Some(battery)) if ac != battery => skipped.push(format!(
+            "{knob} = {ac} on AC and {battery} on battery, but it is set per profile here"
+        )),
+        (Some(value), _) | (None, Some(value)) => {
+            set_balanced(doc.as_table_mut(), knob, into_value(value.clone()));
+        }
+        (None, None) => {}
+    }
+}
+
+/// Add the charge thresholds as vendor knobs that write the same value for every profile.
+fn render_charge_thresholds(doc: &mut toml_edit::Document, thresholds: &[(String, u8)]) {
+    let mut knobs = toml_edit::ArrayOfTables::new();
+    for (path, percent) in thresholds {
+        let mut knob = toml_edit::Table::new();
+        knob["path"] = toml_edit::value(path.as_str());
+        for profile in ["power_saver", "balanced", "performance"] {
+            knob[profile] = toml_edit::value(i64::from(*percent));
+        }
+        knobs.push(knob);
+    }
+    if !knobs.is_empty() {
+        doc["vendor_knob"] = toml_edit::Item::ArrayOfTables(knobs);
+    }
+}
+
+/// Read the configuration of another power management tool and translate it into a
+/// config. Both tools set values per power source rather than per profile, so they become
+/// the balanced profile's values on AC and on battery, while the other profiles keep the
+/// default mapping.
+pub fn import_config(source: ImportSource, path: &path::Path) -> Result<String, String> {
+    let mut imported = Imported::default();
+    match source {
+        ImportSource::AutoCpufreq => {
+            let text =
+                fs::read_to_string(path).map_err(|e| format!("Could not read {path:?}: {e}"))?;
+            parse_auto_cpufreq(&text, &mut imported)?;
+        }
+        ImportSource::Tlp => parse_tlp(&read_tlp(path)?, &mut imported)?,
+    }
+    let tool = match source {
+        ImportSource::AutoCpufreq => "auto-cpufreq",
+        ImportSource::Tlp => "TLP",
+    };
+    let mut out = format!(
+        "# Imported by `pstate_update import` from the {tool} config {}.\n\
+         # The {tool} settings apply to the balanced profile on AC and on battery, while the\n\
+         # other profiles keep the default mapping.\n",
+        path.display()
+    );
+    let default_mapping: Vec<_> = DEFAULT_CONFIG
+        .lines()
+        .skip_while(|l| l.starts_with('#') || l.is_empty())
+        .collect();
+    let mut doc: toml_edit::Document = default_mapping
+        .join("\n")
+        .parse()
+        .map_err(|e| format!("The default config is invalid: {e}"))?;
+    let mut skipped = imported.skipped;
+    let (ac, battery) = (&imported.on_ac, &imported.on_battery);
+    render_per_profile(
+        &mut doc,
+        &mut skipped,
+        "platform_profile",
+        (&ac.platform_profile, &battery.platform_profile),
+        |p: String| p.into(),
+    );
+    render_per_profile(
+        &mut doc,
+        &mut skipped,
+        "boost",
+        (&ac.boost, &battery.boost),
+        |on: bool| on.into(),
+    );
+    render_source(&mut doc, "on_ac", ac);
+    render_source(&mut doc, "on_battery", battery);
+    render_charge_thresholds(&mut doc, &imported.charge_thresholds);
+    for skipped in &skipped {
+        out.push_str(&format!("# Not imported: {skipped}\n"));
+    }
+    out.push('\n');
+    out.push_str(&doc.to_string());
+    Ok(out)
+}
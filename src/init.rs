@@ -0,0 +1,99 @@
+use std::path;
+
+use crate::{config::CONFIG_VERSION, sysfs, PPDPowerProfile};
+
+/// EPPs to use for each profile, in order of preference.
+fn preferred_epps(profile: &PPDPowerProfile) -> &'static [&'static str] {
+    match profile {
+        PPDPowerProfile::PowerSaver => &["power", "balance_power", "default"],
+        PPDPowerProfile::Balanced => &["balance_power", "balance_performance", "default"],
+        PPDPowerProfile::Performance => &["performance", "balance_performance", "default"],
+    }
+}
+
+/// Governors to use for each profile, in order of preference. Passive drivers pin the
+/// frequency with `powersave`, so balanced prefers a dynamic governor where one exists.
+fn preferred_governors(profile: &PPDPowerProfile) -> &'static [&'static str] {
+    match profile {
+        PPDPowerProfile::PowerSaver => &["powersave"],
+        PPDPowerProfile::Balanced => &["schedutil", "ondemand", "powersave"],
+        PPDPowerProfile::Performance => &["performance", "powersave"],
+    }
+}
+
+/// Values supported by every one of the given policies. Policies that do not list any
+/// values at all are ignored.
+fn common_values(lists: impl Iterator<Item = Option<Vec<String>>>) -> Vec<String> {
+    let mut common: Option<Vec<String>> = None;
+    for list in lists.flatten() {
+        common = Some(match common {
+            None => list,
+            Some(c) => c.into_iter().filter(|v| list.contains(v)).collect(),
+        });
+    }
+    common.unwrap_or_default()
+}
+
+/// Pick the first preferred value that is available.
+fn pick<'a>(preferred: &[&'a str], available: &[String]) -> Option<&'a str> {
+    preferred
+        .iter()
+        .copied()
+        .find(|p| available.iter().any(|a| a == p))
+}
+
+/// Detect the hardware capabilities and generate a starter config for them.
+pub fn generate_config(cpufreq_path: &path::Path) -> Result<String, String> {
+    let dirs = sysfs::find_policy_dirs(cpufreq_path)
+        .map_err(|e| format!("Failed to read cpufreq policies from {cpufreq_path:?}: {e}"))?;
+    if dirs.is_empty() {
+        return Err("Could not find any cpufreq policies.".to_string());
+    }
+    let caps: Vec<_> = dirs
+        .iter()
+        .map(|d| sysfs::read_policy_capabilities(d))
+        .collect();
+    let epps = common_values(caps.iter().map(|c| c.available_epps.clone()));
+    let governors = common_values(caps.iter().map(|c| c.available_governors.clone()));
+    let driver = sysfs::read_value(&dirs[0].join("scaling_driver"))
+        .unwrap_or_else(|_| "unknown".to_string());
+    let model = sysfs::read_cpu_model().unwrap_or_else(|| "unknown CPU".to_string());
+
+    let mut epp_section = String::from("[epp]\n");
+    let mut governor_section = String::from("[scaling_governor]\n");
+    for profile in &PPDPowerProfile::ALL {
+        let key = profile.to_string().replace('-', "_");
+        // Drivers without EPP, like acpi-cpufreq, only get governors.
+        let epp = if epps.is_empty() {
+            "keep"
+        } else {
+            pick(preferred_epps(profile), &epps).ok_or_else(|| {
+                format!(
+                    "None of the policies support a suitable EPP for {profile} (driver: {driver})."
+                )
+            })?
+        };
+        let governor = pick(preferred_governors(profile), &governors).ok_or_else(|| {
+            format!("None of the policies support a suitable governor for {profile}.")
+        })?;
+        epp_section.push_str(&format!("{key} = \"{epp}\"\n"));
+        governor_section.push_str(&format!("{key} = \"{governor}\"\n"));
+    }
+    Ok(format!(
+        "# Generated by `pstate_update init` for {model} ({} policies, driver: {driver}).\n\
+         # Available EPPs: {}\n\
+         # Available governors: {}\n\
+         \n\
+         version = {CONFIG_VERSION}\n\
+         \n\
+         {epp_section}\n\
+         {governor_section}",
+        dirs.len(),
+        if epps.is_empty() {
+            "none".to_string()
+        } else {
+            epps.join(", ")
+        },
+        governors.join(", "),
+    ))
+}
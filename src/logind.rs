@@ -0,0 +1,80 @@
+use std::env;
+use std::os::unix::process::CommandExt;
+use std::process;
+
+use crate::executor;
+
+/// Session manager of systemd-logind.
+#[zbus::dbus_proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+pub trait LoginManager {
+    /// Sessions as ID, user ID, user name, seat and object path.
+    #[allow(clippy::type_complexity)]
+    fn list_sessions(
+        &self,
+    ) -> zbus::Result<Vec<(String, u32, String, String, zbus::zvariant::OwnedObjectPath)>>;
+
+    /// Whether every session is idle.
+    #[dbus_proxy(property)]
+    fn idle_hint(&self) -> zbus::Result<bool>;
+
+    /// When `IdleHint` last changed, in microseconds since the Unix epoch.
+    #[dbus_proxy(property)]
+    fn idle_since_hint(&self) -> zbus::Result<u64>;
+
+    /// Emitted with `true` right before the system suspends or hibernates, and with
+    /// `false` after it has resumed.
+    #[dbus_proxy(signal)]
+    fn prepare_for_sleep(&self, start: bool) -> zbus::Result<()>;
+}
+
+/// Users with a session on a seat, i.e. at a local desktop rather than over SSH.
+pub async fn seat_users(proxy: &LoginManagerProxy<'_>) -> zbus::Result<Vec<u32>> {
+    let mut users: Vec<u32> = proxy
+        .list_sessions()
+        .await?
+        .into_iter()
+        .filter(|(_, _, _, seat, _)| !seat.is_empty())
+        .map(|(_, uid, _, _, _)| uid)
+        .collect();
+    users.sort_unstable();
+    users.dedup();
+    Ok(users)
+}
+
+/// Commands running this executable in each local desktop session, along with the user
+/// they run as. Only a user may connect to their own session bus, so when running as root
+/// there is one command for every user with a session on a seat. Otherwise the command
+/// runs in the current user's session.
+pub async fn session_commands() -> Result<Vec<(u32, process::Command)>, String> {
+    let exe = env::current_exe().map_err(|e| format!("the executable was not found: {e}"))?;
+    if !nix::unistd::geteuid().is_root() {
+        let uid = nix::unistd::getuid().as_raw();
+        return Ok(vec![(uid, process::Command::new(&exe))]);
+    }
+    let users = executor::call(async {
+        seat_users(&LoginManagerProxy::new(&executor::system_bus().await?).await?).await
+    })
+    .await
+    .map_err(|e| format!("the desktop users are unknown: {e}"))?;
+    let mut commands = Vec::new();
+    for uid in users {
+        let Ok(Some(user)) = nix::unistd::User::from_uid(uid.into()) else {
+            continue;
+        };
+        let mut command = process::Command::new(&exe);
+        command
+            .uid(uid)
+            .gid(user.gid.as_raw())
+            .env("HOME", &user.dir)
+            .env(
+                "DBUS_SESSION_BUS_ADDRESS",
+                format!("unix:path=/run/user/{uid}/bus"),
+            );
+        commands.push((uid, command));
+    }
+    Ok(commands)
+}
@@ -1,21 +1,351 @@
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs;
 use std::path;
 use std::process;
 use std::str::FromStr;
-use std::{fmt, io};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use clap::{CommandFactory, Parser, Subcommand};
+
+mod config;
+mod controller;
+mod doctor;
+mod drm;
+mod executor;
+mod gamemode;
+mod hooks;
+#[cfg(feature = "http")]
+mod http;
+mod hwmon;
+mod import;
+mod init;
+mod logind;
+mod monitor;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod notify;
+mod power;
+mod powercap;
+mod provider;
+mod rpc;
+mod screensaver;
+mod service;
+mod statusbar;
+mod storage;
+mod sysfs;
+mod systemd;
+mod upower;
+
+use config::{Config, ConfigLocation, Frequency, NumberOrString};
+use controller::EPPController;
+use power::{BatteryWarning, PowerSource};
+
+/// Update AMD P-State EPP and scaling governor from the power-profiles-daemon profile.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Log every sysfs write instead of performing it.
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// Read the config from this file instead of searching for one.
+    #[arg(long, global = true, env = "PSTATE_UPDATE_CONFIG")]
+    config: Option<path::PathBuf>,
+
+    /// Colon-separated list of config files to try, in order.
+    #[arg(
+        long,
+        global = true,
+        env = "PSTATE_UPDATE_CONFIG_SEARCH_PATH",
+        value_delimiter = ':',
+        default_value = config::DEFAULT_SEARCH_PATH
+    )]
+    config_search_path: Vec<path::PathBuf>,
+
+    /// Override a config value, e.g. `--override epp.balanced=power`. Takes precedence
+    /// over config files and environment variables. Can be given multiple times.
+    #[arg(
+        long = "override",
+        global = true,
+        value_name = "KEY=VALUE",
+        value_parser = parse_override
+    )]
+    overrides: Vec<(String, String)>,
+
+    /// Print machine-readable JSON instead of human-readable text.
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Listen for ActiveProfile changes and apply them continuously (default).
+    Daemon {
+        /// Exit once no profile change or control request has arrived for this many
+        /// seconds, e.g. when started on demand through D-Bus activation.
+        #[arg(long, value_name = "SECONDS")]
+        exit_when_idle: Option<u64>,
+    },
+    /// Show the active power profile and the EPP/governor it maps to.
+    Status,
+    /// Apply the EPP and governor mapped to the given profile once.
+    Set {
+        /// Power profile to apply.
+        profile: PPDPowerProfile,
+    },
+    /// List the EPPs, governors and frequency range supported by each cpufreq policy.
+    List,
+    /// Read the active profile once, apply its mapping and exit.
+    ///
+    /// The profile is read from power-profiles-daemon over D-Bus. If that fails, the
+    /// profile persisted in the power-profiles-daemon state file is used instead.
+    Oneshot {
+        /// Profile to apply if the active profile cannot be determined at all.
+        #[arg(long)]
+        fallback: Option<PPDPowerProfile>,
+    },
+    /// Diagnose common problems with the driver, permissions and conflicting tools.
+    ///
+    /// Exits with 1 if any check reports an error.
+    Doctor,
+    /// Compare the values in sysfs to what the config says the active profile should use.
+    Diff {
+        /// Compare against this profile instead of the active one.
+        #[arg(long)]
+        profile: Option<PPDPowerProfile>,
+        /// Also list files that match the desired value.
+        #[arg(long)]
+        all: bool,
+    },
+    /// Generate a starter config from the detected CPU driver capabilities.
+    Init {
+        /// File to write the generated config to.
+        #[arg(long, default_value = "/etc/pstate_update/config.toml")]
+        output: path::PathBuf,
+        /// Overwrite the output file if it already exists.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Generate a config from the settings of auto-cpufreq or TLP.
+    ///
+    /// The other tools set values per power source, so they become the balanced profile's
+    /// values on AC and on battery. Settings without a counterpart, like charge thresholds,
+    /// are listed as comments at the top.
+    Import {
+        /// Tool whose config should be imported.
+        #[arg(value_enum)]
+        from: import::ImportSource,
+        /// Config file of the tool, instead of its default location.
+        #[arg(long)]
+        input: Option<path::PathBuf>,
+        /// File to write the generated config to.
+        #[arg(long, default_value = "/etc/pstate_update/config.toml")]
+        output: path::PathBuf,
+        /// Overwrite the output file if it already exists.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Switch power-profiles-daemon to the next profile.
+    ///
+    /// Profiles are cycled power-saver → balanced → performance → power-saver. Meant to
+    /// be bound to a keyboard shortcut.
+    #[command(visible_alias = "toggle")]
+    Cycle {
+        /// Step through the profiles in the opposite direction.
+        #[arg(long)]
+        reverse: bool,
+    },
+    /// Hold a profile through power-profiles-daemon until interrupted.
+    ///
+    /// power-profiles-daemon switches to the profile, and back once the hold is released
+    /// with Ctrl-C or `pstate_update release`, and the daemon applies the mapping on both
+    /// changes. Only performance and power-saver can be held.
+    Hold {
+        /// Power profile to hold.
+        profile: PPDPowerProfile,
+        /// Why the profile is held, as listed by `powerprofilesctl`.
+        #[arg(long, default_value = "Requested on the command line")]
+        reason: String,
+    },
+    /// Release a hold taken with `hold`.
+    Release {
+        /// Cookie printed by `hold`.
+        cookie: u32,
+    },
+    /// Layer a profile from the config on top of the active one in the running daemon.
+    ///
+    /// The knobs of the `[profile."<name>"]` section take precedence over the mapping of
+    /// the profile reported by power-profiles-daemon until the selection is cleared.
+    Select {
+        /// Name of a `[profile]` section in the config.
+        #[arg(required_unless_present = "clear")]
+        name: Option<String>,
+        /// Go back to the mapping of the power-profiles-daemon profile alone.
+        #[arg(long, conflicts_with = "name")]
+        clear: bool,
+    },
+    /// Print every sysfs write the daemon would make for a profile, without applying it.
+    Explain {
+        /// Power profile to explain.
+        profile: PPDPowerProfile,
+    },
+    /// Save the current value of every managed sysfs file to a file.
+    Snapshot {
+        /// File to write the snapshot to, or `-` for stdout.
+        file: path::PathBuf,
+    },
+    /// Write back the values saved by `snapshot`.
+    Restore {
+        /// Snapshot file to restore.
+        file: path::PathBuf,
+    },
+    /// Print a single-line JSON status for desktop bars like waybar or i3bar.
+    Statusbar {
+        /// Payload format.
+        #[arg(long, value_enum, default_value = "waybar")]
+        format: statusbar::Format,
+        /// Keep printing a new line every INTERVAL seconds instead of exiting.
+        #[arg(long, value_name = "INTERVAL")]
+        interval: Option<u64>,
+    },
+    /// Print a shell completion script to stdout.
+    ///
+    /// In bash, zsh and fish, the argument of `select` completes to the `[profile]` names
+    /// of the config at the time of completion.
+    Completions {
+        /// Shell to generate completions for.
+        shell: clap_complete::Shell,
+    },
+    /// Print the D-Bus introspection XML of the daemon's interfaces to stdout.
+    Introspect,
+    /// Stream profile changes, writes and failures from the running daemon.
+    Watch,
+    /// Show live per-policy frequency, EPP and governor in a terminal UI.
+    Monitor,
+    /// Inspect the configuration.
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Show a desktop notification in the current session. Used by the daemon to notify
+    /// each desktop user.
+    #[command(hide = true)]
+    Notify {
+        summary: String,
+        body: String,
+        #[arg(long, value_enum, default_value = "normal")]
+        urgency: notify::Urgency,
+    },
+    /// Print the number of games using GameMode in the current session on every change.
+    /// Used by the daemon to follow GameMode in each desktop session.
+    #[command(hide = true)]
+    GamemodeWatch,
+    /// Print 1 while the screen is locked in the current session and 0 otherwise, on
+    /// every change. Used by the daemon to follow the screen lock in each desktop session.
+    #[command(hide = true)]
+    ScreensaverWatch,
+    /// Print the name of every `[profile]` section in the config, one per line. Used by the
+    /// shell completions to complete the argument of `select`.
+    #[command(hide = true)]
+    ProfileNames,
+    /// Check the configuration against the values supported by the CPU driver.
+    ///
+    /// Exits with 0 if the config is valid, 1 if it cannot be read or parsed, and 2 if
+    /// it contains values that are rejected by one or more cpufreq policies.
+    Validate {
+        /// Only check that the config can be parsed, skipping the hardware checks.
+        #[arg(long)]
+        skip_hardware: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print the effective configuration after merging all config files, and the files
+    /// it was merged from in order of increasing precedence.
+    Show {
+        /// Print every value with the layer and file, variable or flag it came from.
+        #[arg(long, conflicts_with = "effective")]
+        origin: bool,
+        /// Print the parsed config the daemon uses, with defaults filled in for every key
+        /// that is not set.
+        #[arg(long)]
+        effective: bool,
+    },
+    /// Print the embedded default configuration, which is used when no config file is
+    /// found. Useful as a starting point for a custom config.
+    PrintDefault,
+    /// Set a value in the config file, keeping its comments and formatting, and make the
+    /// running daemon reload it.
+    ///
+    /// The edit is undone if the resulting config is invalid.
+    Set {
+        /// Dotted key path, e.g. `epp.balanced` or `policy.policy4.max_freq.balanced`.
+        key: String,
+        /// New value, parsed as TOML if possible and as a plain string otherwise.
+        value: String,
+        /// Do not ask the running daemon to reload its config.
+        #[arg(long)]
+        no_reload: bool,
+    },
+}
 
 /// Power profile exposed by power-profiles-daemon (PPD)
+#[derive(Clone, PartialEq, clap::ValueEnum, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
 enum PPDPowerProfile {
     PowerSaver,
     Balanced,
     Performance,
 }
 
+impl PPDPowerProfile {
+    const ALL: [PPDPowerProfile; 3] = [
+        PPDPowerProfile::PowerSaver,
+        PPDPowerProfile::Balanced,
+        PPDPowerProfile::Performance,
+    ];
+
+    /// Names accepted in the config.
+    const NAMES: [&'static str; 3] = ["power-saver", "balanced", "performance"];
+
+    /// The next profile in the power-saver → balanced → performance cycle.
+    fn next(&self) -> PPDPowerProfile {
+        match self {
+            PPDPowerProfile::PowerSaver => PPDPowerProfile::Balanced,
+            PPDPowerProfile::Balanced => PPDPowerProfile::Performance,
+            PPDPowerProfile::Performance => PPDPowerProfile::PowerSaver,
+        }
+    }
+
+    /// The previous profile in the power-saver → balanced → performance cycle.
+    fn previous(&self) -> PPDPowerProfile {
+        match self {
+            PPDPowerProfile::PowerSaver => PPDPowerProfile::Performance,
+            PPDPowerProfile::Balanced => PPDPowerProfile::PowerSaver,
+            PPDPowerProfile::Performance => PPDPowerProfile::Balanced,
+        }
+    }
+}
+
+/// Names are matched ignoring case and separators, since providers other than
+/// power-profiles-daemon may report e.g. `Power_Saver` or `powersaver`.
 impl FromStr for PPDPowerProfile {
     type Err = String;
     fn from_str(input: &str) -> Result<PPDPowerProfile, Self::Err> {
-        match input {
-            "power-saver" => Ok(PPDPowerProfile::PowerSaver),
+        let normalized: String = input
+            .chars()
+            .filter(|c| !matches!(c, '-' | '_' | ' '))
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        match normalized.as_str() {
+            "powersaver" => Ok(PPDPowerProfile::PowerSaver),
             "balanced" => Ok(PPDPowerProfile::Balanced),
             "performance" => Ok(PPDPowerProfile::Performance),
             _ => Err(format!("Could not parse {input}")),
@@ -33,21 +363,52 @@ impl fmt::Display for PPDPowerProfile {
     }
 }
 
-#[derive(serde::Deserialize)]
 /// Energy Performance Preference (EPP) exposed by the AMD P-State driver
+#[derive(serde::Deserialize, Default)]
+#[serde(try_from = "NumberOrString")]
 enum EnergyPerformancePreference {
-    #[allow(dead_code)]
-    #[serde(rename(deserialize = "default"))]
     Default,
-    #[serde(rename(deserialize = "performance"))]
     Performance,
-    #[allow(dead_code)]
-    #[serde(rename(deserialize = "balance_performance"))]
     BalancePerformance,
-    #[serde(rename(deserialize = "balance_power"))]
     BalancePower,
-    #[serde(rename(deserialize = "power"))]
     Power,
+    /// Raw EPP hint, from 0 (performance) to 255 (power).
+    Raw(u8),
+    /// Leave the EPP untouched.
+    #[default]
+    Keep,
+}
+
+impl EnergyPerformancePreference {
+    /// Preset names accepted in the config, besides raw numbers.
+    const NAMES: [&'static str; 6] = [
+        "default",
+        "performance",
+        "balance_performance",
+        "balance_power",
+        "power",
+        "keep",
+    ];
+}
+
+/// EPPs may be given as a preset name or as a raw number.
+impl TryFrom<NumberOrString> for EnergyPerformancePreference {
+    type Error = String;
+
+    fn try_from(value: NumberOrString) -> Result<Self, Self::Error> {
+        match value {
+            NumberOrString::Number(n) => match u8::try_from(n) {
+                Ok(n) => Ok(EnergyPerformancePreference::Raw(n)),
+                Err(_) => Err(format!("EPP {n} is out of range. Expected 0-255.")),
+            },
+            NumberOrString::Text(s) => EnergyPerformancePreference::from_str(&s).map_err(|_| {
+                format!(
+                    "Unknown EPP '{s}'. Expected default, performance, balance_performance, \
+                     balance_power, power, keep or a number from 0 to 255."
+                )
+            }),
+        }
+    }
 }
 
 impl fmt::Display for EnergyPerformancePreference {
@@ -58,6 +419,18 @@ impl fmt::Display for EnergyPerformancePreference {
             EnergyPerformancePreference::BalancePerformance => write!(f, "balance_performance"),
             EnergyPerformancePreference::BalancePower => write!(f, "balance_power"),
             EnergyPerformancePreference::Power => write!(f, "power"),
+            EnergyPerformancePreference::Raw(n) => write!(f, "{n}"),
+            EnergyPerformancePreference::Keep => write!(f, "keep"),
+        }
+    }
+}
+
+/// Raw hints are written back as numbers and presets by name.
+impl serde::Serialize for EnergyPerformancePreference {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            EnergyPerformancePreference::Raw(n) => serializer.serialize_u8(*n),
+            _ => serializer.collect_str(self),
         }
     }
 }
@@ -71,18 +444,48 @@ impl FromStr for EnergyPerformancePreference {
             "balance_performance" => Ok(EnergyPerformancePreference::BalancePerformance),
             "balance_power" => Ok(EnergyPerformancePreference::BalancePower),
             "power" => Ok(EnergyPerformancePreference::Power),
-            _ => Err(format!("Could not parse {input}")),
+            "keep" => Ok(EnergyPerformancePreference::Keep),
+            _ => match input.parse::<u8>() {
+                Ok(n) => Ok(EnergyPerformancePreference::Raw(n)),
+                Err(_) => Err(format!("Could not parse {input}")),
+            },
         }
     }
 }
 
-/// Scaling governor exposed by the AMD P-State driver
-#[derive(serde::Deserialize)]
+/// Scaling governor exposed by cpufreq. Drivers in active mode, like amd-pstate-epp and
+/// intel_pstate, only offer `powersave` and `performance`, while passive drivers like
+/// acpi-cpufreq offer the generic governors.
+#[derive(serde::Deserialize, serde::Serialize)]
 enum ScalingGovernor {
-    #[serde(rename(deserialize = "powersave"))]
+    #[serde(rename = "powersave")]
     PowerSave,
-    #[serde(rename(deserialize = "performance"))]
+    #[serde(rename = "performance")]
     Performance,
+    #[serde(rename = "schedutil")]
+    Schedutil,
+    #[serde(rename = "ondemand")]
+    Ondemand,
+    #[serde(rename = "conservative")]
+    Conservative,
+    #[serde(rename = "userspace")]
+    Userspace,
+    /// Leave the governor untouched.
+    #[serde(rename = "keep")]
+    Keep,
+}
+
+impl ScalingGovernor {
+    /// Names accepted in the config.
+    const NAMES: [&'static str; 7] = [
+        "powersave",
+        "performance",
+        "schedutil",
+        "ondemand",
+        "conservative",
+        "userspace",
+        "keep",
+    ];
 }
 
 impl fmt::Display for ScalingGovernor {
@@ -90,6 +493,11 @@ impl fmt::Display for ScalingGovernor {
         match self {
             ScalingGovernor::Performance => write!(f, "performance"),
             ScalingGovernor::PowerSave => write!(f, "powersave"),
+            ScalingGovernor::Schedutil => write!(f, "schedutil"),
+            ScalingGovernor::Ondemand => write!(f, "ondemand"),
+            ScalingGovernor::Conservative => write!(f, "conservative"),
+            ScalingGovernor::Userspace => write!(f, "userspace"),
+            ScalingGovernor::Keep => write!(f, "keep"),
         }
     }
 }
@@ -100,6 +508,11 @@ impl FromStr for ScalingGovernor {
         match input {
             "powersave" => Ok(ScalingGovernor::PowerSave),
             "performance" => Ok(ScalingGovernor::Performance),
+            "schedutil" => Ok(ScalingGovernor::Schedutil),
+            "ondemand" => Ok(ScalingGovernor::Ondemand),
+            "conservative" => Ok(ScalingGovernor::Conservative),
+            "userspace" => Ok(ScalingGovernor::Userspace),
+            "keep" => Ok(ScalingGovernor::Keep),
             _ => Err(format!("Could not parse {input}")),
         }
     }
@@ -113,235 +526,2021 @@ impl FromStr for ScalingGovernor {
 trait PowerProfilesDaemonManager {
     #[dbus_proxy(property)]
     fn active_profile(&self) -> zbus::Result<String>;
+
+    #[dbus_proxy(property)]
+    fn set_active_profile(&self, profile: &str) -> zbus::Result<()>;
+
+    #[dbus_proxy(property)]
+    fn profiles(
+        &self,
+    ) -> zbus::Result<Vec<std::collections::HashMap<String, zbus::zvariant::OwnedValue>>>;
+
+    #[dbus_proxy(property)]
+    fn performance_degraded(&self) -> zbus::Result<String>;
+
+    #[dbus_proxy(property)]
+    fn active_profile_holds(
+        &self,
+    ) -> zbus::Result<Vec<std::collections::HashMap<String, zbus::zvariant::OwnedValue>>>;
+
+    fn hold_profile(&self, profile: &str, reason: &str, application_id: &str) -> zbus::Result<u32>;
+
+    fn release_profile(&self, cookie: u32) -> zbus::Result<()>;
+
+    #[dbus_proxy(signal)]
+    fn profile_released(&self, cookie: u32) -> zbus::Result<()>;
 }
 
-/// `EPPController` controls the CPU EPP levels
-struct EPPController {
-    epp_core_files: Vec<path::PathBuf>,
-    epp_config: EPPConfig,
-    governor_core_files: Vec<path::PathBuf>,
-    governor_config: GovernorConfig,
+/// Application ID of the holds taken by `pstate_update hold`.
+const HOLD_APPLICATION_ID: &str = "pstate_update";
+
+/// Bus names of power-profiles-daemon with their object paths, in order of preference.
+/// v0.20 added `org.freedesktop.UPower.PowerProfiles`, with the same interface under the
+/// same name, next to the older `net.hadess.PowerProfiles`.
+const PPD_SERVICES: [(&str, &str); 2] = [
+    (
+        "org.freedesktop.UPower.PowerProfiles",
+        "/org/freedesktop/UPower/PowerProfiles",
+    ),
+    ("net.hadess.PowerProfiles", "/net/hadess/PowerProfiles"),
+];
+
+/// Create a proxy for the first name in `PPD_SERVICES` that is owned on the bus, falling
+/// back to `net.hadess.PowerProfiles` if neither is.
+fn ppd_proxy(
+    conn: &zbus::blocking::Connection,
+) -> zbus::Result<PowerProfilesDaemonManagerProxyBlocking<'static>> {
+    let dbus = zbus::blocking::fdo::DBusProxy::new(conn)?;
+    for (name, path) in &PPD_SERVICES[..PPD_SERVICES.len() - 1] {
+        if dbus.name_has_owner((*name).try_into()?)? {
+            return PowerProfilesDaemonManagerProxyBlocking::builder(conn)
+                .destination(*name)?
+                .path(*path)?
+                .interface(*name)?
+                .build();
+        }
+    }
+    PowerProfilesDaemonManagerProxyBlocking::new(conn)
 }
 
-impl EPPController {
-    /// Write the provided EPP to the CPU core given by the file path.
-    fn write_epp_to_core(
-        epp: &EnergyPerformancePreference,
-        epp_file: &path::Path,
-    ) -> io::Result<()> {
-        log::debug!("Writing EPP '{epp}' to file {epp_file:?}.");
-        fs::write(epp_file, epp.to_string())?;
-        Ok(())
+/// Discover CPU cores and read the config, exiting on any failure.
+fn init_controller(location: &ConfigLocation, dry_run: bool) -> EPPController {
+    let config = load_config_or_exit(location);
+    executor::set_call_timeout(config.retry.dbus_timeout());
+    let cpufreq_path = path::Path::new(sysfs::CPUFREQ_PATH);
+    let (epp_files, governor_files) =
+        match sysfs::find_managed_files(cpufreq_path, &config.exclude_cpus.0) {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("{e}");
+                process::exit(1);
+            }
+        };
+    if governor_files.is_empty() {
+        log::error!("Could not find any valid governor files. Exiting.");
+        process::exit(1);
+    }
+    let driver = governor_files[0]
+        .parent()
+        .and_then(|dir| sysfs::read_value(&dir.join("scaling_driver")).ok());
+    if let Some(driver) = driver {
+        log::info!("Managing cpufreq through the {driver} driver.");
+    }
+
+    // UPower is only asked for the battery level and warning if the config depends on
+    // them.
+    let battery_level = if config.battery_rule.is_empty() {
+        None
+    } else {
+        controller::fetch_battery_level()
+    };
+    let battery_warning = if config.low_battery.is_none() {
+        None
+    } else {
+        controller::fetch_battery_warning()
+    };
+    let mut controller = EPPController {
+        epp_core_files: epp_files,
+        governor_core_files: governor_files,
+        offlined_cpus: Vec::new(),
+        pollers: controller::Pollers::default(),
+        ppd_owns_platform_profile: config.uses_platform_profile()
+            && !config.provider
+            && controller::ppd_owns_platform_profile(),
+        thermal_active: vec![false; config.thermal_rule.len()],
+        schedule_active: config.schedules_at(controller::local_time_of_day()),
+        docked: false,
+        held: false,
+        idle: false,
+        inhibitors: Vec::new(),
+        degraded: false,
+        gaming: false,
+        locked: false,
+        idle_timeout: None,
+        config: Arc::new(config),
+        dry_run,
+        active_profile: None,
+        selected_profile: None,
+        user_override: None,
+        notifier: notify::ErrorNotifier::default(),
+        #[cfg(feature = "mqtt")]
+        mqtt: None,
+        power_source: controller::fetch_power_source(),
+        battery_level,
+        battery_warning,
+    };
+    controller.docked = controller.detect_docked();
+    controller.warn_unmatched_overrides();
+    controller.warn_missing_sensors();
+    controller
+}
+
+fn load_config_or_exit(location: &ConfigLocation) -> Config {
+    match config::read_config(location) {
+        Ok(c) => c,
+        Err(e) => {
+            log::error!("{e}");
+            process::exit(1);
+        }
     }
+}
 
-    /// Write the provided EPP to all discovered CPU cores.
-    fn write_epp_to_all_cores(&self, epp: &EnergyPerformancePreference) {
-        log::info!("Writing EPP {epp} to all EPP files.");
-        for f in &self.epp_core_files {
-            if let Err(e) = EPPController::write_epp_to_core(epp, f) {
-                log::error!("Failed to write EPP to core ({f:?}): {e}.");
+fn run_daemon(location: &ConfigLocation, dry_run: bool, exit_when_idle: Option<u64>) {
+    let mut controller = init_controller(location, dry_run);
+    controller.idle_timeout = exit_when_idle.map(Duration::from_secs);
+    let (sender, events) = mpsc::channel();
+    if let Err(e) = controller::spawn_signal_listener(sender.clone()) {
+        log::error!("Failed to install SIGHUP handler: {e}");
+        process::exit(1);
+    }
+    controller::spawn_power_source_listener(sender.clone());
+    controller::spawn_battery_level_listener(sender.clone());
+    controller.start_pollers(&sender);
+    controller::spawn_watchdog_listener(sender.clone());
+    if let Err(e) = rpc::serve(path::Path::new(rpc::SOCKET_PATH), sender.clone()) {
+        log::warn!(
+            "Could not serve the control API on {}: {e}",
+            rpc::SOCKET_PATH
+        );
+    }
+    if let Some(config) = &controller.config.http {
+        #[cfg(feature = "http")]
+        if let Err(e) = http::serve(config, sender.clone()) {
+            log::warn!("Could not serve the HTTP API on {}: {e}", config.listen);
+        }
+        #[cfg(not(feature = "http"))]
+        log::warn!(
+            "Not serving the HTTP API on {}, this build lacks the http feature.",
+            config.listen
+        );
+    }
+    if let Some(config) = &controller.config.mqtt {
+        #[cfg(feature = "mqtt")]
+        {
+            match mqtt::Publisher::connect(config, sender.clone()) {
+                Ok(publisher) => controller.mqtt = Some(publisher),
+                Err(e) => log::error!("Not reporting to the MQTT broker at {}: {e}", config.host),
             }
         }
+        #[cfg(not(feature = "mqtt"))]
+        log::warn!(
+            "Not reporting to the MQTT broker at {}, this build lacks the mqtt feature.",
+            config.host
+        );
     }
+    if controller.idle_timeout.is_some() {
+        controller::spawn_idle_listener(sender.clone());
+    }
+    if controller.config.gamemode.is_some() {
+        controller::spawn_gamemode_listener(sender.clone());
+    }
+    if controller.config.screen_lock.is_some() {
+        controller::spawn_screen_lock_listener(sender.clone());
+    }
+    if controller.config.idle.is_some() {
+        controller::spawn_sessions_idle_listener(sender.clone());
+    }
+    // Consecutive D-Bus failures. A connection that held up for longer than the longest
+    // backoff counts as recovered.
+    let mut failures = 0;
+    loop {
+        let started = Instant::now();
+        match controller.run(location, &sender, &events) {
+            Ok(()) => {
+                failures = 0;
+                log::info!("Controller finished without error. Respawning.");
+            }
+            Err(e) => {
+                let retry = &controller.config.retry;
+                if started.elapsed() > Duration::from_millis(retry.dbus_max_backoff_ms) {
+                    failures = 0;
+                }
+                failures += 1;
+                if retry.dbus_exhausted(failures) {
+                    log::error!("Encountered error. Exiting. {e}");
+                    let body = format!("The daemon stopped after repeated D-Bus failures: {e}");
+                    controller.notifier.notify(&controller.config.notify, &body);
+                    process::exit(1);
+                }
+                let delay = retry.dbus_backoff(failures);
+                log::warn!(
+                    "Encountered error: {e}. Reconnecting in {} ms (failure {failures}).",
+                    delay.as_millis()
+                );
+                systemd::notify(&format!("STATUS=Reconnecting to D-Bus after failure: {e}"));
+                systemd::sleep(delay);
+            }
+        }
+    }
+}
 
-    /// Write the provided scaling governor to the CPU core given by the file path.
-    fn write_governor_to_core(gov: &ScalingGovernor, gov_file: &path::Path) -> io::Result<()> {
-        log::debug!("Writing governor '{gov}' to file {gov_file:?}.");
-        fs::write(gov_file, gov.to_string())?;
-        Ok(())
+/// Fetch the active profile from power-profiles-daemon.
+fn fetch_active_profile() -> Result<PPDPowerProfile, zbus::Error> {
+    let conn = zbus::blocking::Connection::system()?;
+    let proxy = PowerProfilesDaemonManagerProxy::from(ppd_proxy(&conn)?.into_inner().into_inner());
+    let active = executor::block_on_call(proxy.active_profile())?;
+    PPDPowerProfile::from_str(&active).map_err(zbus::Error::Failure)
+}
+
+/// Fetch the active profile from power-profiles-daemon, or from the daemon's control
+/// socket on systems without D-Bus.
+fn fetch_active_profile_or_socket() -> Result<PPDPowerProfile, String> {
+    let dbus_error = match fetch_active_profile() {
+        Ok(profile) => return Ok(profile),
+        Err(e) => e,
+    };
+    let status = rpc::fetch_status().map_err(|e| format!("{dbus_error}, and {e}"))?;
+    let name = status
+        .active_profile
+        .ok_or("the daemon has not received a profile yet")?;
+    PPDPowerProfile::from_str(&name)
+}
+
+/// File where power-profiles-daemon persists the selected profile across restarts.
+const PPD_STATE_FILE: &str = "/var/lib/power-profiles-daemon/state.ini";
+
+/// Read the profile persisted by power-profiles-daemon from its `state.ini` keyfile.
+fn read_persisted_profile(state_file: &path::Path) -> Result<PPDPowerProfile, String> {
+    let s = fs::read_to_string(state_file).map_err(|e| format!("{state_file:?}: {e}"))?;
+    let value = s
+        .lines()
+        .find_map(|l| l.trim().strip_prefix("Profile="))
+        .ok_or_else(|| format!("No Profile entry in {state_file:?}"))?;
+    PPDPowerProfile::from_str(value.trim())
+}
+
+/// Snapshot of the active profile, its mapping and the per-policy state.
+#[derive(serde::Serialize)]
+struct StatusReport {
+    active_profile: Option<String>,
+    mapping: Option<MappedValues>,
+    policies: Vec<sysfs::PolicyStatus>,
+}
+
+/// EPP and governor the config maps the active profile to.
+#[derive(serde::Serialize)]
+struct MappedValues {
+    epp: String,
+    governor: String,
+}
+
+/// Print the given value as pretty JSON on stdout.
+fn print_json<T: serde::Serialize>(value: &T) {
+    match serde_json::to_string_pretty(value) {
+        Ok(s) => println!("{s}"),
+        Err(e) => {
+            log::error!("Failed to serialize output as JSON: {e}");
+            process::exit(1);
+        }
     }
+}
 
-    /// Write the provided EPP to all discovered CPU cores.
-    fn write_governor_to_all_cores(&self, gov: &ScalingGovernor) {
-        log::info!("Writing governor {gov} to all governor files.");
-        for f in &self.governor_core_files {
-            if let Err(e) = EPPController::write_governor_to_core(gov, f) {
-                log::error!("Failed to write governor to core ({f:?}): {e}.");
+fn run_status(location: &ConfigLocation, json: bool) {
+    let cpufreq_path = path::Path::new(sysfs::CPUFREQ_PATH);
+    let policies = match sysfs::read_all_policy_status(cpufreq_path) {
+        Ok(p) => p,
+        Err(e) => {
+            log::error!("Failed to read cpufreq policies from {cpufreq_path:?}: {e}");
+            process::exit(1);
+        }
+    };
+    let mut report = StatusReport {
+        active_profile: None,
+        mapping: None,
+        policies,
+    };
+    // Users who may not write to sysfs may not be able to read the config either, so they
+    // are shown what the daemon applied instead, if it runs.
+    let daemon_state = if can_write_cpufreq() {
+        None
+    } else {
+        service::fetch_state()
+            .map_err(|e| log::warn!("Could not ask the daemon what it applied: {e}"))
+            .ok()
+    };
+    if let Some(state) = daemon_state {
+        report.active_profile = Some(state.active_profile);
+        report.mapping = Some(MappedValues {
+            epp: state.applied_epp,
+            governor: state.applied_governor,
+        });
+    } else {
+        match fetch_active_profile_or_socket() {
+            Ok(profile) => {
+                report.active_profile = Some(profile.to_string());
+                // The mapping is informational here, so a missing config is not fatal.
+                match config::read_config(location) {
+                    Ok(config) => {
+                        report.mapping = Some(MappedValues {
+                            epp: config.epp.for_profile(&profile).to_string(),
+                            governor: config.scaling_governor.for_profile(&profile).to_string(),
+                        });
+                    }
+                    Err(e) => log::warn!("Could not read config: {e}"),
+                }
             }
+            Err(e) => log::warn!("Failed to fetch active profile: {e}"),
         }
     }
+    if json {
+        print_json(&report);
+        return;
+    }
+    println!(
+        "Active profile: {}",
+        report.active_profile.as_deref().unwrap_or("unknown")
+    );
+    if let Some(mapping) = &report.mapping {
+        println!("Mapped EPP:      {}", mapping.epp);
+        println!("Mapped governor: {}", mapping.governor);
+    }
+    println!();
+    print_policy_table(&report.policies);
+}
 
-    /// Listen for `PowerProfiles` property changes on D-Bus and act on relvant changes.
-    fn run(&self) -> Result<(), zbus::Error> {
-        let conn = zbus::blocking::Connection::system()?;
-        let proxy = PowerProfilesDaemonManagerProxyBlocking::new(&conn)?;
-        let active = proxy.active_profile()?;
-        // The general strategy is to fail early here, but not fail on later property changes.
-        // If we encounter errors on property changes, they will mainly be logged.
-        self.process_active_profile_changed(&active)?;
-
-        let mut active_profile_changes = proxy.receive_active_profile_changed();
-        log::info!(
-            "Starting to listen for ActiveProfile changes on {}, {}.",
-            proxy.destination(),
-            proxy.path(),
+/// Print one row per cpufreq policy with its current EPP, governor and frequency.
+fn print_policy_table(policies: &[sysfs::PolicyStatus]) {
+    println!(
+        "{:<10} {:<12} {:<22} {:<14} {:>10}",
+        "POLICY", "CPUS", "EPP", "GOVERNOR", "FREQ (MHz)"
+    );
+    for p in policies {
+        let freq = match p.cur_freq_khz {
+            Some(khz) => (khz / 1000).to_string(),
+            None => "-".to_string(),
+        };
+        println!(
+            "{:<10} {:<12} {:<22} {:<14} {:>10}",
+            p.name,
+            p.cpus.as_deref().unwrap_or("-"),
+            p.epp.as_deref().unwrap_or("-"),
+            p.governor.as_deref().unwrap_or("-"),
+            freq,
         );
-        for change in &mut active_profile_changes {
-            let val = change.get()?;
-            if let Err(e) = self.process_active_profile_changed(&val) {
-                log::error!("Failed to process ActiveProfile change ({val}): {e}.");
-            }
+    }
+}
+
+/// Whether the current user may write to the cpufreq files, judging by the first governor
+/// file. Also true if there are none, so that the caller reports that instead.
+fn can_write_cpufreq() -> bool {
+    let cpufreq_path = path::Path::new(sysfs::CPUFREQ_PATH);
+    sysfs::find_managed_files(cpufreq_path, &[])
+        .ok()
+        .and_then(|(_, files)| files.first().map(|f| sysfs::is_writable(f)))
+        .unwrap_or(true)
+}
+
+fn run_set(location: &ConfigLocation, profile: &PPDPowerProfile, dry_run: bool) {
+    if !dry_run && !can_write_cpufreq() {
+        log::info!("No permission to write to sysfs, asking the daemon to apply {profile}.");
+        if let Err(e) = service::apply_profile(profile) {
+            log::error!("Failed to apply {profile} through the daemon: {e}");
+            process::exit(1);
         }
-        log::info!("Finished listening for property changes.");
-        Ok(())
+        return;
     }
+    let mut controller = init_controller(location, dry_run);
+    controller.apply_profile(profile, None);
+}
 
-    /// Process the provided property change value and write EPPs from it.
-    fn process_active_profile_changed(&self, value: &str) -> Result<(), zbus::Error> {
-        let profile = match PPDPowerProfile::from_str(value) {
+fn run_oneshot(location: &ConfigLocation, fallback: Option<PPDPowerProfile>, dry_run: bool) {
+    let mut controller = init_controller(location, dry_run);
+    let profile = match fetch_active_profile() {
+        Ok(p) => p,
+        Err(e) => {
+            log::warn!("Failed to fetch active profile over D-Bus: {e}");
+            match read_persisted_profile(path::Path::new(PPD_STATE_FILE)) {
+                Ok(p) => {
+                    log::info!("Using profile {p} persisted by power-profiles-daemon.");
+                    p
+                }
+                Err(e) => {
+                    log::warn!("Failed to read persisted profile: {e}");
+                    match fallback {
+                        Some(p) => {
+                            log::info!("Using fallback profile {p}.");
+                            p
+                        }
+                        None => {
+                            log::error!("Could not determine active profile. Exiting.");
+                            process::exit(1);
+                        }
+                    }
+                }
+            }
+        }
+    };
+    log::info!("Applying profile {profile} once.");
+    controller.apply_profile(&profile, None);
+}
+
+/// Desired and actual value of a single managed sysfs file.
+#[derive(serde::Serialize)]
+struct DiffEntry {
+    knob: String,
+    path: path::PathBuf,
+    desired: String,
+    /// Current value in sysfs, or `None` if it could not be read.
+    actual: Option<String>,
+    drifted: bool,
+}
+
+/// Comparison of the sysfs state against the desired state for a profile.
+#[derive(serde::Serialize)]
+struct DiffReport {
+    profile: String,
+    drifted: usize,
+    entries: Vec<DiffEntry>,
+}
+
+fn run_diff(location: &ConfigLocation, profile: Option<PPDPowerProfile>, all: bool, json: bool) {
+    // The controller is only used for planning, so make sure it can never write.
+    let controller = init_controller(location, true);
+    let profile = match profile {
+        Some(p) => p,
+        None => match fetch_active_profile() {
             Ok(p) => p,
             Err(e) => {
-                return Err(zbus::Error::Failure(e));
+                log::error!("Failed to fetch active profile: {e}");
+                process::exit(1);
+            }
+        },
+    };
+    let entries: Vec<DiffEntry> = controller
+        .plan(&profile)
+        .into_iter()
+        .map(|w| {
+            let actual = sysfs::read_value(&w.path).ok();
+            DiffEntry {
+                drifted: actual.as_deref() != Some(w.value.as_str()),
+                knob: w.knob,
+                path: w.path,
+                desired: w.value,
+                actual,
             }
+        })
+        .collect();
+    let report = DiffReport {
+        profile: profile.to_string(),
+        drifted: entries.iter().filter(|e| e.drifted).count(),
+        entries,
+    };
+    if json {
+        print_json(&report);
+        return;
+    }
+    for e in report.entries.iter().filter(|e| all || e.drifted) {
+        let marker = if e.drifted { "!" } else { " " };
+        println!(
+            "{marker} {:<9} {:<20} {:<20} {}",
+            e.knob,
+            e.desired,
+            e.actual.as_deref().unwrap_or("<unreadable>"),
+            e.path.display(),
+        );
+    }
+    println!(
+        "{} of {} values drifted from profile {}.",
+        report.drifted,
+        report.entries.len(),
+        report.profile
+    );
+}
+
+fn run_cycle(reverse: bool, dry_run: bool) {
+    let result = zbus::blocking::Connection::system().and_then(|conn| {
+        let proxy =
+            PowerProfilesDaemonManagerProxy::from(ppd_proxy(&conn)?.into_inner().into_inner());
+        let active = executor::block_on_call(proxy.active_profile())?;
+        let active = PPDPowerProfile::from_str(&active).map_err(zbus::Error::Failure)?;
+        let next = if reverse {
+            active.previous()
+        } else {
+            active.next()
         };
-        log::info!("ActiveProfile changed: {profile}");
-        self.write_governor_to_all_cores(self.desired_governor(&profile));
-        self.write_epp_to_all_cores(self.desired_epp(&profile));
+        if dry_run {
+            log::info!("Dry run: would switch profile from {active} to {next}.");
+        } else {
+            executor::block_on_call(proxy.set_active_profile(&next.to_string()))?;
+            log::info!("Switched profile from {active} to {next}.");
+        }
+        Ok(next)
+    });
+    match result {
+        Ok(next) => println!("{next}"),
+        Err(e) => {
+            log::error!("Failed to cycle profile: {e}");
+            process::exit(1);
+        }
+    }
+}
+
+fn run_hold(profile: &PPDPowerProfile, reason: &str) {
+    if matches!(profile, PPDPowerProfile::Balanced) {
+        log::error!("Only performance and power-saver can be held.");
+        process::exit(1);
+    }
+    // Sent true once the hold is released elsewhere, e.g. by `release` or because the
+    // user picked another profile, and false on Ctrl-C.
+    let (sender, done) = mpsc::channel();
+    let interrupts = [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM];
+    let mut signals = match signal_hook::iterator::Signals::new(interrupts) {
+        Ok(s) => s,
+        Err(e) => {
+            log::error!("Failed to install the Ctrl-C handler: {e}");
+            process::exit(1);
+        }
+    };
+    let interrupted = sender.clone();
+    thread::spawn(move || {
+        if signals.forever().next().is_some() {
+            let _ = interrupted.send(false);
+        }
+    });
+    let result = zbus::blocking::Connection::system().and_then(|conn| {
+        let proxy = ppd_proxy(&conn)?;
+        // Subscribe first, so that an immediate release is not missed.
+        let released = proxy.receive_profile_released()?;
+        let calls = PowerProfilesDaemonManagerProxy::from(proxy.inner().inner().clone());
+        let name = profile.to_string();
+        let cookie =
+            executor::block_on_call(calls.hold_profile(&name, reason, HOLD_APPLICATION_ID))?;
+        println!(
+            "Holding {profile} with cookie {cookie}. Press Ctrl-C or run `pstate_update \
+             release {cookie}` to release it."
+        );
+        thread::spawn(move || {
+            for signal in released {
+                if signal.args().is_ok_and(|a| a.cookie == cookie) {
+                    let _ = sender.send(true);
+                    return;
+                }
+            }
+        });
+        if done.recv().unwrap_or(true) {
+            println!("The hold on {profile} was released.");
+        } else {
+            executor::block_on_call(calls.release_profile(cookie))?;
+            println!("Released the hold on {profile}.");
+        }
         Ok(())
+    });
+    if let Err(e) = result {
+        log::error!("Failed to hold {profile}: {e}");
+        process::exit(1);
     }
+}
 
-    /// Select appropriate EPP from Power profile.
-    fn desired_epp(&self, profile: &PPDPowerProfile) -> &EnergyPerformancePreference {
-        match profile {
-            PPDPowerProfile::Performance => &self.epp_config.performance,
-            PPDPowerProfile::Balanced => &self.epp_config.balanced,
-            PPDPowerProfile::PowerSaver => &self.epp_config.power_saver,
+fn run_release(cookie: u32) {
+    let result = zbus::blocking::Connection::system().and_then(|conn| {
+        let proxy = ppd_proxy(&conn)?;
+        let calls = PowerProfilesDaemonManagerProxy::from(proxy.inner().inner().clone());
+        executor::block_on_call(calls.release_profile(cookie))
+    });
+    if let Err(e) = result {
+        log::error!("Failed to release hold {cookie}: {e}");
+        process::exit(1);
+    }
+    println!("Released hold {cookie}.");
+}
+
+fn run_select(location: &ConfigLocation, name: Option<&str>) {
+    // Check the name against the config here, since the daemon can only log its refusal.
+    if let Some(name) = name {
+        let config = load_config_or_exit(location);
+        if !config.profile.contains_key(name) {
+            let known: Vec<_> = config.profile.keys().map(String::as_str).collect();
+            log::error!(
+                "The config has no [profile.\"{name}\"] section. Known profiles: {}.",
+                if known.is_empty() {
+                    "none".to_string()
+                } else {
+                    known.join(", ")
+                }
+            );
+            process::exit(1);
         }
     }
+    if let Err(e) = service::select_profile(name) {
+        log::error!("Failed to reach the daemon: {e}");
+        process::exit(1);
+    }
+    match name {
+        Some(name) => println!("Selected profile {name}."),
+        None => println!("Cleared the selected profile."),
+    }
+}
 
-    /// Select appropriate Scaling Governor from Power profile.
-    fn desired_governor(&self, profile: &PPDPowerProfile) -> &ScalingGovernor {
-        match profile {
-            PPDPowerProfile::Performance => &self.governor_config.performance,
-            PPDPowerProfile::Balanced => &self.governor_config.balanced,
-            PPDPowerProfile::PowerSaver => &self.governor_config.power_saver,
+fn run_explain(location: &ConfigLocation, profile: &PPDPowerProfile, json: bool) {
+    // The controller is only used for planning, so make sure it can never write.
+    let controller = init_controller(location, true);
+    let plan = controller.plan(profile);
+    if json {
+        print_json(&plan);
+        return;
+    }
+    match controller.power_source {
+        Some(source) => println!(
+            "Profile {profile} on {source} writes {} values, in order:",
+            plan.len()
+        ),
+        None => println!("Profile {profile} writes {} values, in order:", plan.len()),
+    }
+    for w in &plan {
+        println!("  {:<16} {:<20} {}", w.knob, w.value, w.path.display());
+    }
+}
+
+/// A configured value that a cpufreq policy does not support.
+#[derive(serde::Serialize, PartialEq)]
+struct ValidationProblem {
+    policy: String,
+    knob: &'static str,
+    value: String,
+    profile: String,
+    /// Values the policy reports as available, if it reports any.
+    available: Option<Vec<String>>,
+}
+
+impl fmt::Display for ValidationProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (policy, knob, value, profile) = (&self.policy, self.knob, &self.value, &self.profile);
+        match &self.available {
+            Some(available) => write!(
+                f,
+                "{policy}: {knob} '{value}' for {profile} is not supported. Available: {}",
+                available.join(", ")
+            ),
+            None => write!(
+                f,
+                "{policy}: {knob} '{value}' for {profile} cannot be verified, no available \
+                 values listed."
+            ),
         }
     }
 }
 
-/// Traverse the given `cpufreq` folder and collect valid EPP files for each CPU core
-fn find_cpu_core_epp_paths(cpufreq_path: &path::Path) -> Result<Vec<path::PathBuf>, io::Error> {
-    let mut paths = Vec::new();
-    log::info!("Looking for EPP files for individual CPU cores in {cpufreq_path:?}.");
-    for entry in cpufreq_path.read_dir()? {
-        let p = entry.expect("any path from read_dir should be Ok").path();
-        let dirname = match p.file_name() {
-            Some(f) => match f.to_str() {
-                Some(s) => s,
-                None => continue,
-            },
-            None => continue,
-        };
-        if !dirname.starts_with("policy") {
+/// Check a configured value against the values a policy reports as available.
+fn check_supported(
+    policy: &str,
+    knob: &'static str,
+    value: String,
+    profile: &str,
+    available: Option<&[String]>,
+) -> Option<ValidationProblem> {
+    if let Some(available) = available {
+        if available.contains(&value) {
+            return None;
+        }
+    }
+    Some(ValidationProblem {
+        policy: policy.to_string(),
+        knob,
+        value,
+        profile: profile.to_string(),
+        available: available.map(|a| a.to_vec()),
+    })
+}
+
+/// Check a configured frequency limit against the range a policy supports.
+fn check_frequency(
+    policy: &str,
+    knob: &'static str,
+    freq: Option<Frequency>,
+    profile: &str,
+    caps: &sysfs::PolicyCapabilities,
+) -> Option<ValidationProblem> {
+    let freq = freq?;
+    let (min, max) = (caps.min_freq_khz, caps.max_freq_khz);
+    if let (Some(khz), Some(min), Some(max)) = (freq.to_khz(max), min, max) {
+        if (min..=max).contains(&khz) {
+            return None;
+        }
+    }
+    Some(ValidationProblem {
+        policy: policy.to_string(),
+        knob,
+        value: freq.to_string(),
+        profile: profile.to_string(),
+        available: min
+            .zip(max)
+            .map(|(min, max)| vec![format!("{min}-{max} kHz")]),
+    })
+}
+
+/// Check configured RAPL power limits against the maximum power of each package zone.
+fn check_power_limits(
+    limits: &config::PowerLimits,
+    profile: &str,
+    zones: &[path::PathBuf],
+) -> Vec<ValidationProblem> {
+    let values = [
+        ("pl1", powercap::PL1_CONSTRAINT, limits.pl1_watts),
+        ("pl2", powercap::PL2_CONSTRAINT, limits.pl2_watts),
+    ];
+    let mut problems = Vec::new();
+    for (knob, constraint, watts) in values {
+        let Some(watts) = watts else {
             continue;
+        };
+        let problem = |zone: &str, available: Option<Vec<String>>| ValidationProblem {
+            policy: zone.to_string(),
+            knob,
+            value: watts.to_string(),
+            profile: profile.to_string(),
+            available,
+        };
+        if zones.is_empty() {
+            problems.push(problem("rapl", None));
         }
-        let epp_file = p.join("energy_performance_preference");
-        if !epp_file.exists() {
-            log::warn!("EPP file does not exist: {epp_file:?}.");
+        for zone in zones {
+            let max_uw: Option<f64> = powercap::constraint_file(zone, constraint, "max_power_uw")
+                .and_then(|f| sysfs::read_value(&f).ok()?.parse().ok());
+            // Not all constraints report a maximum, so only the ones that do are checked.
+            if let Some(max) = max_uw.filter(|max| watts.0 * 1e6 > *max) {
+                let available = vec![format!("0-{} W", max / 1e6)];
+                problems.push(problem(&sysfs::policy_name(zone), Some(available)));
+            }
+        }
+    }
+    problems
+}
+
+/// Check a GPU power cap against the range every amdgpu card accepts. Caps outside the
+/// range are clamped when applied, but are reported since the profile does not get the
+/// configured cap.
+fn check_gpu_power_cap(
+    watts: config::Watts,
+    profile: &str,
+    devices: &[path::PathBuf],
+) -> Vec<ValidationProblem> {
+    let problem = |card: &str, available: Option<Vec<String>>| ValidationProblem {
+        policy: card.to_string(),
+        knob: controller::GPU_POWER_CAP_KNOB,
+        value: watts.to_string(),
+        profile: profile.to_string(),
+        available,
+    };
+    let hwmons: Vec<_> = devices
+        .iter()
+        .filter_map(|d| drm::find_power_cap_hwmon(d))
+        .collect();
+    if hwmons.is_empty() {
+        return vec![problem("amdgpu", None)];
+    }
+    let mut problems = Vec::new();
+    for hwmon in &hwmons {
+        let (min, max) = drm::read_power_cap_range(hwmon);
+        let uw = watts.to_microwatts();
+        if uw < min || uw > max {
+            let available = vec![format!("{}-{} W", min as f64 / 1e6, max as f64 / 1e6)];
+            problems.push(problem(&sysfs::policy_name(hwmon), Some(available)));
+        }
+    }
+    problems
+}
+
+/// Check that the CPUs a profile takes offline can be taken offline, and that a count
+/// leaves some online.
+fn check_offline_cpus(
+    offline: &config::OfflineCpus,
+    profile: &str,
+    hotplug_cpus: &[u32],
+) -> Vec<ValidationProblem> {
+    let available = config::CpuList(hotplug_cpus.to_vec()).to_string();
+    let problem = |value: String, available: String| ValidationProblem {
+        policy: "cpu".to_string(),
+        knob: controller::OFFLINE_CPUS_KNOB,
+        value,
+        profile: profile.to_string(),
+        available: Some(vec![available]),
+    };
+    match offline {
+        config::OfflineCpus::Count(n) if *n > hotplug_cpus.len() => {
+            vec![problem(n.to_string(), format!("0-{}", hotplug_cpus.len()))]
+        }
+        config::OfflineCpus::Count(_) => Vec::new(),
+        config::OfflineCpus::List(list) => list
+            .0
+            .iter()
+            .filter(|cpu| !hotplug_cpus.contains(cpu))
+            .map(|cpu| problem(cpu.to_string(), available.clone()))
+            .collect(),
+    }
+}
+
+/// Check i915 frequency caps against the frequency range of every card, and that the
+/// lower cap is at most the upper one.
+fn check_i915_caps(
+    caps: &config::GpuFreqCaps,
+    profile: &str,
+    cards: &[path::PathBuf],
+) -> Vec<ValidationProblem> {
+    let values = [
+        ("i915 min", caps.min_mhz),
+        ("i915 max", caps.max_mhz),
+        ("i915 boost", caps.boost_mhz),
+    ];
+    let mut problems = Vec::new();
+    for (knob, mhz) in values {
+        let Some(mhz) = mhz else {
             continue;
+        };
+        let problem = |card: String, available: Option<Vec<String>>| ValidationProblem {
+            policy: card,
+            knob,
+            value: format!("{mhz} MHz"),
+            profile: profile.to_string(),
+            available,
+        };
+        if cards.is_empty() {
+            problems.push(problem("i915".to_string(), None));
+        }
+        for card in cards {
+            let read =
+                |file| -> Option<u32> { sysfs::read_value(&card.join(file)).ok()?.parse().ok() };
+            let low = read(drm::I915_HW_MIN_FREQ_FILE);
+            let high = read(drm::I915_HW_MAX_FREQ_FILE);
+            let (Some(low), Some(high)) = (low, high) else {
+                continue;
+            };
+            // The lower cap cannot exceed the upper one, nor boost go below the lower cap.
+            let (low, high) = match knob {
+                "i915 min" => (low, caps.max_mhz.unwrap_or(high).min(high)),
+                "i915 boost" => (caps.min_mhz.unwrap_or(low).max(low), high),
+                _ => (low, high),
+            };
+            if mhz < low || mhz > high {
+                let available = vec![format!("{low}-{high} MHz")];
+                problems.push(problem(sysfs::policy_name(card), Some(available)));
+            }
         }
-        log::debug!("Found valid EPP file: {epp_file:?}.");
-        paths.push(epp_file);
     }
-    log::info!("Found {} valid EPP files.", paths.len());
-    Ok(paths)
+    problems
 }
 
-fn generate_cpu_core_gorvernor_paths(epp_paths: &[path::PathBuf]) -> Vec<path::PathBuf> {
-    let mut paths = Vec::new();
-    for epp in epp_paths {
-        let p = epp.with_file_name("scaling_governor");
-        if !p.exists() {
-            log::warn!("Governor file does not exist: {p:?}.");
+/// Check intel_pstate knobs against the running driver. Percentages must be at most 100,
+/// and the minimum at most the maximum.
+fn check_intel_pstate(knobs: &config::IntelPstate, profile: &str) -> Vec<ValidationProblem> {
+    let dir = path::Path::new(sysfs::INTEL_PSTATE_PATH);
+    let flag = |on: bool| if on { "1" } else { "0" }.to_string();
+    let pct = |p: u8| format!("{p}%");
+    let values = [
+        ("no_turbo", knobs.no_turbo.map(flag), None),
+        (
+            "min_perf_pct",
+            knobs.min_perf_pct.map(pct),
+            knobs.min_perf_pct,
+        ),
+        (
+            "max_perf_pct",
+            knobs.max_perf_pct.map(pct),
+            knobs.max_perf_pct,
+        ),
+        ("hwp_dynamic_boost", knobs.hwp_dynamic_boost.map(flag), None),
+    ];
+    let max = knobs.max_perf_pct.unwrap_or(100).min(100);
+    let mut problems = Vec::new();
+    for (knob, value, percent) in values {
+        let Some(value) = value else {
+            continue;
+        };
+        let limit = if knob == "min_perf_pct" { max } else { 100 };
+        let available = if !dir.join(knob).exists() {
+            None
+        } else if percent.is_some_and(|p| p > limit) {
+            Some(vec![format!("0-{limit}%")])
+        } else {
             continue;
+        };
+        problems.push(ValidationProblem {
+            policy: "intel_pstate".to_string(),
+            knob,
+            value,
+            profile: profile.to_string(),
+            available,
+        });
+    }
+    problems
+}
+
+/// Check every value the config maps a profile to in the given scope against what the
+/// policy supports. `name` is the profile name reported by power-profiles-daemon.
+fn validate_profile(
+    config: &Config,
+    scope: &config::Scope,
+    name: &str,
+    profile: &PPDPowerProfile,
+    caps: &sysfs::PolicyCapabilities,
+) -> Vec<ValidationProblem> {
+    let policy = scope.policy;
+    let mut problems = Vec::new();
+    let epp = config.epp_for(scope, profile);
+    // Raw EPP hints are not listed among the available preferences, but every policy with
+    // an EPP file accepts them.
+    let raw_accepted = matches!(epp, EnergyPerformancePreference::Raw(_)) && caps.has_epp;
+    if !matches!(epp, EnergyPerformancePreference::Keep) && !raw_accepted {
+        problems.extend(check_supported(
+            policy,
+            controller::EPP_KNOB,
+            epp.to_string(),
+            name,
+            caps.available_epps.as_deref(),
+        ));
+    }
+    let governor = config.governor_for(scope, profile);
+    if !matches!(governor, ScalingGovernor::Keep) {
+        problems.extend(check_supported(
+            policy,
+            controller::GOVERNOR_KNOB,
+            governor.to_string(),
+            name,
+            caps.available_governors.as_deref(),
+        ));
+    }
+    let min_freq = config.min_freq_for(scope, profile);
+    problems.extend(check_frequency(
+        policy,
+        controller::MIN_FREQ_KNOB,
+        min_freq,
+        name,
+        caps,
+    ));
+    let max_freq = config.max_freq_for(scope, profile);
+    problems.extend(check_frequency(
+        policy,
+        controller::MAX_FREQ_KNOB,
+        max_freq,
+        name,
+        caps,
+    ));
+    problems
+}
+
+/// Conditions under which the config is validated, besides the policy and profile.
+struct Condition<'a> {
+    power_source: PowerSource,
+    battery_level: Option<f64>,
+    thermal_rules: Vec<&'a config::ThermalRule>,
+    schedules: Vec<&'a config::Schedule>,
+    docked: bool,
+    held: bool,
+    idle: bool,
+    degraded: bool,
+    battery_warning: Option<BatteryWarning>,
+}
+
+impl<'a> Condition<'a> {
+    /// What values of the given policy are looked up for under this condition, refined by
+    /// a custom profile if given.
+    fn scope(
+        &self,
+        policy: &'a str,
+        cpus: &'a [u32],
+        custom: Option<&'a config::CustomProfile>,
+    ) -> config::Scope<'a> {
+        config::Scope {
+            policy,
+            cpus,
+            power_source: Some(self.power_source),
+            custom,
+            user_override: None,
+            battery_level: self.battery_level,
+            thermal_rules: self.thermal_rules.clone(),
+            schedules: self.schedules.clone(),
+            docked: self.docked,
+            held: self.held,
+            idle: self.idle,
+            degraded: self.degraded,
+            battery_warning: self.battery_warning,
         }
-        paths.push(p);
     }
-    log::info!("Found {} valid governor files.", paths.len());
-    paths
 }
 
-#[derive(serde::Deserialize)]
-struct EPPConfig {
-    power_saver: EnergyPerformancePreference,
-    balanced: EnergyPerformancePreference,
-    performance: EnergyPerformancePreference,
+impl Default for Condition<'_> {
+    fn default() -> Self {
+        Condition {
+            power_source: PowerSource::Ac,
+            battery_level: None,
+            thermal_rules: Vec::new(),
+            schedules: Vec::new(),
+            docked: false,
+            held: false,
+            idle: false,
+            degraded: false,
+            battery_warning: None,
+        }
+    }
 }
 
-#[derive(serde::Deserialize)]
-struct GovernorConfig {
-    power_saver: ScalingGovernor,
-    balanced: ScalingGovernor,
-    performance: ScalingGovernor,
+/// Outcome of validating the config against the hardware.
+#[derive(serde::Serialize)]
+struct ValidationReport {
+    valid: bool,
+    policies_checked: usize,
+    problems: Vec<ValidationProblem>,
 }
 
-#[derive(serde::Deserialize)]
-struct Config {
-    epp: EPPConfig,
-    scaling_governor: GovernorConfig,
+fn run_validate(location: &ConfigLocation, skip_hardware: bool, json: bool) {
+    let config = load_config_or_exit(location);
+    if skip_hardware {
+        if json {
+            print_json(&ValidationReport {
+                valid: true,
+                policies_checked: 0,
+                problems: Vec::new(),
+            });
+        } else {
+            println!("Configuration is valid.");
+        }
+        return;
+    }
+    let cpufreq_path = path::Path::new(sysfs::CPUFREQ_PATH);
+    let excluded = |d: &path::PathBuf| {
+        let cpus = sysfs::read_policy_cpus(d);
+        cpus.iter().any(|c| config.exclude_cpus.0.contains(c))
+    };
+    let policies: Vec<_> = match sysfs::find_policy_dirs(cpufreq_path) {
+        Ok(p) => p
+            .into_iter()
+            .filter(|d| sysfs::is_active_policy(d) && !excluded(d))
+            .collect(),
+        Err(e) => {
+            log::error!("Failed to read cpufreq policies from {cpufreq_path:?}: {e}");
+            process::exit(1);
+        }
+    };
+    if policies.is_empty() {
+        log::error!("Could not find any cpufreq policies to validate against.");
+        process::exit(2);
+    }
+    let scopes: Vec<_> = policies
+        .iter()
+        .map(|d| (sysfs::policy_name(d), sysfs::read_policy_cpus(d)))
+        .collect();
+    for problem in config.check_overrides(&scopes) {
+        log::warn!("{problem}");
+    }
+    for problem in config.check_aliases() {
+        log::warn!("{problem}");
+    }
+    // Only possible while power-profiles-daemon runs, and moot when replacing it.
+    let offered = zbus::blocking::Connection::system()
+        .and_then(|conn| {
+            let proxy = ppd_proxy(&conn)?.into_inner().into_inner();
+            executor::block_on_call(PowerProfilesDaemonManagerProxy::from(proxy).profiles())
+        })
+        .map(|profiles| controller::offered_profiles(&profiles));
+    if let (Ok(offered), false) = (offered, config.provider) {
+        for problem in config.check_offered_profiles(&offered) {
+            log::warn!("{problem}");
+        }
+    }
+    let mut problems = Vec::new();
+    let builtin = PPDPowerProfile::ALL
+        .iter()
+        .map(|p| (p.to_string(), p, None));
+    let custom = config
+        .profile
+        .iter()
+        .map(|(name, c)| (name.clone(), &c.base, Some(c)));
+    let profiles: Vec<_> = builtin.chain(custom).collect();
+    // Each battery rule is checked just below its threshold, where exactly that rule
+    // applies, and each thermal rule, schedule, the docked, hold and degraded overrides and
+    // the battery warnings are checked on their own.
+    let plain = PowerSource::ALL.map(|power_source| Condition {
+        power_source,
+        ..Condition::default()
+    });
+    let rule_levels = config.battery_rule.iter().map(|r| Condition {
+        power_source: PowerSource::Battery,
+        battery_level: Some(r.below - 0.001),
+        ..Condition::default()
+    });
+    let thermal_rules = config.thermal_rule.iter().map(|r| Condition {
+        thermal_rules: vec![r],
+        ..Condition::default()
+    });
+    let schedules = config.schedule.iter().map(|s| Condition {
+        schedules: vec![s],
+        ..Condition::default()
+    });
+    let docked = config.docked.iter().map(|_| Condition {
+        docked: true,
+        ..Condition::default()
+    });
+    let held = config.hold.iter().map(|_| Condition {
+        held: true,
+        ..Condition::default()
+    });
+    let idle = config.idle.iter().map(|_| Condition {
+        idle: true,
+        ..Condition::default()
+    });
+    let degraded = Some(Condition {
+        degraded: true,
+        ..Condition::default()
+    })
+    .filter(|_| !config.degraded.is_empty());
+    let warnings = config.low_battery.iter().flat_map(|_| {
+        [BatteryWarning::Low, BatteryWarning::Critical].map(|warning| Condition {
+            power_source: PowerSource::Battery,
+            battery_warning: Some(warning),
+            ..Condition::default()
+        })
+    });
+    let conditions: Vec<_> = plain
+        .into_iter()
+        .chain(rule_levels)
+        .chain(thermal_rules)
+        .chain(schedules)
+        .chain(docked)
+        .chain(held)
+        .chain(idle)
+        .chain(degraded)
+        .chain(warnings)
+        .collect();
+    let platform_profiles =
+        sysfs::read_available(path::Path::new(sysfs::PLATFORM_PROFILE_CHOICES_PATH));
+    for (name, profile, custom) in &profiles {
+        if let Some(platform_profile) = config.platform_profile_for(profile, *custom) {
+            problems.extend(check_supported(
+                "acpi",
+                controller::PLATFORM_PROFILE_KNOB,
+                platform_profile.to_string(),
+                name,
+                platform_profiles.as_deref(),
+            ));
+        }
+    }
+    let amd_pstate_modes = Some(config::AmdPstateMode::ALL.map(|m| m.to_string()))
+        .filter(|_| path::Path::new(sysfs::AMD_PSTATE_STATUS_PATH).exists());
+    for (name, profile, custom) in &profiles {
+        if let Some(mode) = config.amd_pstate_mode_for(profile, *custom) {
+            problems.extend(check_supported(
+                "amd_pstate",
+                controller::AMD_PSTATE_MODE_KNOB,
+                mode.to_string(),
+                name,
+                amd_pstate_modes.as_ref().map(|m| m.as_slice()),
+            ));
+        }
+    }
+    let policy_dirs: Vec<_> = policies.iter().map(|p| p.as_path()).collect();
+    let boost_values = Some(["0", "1"].map(String::from))
+        .filter(|_| !sysfs::find_boost_files(cpufreq_path, &policy_dirs).is_empty());
+    for (name, profile, custom) in &profiles {
+        if let Some(on) = config.boost_for(profile, *custom) {
+            problems.extend(check_supported(
+                "cpufreq",
+                controller::BOOST_KNOB,
+                if on { "1" } else { "0" }.to_string(),
+                name,
+                boost_values.as_ref().map(|b| b.as_slice()),
+            ));
+        }
+    }
+    let smt_states = match sysfs::read_value(path::Path::new(sysfs::SMT_CONTROL_PATH)) {
+        Ok(state) if state == "on" || state == "off" => Some(vec!["on".into(), "off".into()]),
+        Ok(state) => Some(vec![state]),
+        Err(_) => None,
+    };
+    for (name, profile, custom) in &profiles {
+        if let Some(on) = config.smt_for(profile, *custom) {
+            problems.extend(check_supported(
+                "cpu",
+                controller::SMT_KNOB,
+                if on { "on" } else { "off" }.to_string(),
+                name,
+                smt_states.as_deref(),
+            ));
+        }
+    }
+    let hotplug_cpus: Vec<u32> = sysfs::find_hotplug_cpus(path::Path::new(sysfs::CPU_PATH))
+        .into_iter()
+        .map(|(cpu, _)| cpu)
+        .filter(|cpu| !config.exclude_cpus.0.contains(cpu))
+        .collect();
+    for (name, profile, custom) in &profiles {
+        if let Some(offline) = config.offline_cpus_for(profile, *custom) {
+            problems.extend(check_offline_cpus(offline, name, &hotplug_cpus));
+        }
+    }
+    for knob in &config.vendor_knob {
+        let file = &knob.path.0;
+        for (name, profile, _) in &profiles {
+            match knob.values.for_profile(profile) {
+                Some(value) if !file.exists() => problems.push(ValidationProblem {
+                    policy: file.display().to_string(),
+                    knob: controller::VENDOR_KNOB,
+                    value: value.0.clone(),
+                    profile: name.to_string(),
+                    available: None,
+                }),
+                _ => {}
+            }
+        }
+    }
+    let idle_states =
+        sysfs::find_idle_states(path::Path::new(sysfs::CPU_PATH), &config.exclude_cpus.0);
+    let mut idle_state_names = vec![sysfs::ALL_IDLE_STATES.to_string()];
+    for (name, _) in idle_states.iter().flat_map(|(_, states)| states) {
+        if !idle_state_names.contains(name) {
+            idle_state_names.push(name.clone());
+        }
+    }
+    for (name, profile, custom) in &profiles {
+        if let Some(max) = config.max_cstate_for(profile, *custom) {
+            problems.extend(check_supported(
+                "cpuidle",
+                controller::MAX_CSTATE_KNOB,
+                max.to_string(),
+                name,
+                Some(idle_state_names.as_slice()).filter(|_| !idle_states.is_empty()),
+            ));
+        }
+    }
+    let epb_files = sysfs::find_epb_files(path::Path::new(sysfs::CPU_PATH), &config.exclude_cpus.0);
+    for (name, profile, custom) in &profiles {
+        match config.epb_for(profile, *custom) {
+            Some(epb) if epb_files.is_empty() => problems.push(ValidationProblem {
+                policy: "cpu".to_string(),
+                knob: controller::EPB_KNOB,
+                value: epb.to_string(),
+                profile: name.to_string(),
+                available: None,
+            }),
+            _ => {}
+        }
+    }
+    let zones = powercap::find_package_zones(path::Path::new(powercap::POWERCAP_PATH));
+    for (name, profile, custom) in &profiles {
+        if let Some(limits) = config.rapl_for(profile, *custom) {
+            problems.extend(check_power_limits(limits, name, &zones));
+        }
+    }
+    for (name, profile, custom) in &profiles {
+        if let Some(knobs) = config.intel_pstate_for(profile, *custom) {
+            problems.extend(check_intel_pstate(knobs, name));
+        }
+    }
+    let i915_cards = drm::find_i915_cards(path::Path::new(drm::DRM_PATH));
+    for (name, profile, custom) in &profiles {
+        if let Some(caps) = config.i915_for(profile, *custom) {
+            problems.extend(check_i915_caps(caps, name, &i915_cards));
+        }
+    }
+    let gpu_levels = Some(drm::DPM_LEVELS.map(String::from)).filter(|_| {
+        !config
+            .gpu_target
+            .devices(path::Path::new(drm::DRM_PATH))
+            .is_empty()
+    });
+    for (name, profile, custom) in &profiles {
+        if let Some(level) = config.gpu_level_for(profile, *custom) {
+            problems.extend(check_supported(
+                "amdgpu",
+                controller::GPU_LEVEL_KNOB,
+                level.to_string(),
+                name,
+                gpu_levels.as_ref().map(|l| l.as_slice()),
+            ));
+        }
+    }
+    let gpu_devices = config.gpu_target.devices(path::Path::new(drm::DRM_PATH));
+    for (name, profile, custom) in &profiles {
+        let Some(mode) = config.gpu_power_profile_for(profile, *custom) else {
+            continue;
+        };
+        if gpu_devices.is_empty() {
+            problems.extend(check_supported(
+                "amdgpu",
+                controller::GPU_POWER_PROFILE_KNOB,
+                mode.to_string(),
+                name,
+                None,
+            ));
+        }
+        for device in &gpu_devices {
+            let card = device
+                .parent()
+                .and_then(|c| c.file_name())
+                .map_or("amdgpu".into(), |c| c.to_string_lossy());
+            let modes: Vec<_> = drm::read_power_profile_modes(device)
+                .into_iter()
+                .map(|(_, mode)| mode)
+                .collect();
+            problems.extend(check_supported(
+                &card,
+                controller::GPU_POWER_PROFILE_KNOB,
+                mode.to_string(),
+                name,
+                Some(modes.as_slice()).filter(|m| !m.is_empty()),
+            ));
+        }
+    }
+    for (name, profile, custom) in &profiles {
+        if let Some(watts) = config.gpu_power_cap_for(profile, *custom) {
+            problems.extend(check_gpu_power_cap(watts, name, &gpu_devices));
+        }
+    }
+    // Any latency is accepted, so only a missing NVMe drive is reported.
+    let has_nvme = !storage::find_nvme_controllers(path::Path::new(storage::NVME_PATH)).is_empty();
+    for (name, profile, custom) in &profiles {
+        let latency = config.nvme_latency_us_for(profile, *custom);
+        if let Some(latency) = latency.filter(|_| !has_nvme) {
+            problems.push(ValidationProblem {
+                policy: "nvme".to_string(),
+                knob: controller::NVME_LATENCY_KNOB,
+                value: format!("{latency} us"),
+                profile: name.to_string(),
+                available: None,
+            });
+        }
+    }
+    let cpuidle_path = path::Path::new(sysfs::CPUIDLE_PATH);
+    let cpuidle_governors = sysfs::read_available(&cpuidle_path.join("available_governors"));
+    for (name, profile, custom) in &profiles {
+        if let Some(gov) = config.cpuidle_governor_for(profile, *custom) {
+            problems.extend(check_supported(
+                "cpuidle",
+                controller::CPUIDLE_GOVERNOR_KNOB,
+                gov.to_string(),
+                name,
+                cpuidle_governors.as_deref(),
+            ));
+        }
+    }
+    for ((policy, cpus), dir) in scopes.iter().zip(&policies) {
+        let caps = sysfs::read_policy_capabilities(dir);
+        for condition in &conditions {
+            for (name, profile, custom) in &profiles {
+                let scope = condition.scope(policy, cpus, *custom);
+                for problem in validate_profile(&config, &scope, name, profile, &caps) {
+                    // Values shared by several conditions are only reported once.
+                    if !problems.contains(&problem) {
+                        problems.push(problem);
+                    }
+                }
+            }
+        }
+    }
+    let report = ValidationReport {
+        valid: problems.is_empty(),
+        policies_checked: policies.len(),
+        problems,
+    };
+    if json {
+        print_json(&report);
+    } else {
+        for problem in &report.problems {
+            println!("{problem}");
+        }
+        if report.valid {
+            println!(
+                "Configuration is valid for all {} cpufreq policies.",
+                report.policies_checked
+            );
+        } else {
+            println!(
+                "Found {} problem(s) in configuration.",
+                report.problems.len()
+            );
+        }
+    }
+    if !report.valid {
+        process::exit(2);
+    }
 }
 
-fn read_config() -> Result<Config, io::Error> {
-    let mut config_file = path::Path::new("/etc/pstate_update/config.toml");
-    if !config_file.exists() {
-        log::warn!("Could not find {config_file:?}. Trying local folder instead.");
-        config_file = path::Path::new("config.toml");
+fn run_list(json: bool) {
+    let cpufreq_path = path::Path::new(sysfs::CPUFREQ_PATH);
+    let policies: Vec<_> = match sysfs::find_policy_dirs(cpufreq_path) {
+        Ok(dirs) => dirs
+            .iter()
+            .map(|d| sysfs::read_policy_capabilities(d))
+            .collect(),
+        Err(e) => {
+            log::error!("Failed to read cpufreq policies from {cpufreq_path:?}: {e}");
+            process::exit(1);
+        }
+    };
+    if json {
+        print_json(&policies);
+        return;
     }
-    let s = fs::read_to_string(config_file)?;
-    let config: Config = match toml::from_str(&s) {
+    let list = |values: &Option<Vec<String>>| match values {
+        Some(v) => v.join(" "),
+        None => "-".to_string(),
+    };
+    let mhz = |khz: Option<u64>| match khz {
+        Some(khz) => (khz / 1000).to_string(),
+        None => "?".to_string(),
+    };
+    for p in &policies {
+        println!("{} (CPUs: {})", p.name, p.cpus.as_deref().unwrap_or("-"));
+        println!("  EPPs:      {}", list(&p.available_epps));
+        println!("  Governors: {}", list(&p.available_governors));
+        println!(
+            "  Frequency: {} - {} MHz",
+            mhz(p.min_freq_khz),
+            mhz(p.max_freq_khz)
+        );
+    }
+}
+
+fn run_doctor(json: bool) {
+    let findings = doctor::run_checks(path::Path::new(sysfs::CPUFREQ_PATH));
+    if json {
+        print_json(&findings);
+    } else {
+        for finding in &findings {
+            println!("{finding}");
+        }
+    }
+    if doctor::has_errors(&findings) {
+        process::exit(1);
+    }
+}
+
+fn run_init(output: &path::Path, force: bool, dry_run: bool) {
+    let config = match init::generate_config(path::Path::new(sysfs::CPUFREQ_PATH)) {
         Ok(c) => c,
         Err(e) => {
-            return Err(io::Error::new(io::ErrorKind::Other, e));
+            log::error!("Could not generate config: {e}");
+            process::exit(1);
         }
     };
-    Ok(config)
+    write_generated_config(&config, output, force, dry_run);
 }
 
-fn main() {
-    // TODO: Notify desktop on certain errors? Could be easily done using DBus.
-    let env = env_logger::Env::new().default_filter_or("info");
-    env_logger::init_from_env(env);
+fn run_import(
+    from: import::ImportSource,
+    input: Option<&path::Path>,
+    output: &path::Path,
+    force: bool,
+    dry_run: bool,
+) {
+    let input = input.unwrap_or(path::Path::new(from.default_path()));
+    let config = match import::import_config(from, input) {
+        Ok(c) => c,
+        Err(e) => {
+            log::error!("Could not import config: {e}");
+            process::exit(1);
+        }
+    };
+    write_generated_config(&config, output, force, dry_run);
+}
 
-    let cpufreq_path = path::Path::new("/sys/devices/system/cpu/cpufreq");
-    let epp_files = match find_cpu_core_epp_paths(cpufreq_path) {
-        Ok(v) => v,
+/// Write a generated config to the output file, or print it for a dry run or when the
+/// output is `-`.
+fn write_generated_config(config: &str, output: &path::Path, force: bool, dry_run: bool) {
+    if dry_run || output == path::Path::new("-") {
+        print!("{config}");
+        return;
+    }
+    if output.exists() && !force {
+        log::error!("{output:?} already exists. Use --force to overwrite it.");
+        process::exit(1);
+    }
+    if let Some(dir) = output.parent() {
+        if let Err(e) = fs::create_dir_all(dir) {
+            log::error!("Could not create {dir:?}: {e}");
+            process::exit(1);
+        }
+    }
+    if let Err(e) = fs::write(output, config) {
+        log::error!("Could not write {output:?}: {e}");
+        process::exit(1);
+    }
+    println!("Wrote config to {}.", output.display());
+}
+
+/// Saved values of all managed sysfs files, in the order they should be restored.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Snapshot {
+    /// Seconds since the Unix epoch when the snapshot was taken.
+    created: u64,
+    entries: Vec<controller::PlannedWrite>,
+}
+
+fn run_snapshot(location: &ConfigLocation, file: &path::Path) {
+    let controller = init_controller(location, true);
+    let snapshot = Snapshot {
+        created: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+        entries: controller.snapshot(),
+    };
+    let s = match serde_json::to_string_pretty(&snapshot) {
+        Ok(s) => s,
         Err(e) => {
-            log::error!("{e}");
+            log::error!("Failed to serialize snapshot: {e}");
             process::exit(1);
         }
     };
-    if epp_files.is_empty() {
-        log::error!("Could not find any valid EPP files. Exiting.");
+    if file == path::Path::new("-") {
+        println!("{s}");
+        return;
+    }
+    if let Err(e) = fs::write(file, s + "\n") {
+        log::error!("Could not write snapshot to {file:?}: {e}");
         process::exit(1);
     }
-    let governor_files = generate_cpu_core_gorvernor_paths(&epp_files);
-    if epp_files.is_empty() {
-        log::error!("Could not find any valid governor files. Exiting.");
+    log::info!("Saved {} values to {file:?}.", snapshot.entries.len());
+}
+
+fn run_restore(file: &path::Path, dry_run: bool) {
+    let snapshot: Snapshot = match fs::read_to_string(file)
+        .map_err(|e| e.to_string())
+        .and_then(|s| serde_json::from_str(&s).map_err(|e| e.to_string()))
+    {
+        Ok(s) => s,
+        Err(e) => {
+            log::error!("Could not read snapshot from {file:?}: {e}");
+            process::exit(1);
+        }
+    };
+    log::info!("Restoring {} values from {file:?}.", snapshot.entries.len());
+    let retry = config::RetryConfig::default();
+    controller::apply_plan(&snapshot.entries, dry_run, &retry, None);
+}
+
+fn run_statusbar(format: &statusbar::Format, interval: Option<u64>) {
+    let cpufreq_path = path::Path::new(sysfs::CPUFREQ_PATH);
+    // A bar should keep showing the sysfs values even if D-Bus is unavailable.
+    let conn = zbus::blocking::Connection::system();
+    let proxy = match &conn {
+        Ok(conn) => ppd_proxy(conn).ok(),
+        Err(e) => {
+            log::warn!("Could not connect to system bus: {e}");
+            None
+        }
+    };
+    let interval = interval.map(Duration::from_secs);
+    if let Err(e) = statusbar::run(cpufreq_path, proxy.as_ref(), format, interval) {
+        log::error!("Failed to write status: {e}");
         process::exit(1);
     }
-    let config = match read_config() {
+}
+
+/// Bash completion of the `select` argument, wrapping the function generated by clap.
+const BASH_PROFILE_COMPLETION: &str = r#"
+_pstate_update_select() {
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+    if [[ ${COMP_WORDS[COMP_CWORD-1]} == select && ${COMP_WORDS[COMP_CWORD-2]} != help \
+        && ${cur} != -* ]]; then
+        COMPREPLY=( $(compgen -W "$(pstate_update profile-names 2>/dev/null)" -- "${cur}") )
+        return 0
+    fi
+    _pstate_update "$@"
+}
+"#;
+
+/// Zsh completion function for the `select` argument, used as its `_arguments` action.
+const ZSH_PROFILE_COMPLETION: &str = r#"(( $+functions[_pstate_update_profiles] )) ||
+_pstate_update_profiles() {
+    local -a profiles
+    profiles=(${(f)"$(pstate_update profile-names 2>/dev/null)"})
+    compadd -a profiles
+}
+"#;
+
+/// Fish completion of the `select` argument.
+const FISH_PROFILE_COMPLETION: &str = "complete -c pstate_update \
+    -n \"__fish_pstate_update_using_subcommand select\" \
+    -f -a \"(pstate_update profile-names 2>/dev/null)\"
+";
+
+fn run_completions(shell: clap_complete::Shell) {
+    print!("{}", completion_script(shell));
+}
+
+fn completion_script(shell: clap_complete::Shell) -> String {
+    let mut cmd = Cli::command();
+    let name = cmd.get_name().to_string();
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut cmd, name, &mut script);
+    let script = String::from_utf8_lossy(&script).into_owned();
+    // clap only knows the arguments, so hook the profile names of the config into the
+    // scripts of the shells that can call back into pstate_update while completing.
+    match shell {
+        clap_complete::Shell::Bash => {
+            script.replace(
+                "complete -F _pstate_update ",
+                "complete -F _pstate_update_select ",
+            ) + BASH_PROFILE_COMPLETION
+        }
+        // The function goes after the `#compdef` line, and the `select` argument is found
+        // by its help text.
+        clap_complete::Shell::Zsh => script
+            .replacen('\n', &format!("\n{ZSH_PROFILE_COMPLETION}"), 1)
+            .replace(
+                "section in the config:_default'",
+                "section in the config:_pstate_update_profiles'",
+            ),
+        clap_complete::Shell::Fish => script + FISH_PROFILE_COMPLETION,
+        _ => script,
+    }
+}
+
+fn run_profile_names(location: &ConfigLocation) {
+    let config = load_config_or_exit(location);
+    for name in config.profile.keys() {
+        println!("{name}");
+    }
+}
+
+fn run_watch(json: bool) {
+    let result = service::watch(|entry| {
+        if json {
+            match serde_json::to_string(&entry) {
+                Ok(s) => println!("{s}"),
+                Err(e) => log::error!("Failed to serialize event as JSON: {e}"),
+            }
+        } else {
+            println!("{entry}");
+        }
+    });
+    if let Err(e) = result {
+        log::error!("Failed to watch daemon: {e}");
+        process::exit(1);
+    }
+}
+
+/// Effective configuration and the files and environment variables it was merged from.
+#[derive(serde::Serialize)]
+struct ConfigReport {
+    embedded_default: bool,
+    sources: Vec<path::PathBuf>,
+    env_overrides: Vec<String>,
+    cli_overrides: Vec<String>,
+    config: toml::Table,
+    /// Origin of each value by dotted key path, when requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    origins: Option<BTreeMap<String, config::Origin>>,
+}
+
+/// Parse a `key=value` command-line override.
+fn parse_override(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(k, v)| (k.trim().to_string(), v.to_string()))
+        .ok_or_else(|| format!("expected KEY=VALUE, got `{s}`"))
+}
+
+/// Every value in the table that is not a table itself, with its dotted key path.
+fn flatten_table<'a>(
+    prefix: &str,
+    table: &'a toml::Table,
+    out: &mut Vec<(String, &'a toml::Value)>,
+) {
+    for (key, value) in table {
+        let key = format!("{prefix}{key}");
+        match value {
+            toml::Value::Table(t) => flatten_table(&format!("{key}."), t, out),
+            _ => out.push((key, value)),
+        }
+    }
+}
+
+fn run_config_show(location: &ConfigLocation, origin: bool, effective: bool, json: bool) {
+    let merged = match config::read_merged(location) {
+        Ok(m) => m,
+        Err(e) => {
+            log::error!("{e}");
+            process::exit(1);
+        }
+    };
+    if effective {
+        let config = match config::parse_table(merged.table) {
+            Ok(c) => c,
+            Err(e) => {
+                log::error!("{e}");
+                process::exit(1);
+            }
+        };
+        if json {
+            print_json(&config);
+            return;
+        }
+        match toml::to_string(&config) {
+            Ok(s) => print!("{s}"),
+            Err(e) => {
+                log::error!("Failed to serialize config: {e}");
+                process::exit(1);
+            }
+        }
+        return;
+    }
+    if json {
+        print_json(&ConfigReport {
+            embedded_default: merged.embedded_default,
+            sources: merged.sources,
+            env_overrides: merged.env_overrides,
+            cli_overrides: merged.cli_overrides,
+            config: merged.table,
+            origins: origin.then_some(merged.origins),
+        });
+        return;
+    }
+    println!("# Merged from, in order of increasing precedence:");
+    if merged.embedded_default {
+        println!("#   (embedded default)");
+    }
+    for source in &merged.sources {
+        println!("#   {}", source.display());
+    }
+    for name in &merged.env_overrides {
+        println!("#   ${name}");
+    }
+    for flag in &merged.cli_overrides {
+        println!("#   --override {flag}");
+    }
+    if origin {
+        let mut values = Vec::new();
+        flatten_table("", &merged.table, &mut values);
+        for (key, value) in values {
+            match merged.origins.get(&key) {
+                Some(o) => println!("{key} = {value}  # {}: {}", o.layer, o.source),
+                None => println!("{key} = {value}"),
+            }
+        }
+        return;
+    }
+    match toml::to_string(&merged.table) {
+        Ok(s) => print!("{s}"),
+        Err(e) => {
+            log::error!("Failed to serialize config: {e}");
+            process::exit(1);
+        }
+    }
+}
+
+fn run_config_set(
+    location: &ConfigLocation,
+    key: &str,
+    value: &str,
+    no_reload: bool,
+    dry_run: bool,
+) {
+    let Some(file) = location.resolve() else {
+        log::error!("No config file found. Create one with `pstate_update init` first.");
+        process::exit(1);
+    };
+    if file.extension().is_some_and(|e| e != "toml") {
+        log::error!("Only TOML config files can be edited, not {file:?}.");
+        process::exit(1);
+    }
+    if dry_run {
+        println!("Would set {key} = {value} in {}.", file.display());
+        return;
+    }
+    let contents = match config::edit_key(&file, key, value) {
         Ok(c) => c,
         Err(e) => {
             log::error!("{e}");
             process::exit(1);
         }
     };
+    if let Err(e) = config::check_edit(location, &file, &contents) {
+        log::error!("{e}");
+        log::error!("Left {file:?} unchanged.");
+        process::exit(1);
+    }
+    if let Err(e) = config::write_atomically(&file, &contents) {
+        log::error!("Could not write {file:?}: {e}");
+        process::exit(1);
+    }
+    println!("Set {key} = {value} in {}.", file.display());
+    warn_shadowed(location, &file, key);
+    if no_reload {
+        return;
+    }
+    match service::reload_daemon() {
+        Ok(pid) => println!("Asked the daemon (pid {pid}) to reload its config."),
+        Err(e) => match rpc::call("reload", serde_json::Value::Null) {
+            Ok(_) => println!("Asked the daemon to reload its config through its control socket."),
+            Err(e2) => println!("Could not reach the daemon ({e}; {e2}), so it was not reloaded."),
+        },
+    }
+}
 
-    let controller = EPPController {
-        epp_core_files: epp_files,
-        epp_config: config.epp,
-        governor_core_files: governor_files,
-        governor_config: config.scaling_governor,
+/// Warn when a layer above the edited file, like a drop-in, the user config, an environment
+/// variable or `--override`, sets the key as well, so the new value does not take effect.
+fn warn_shadowed(location: &ConfigLocation, file: &path::Path, key: &str) {
+    let Ok(merged) = config::read_merged(location) else {
+        return;
     };
-    loop {
-        match controller.run() {
-            Ok(()) => {
-                log::info!("Controller finished without error. Respawning.");
-            }
+    let below = format!("{key}.");
+    let file = file.display().to_string();
+    let mut shadowing: Vec<_> = merged
+        .origins
+        .iter()
+        .filter(|(k, _)| *k == key || k.starts_with(&below))
+        .map(|(_, origin)| (origin.layer.to_string(), &origin.source))
+        .filter(|(_, source)| **source != file)
+        .collect();
+    shadowing.sort();
+    shadowing.dedup();
+    for (layer, source) in shadowing {
+        log::warn!(
+            "{key} is also set by the {layer} layer in {source}, which takes precedence \
+             over {file}."
+        );
+    }
+}
+
+fn run_notify(summary: &str, body: &str, urgency: notify::Urgency) {
+    if let Err(e) = notify::send(summary, body, urgency) {
+        log::error!("Failed to show notification: {e}");
+        process::exit(1);
+    }
+}
+
+fn run_gamemode_watch() {
+    if let Err(e) = gamemode::watch() {
+        log::error!("Failed to follow GameMode: {e}");
+        process::exit(1);
+    }
+}
+
+fn run_screensaver_watch() {
+    if let Err(e) = screensaver::watch() {
+        log::error!("Failed to follow the screen saver: {e}");
+        process::exit(1);
+    }
+}
+
+fn run_monitor() {
+    let cpufreq_path = path::Path::new(sysfs::CPUFREQ_PATH);
+    // The monitor is still useful without D-Bus, so only warn if we cannot connect.
+    let conn = zbus::blocking::Connection::system();
+    let proxy = match &conn {
+        Ok(conn) => match ppd_proxy(conn) {
+            Ok(p) => Some(p),
             Err(e) => {
-                log::error!("Encountered error. Exiting. {e}");
-                process::exit(1);
+                log::warn!("Could not create power-profiles-daemon proxy: {e}");
+                None
             }
+        },
+        Err(e) => {
+            log::warn!("Could not connect to system bus: {e}");
+            None
+        }
+    };
+    if let Err(e) = monitor::Monitor::new(cpufreq_path, proxy).run() {
+        log::error!("Monitor failed: {e}");
+        process::exit(1);
+    }
+}
+
+fn main() {
+    let env = env_logger::Env::new().default_filter_or("info");
+    env_logger::init_from_env(env);
+
+    let cli = Cli::parse();
+    let daemon = Command::Daemon {
+        exit_when_idle: None,
+    };
+    let command = cli.command.unwrap_or(daemon);
+    let location = ConfigLocation {
+        file: cli.config,
+        search_path: cli.config_search_path,
+        overrides: cli.overrides,
+        user_layers: !matches!(
+            command,
+            Command::Daemon { .. } | Command::Set { .. } | Command::Oneshot { .. }
+        ),
+    };
+    match command {
+        Command::Daemon { exit_when_idle } => run_daemon(&location, cli.dry_run, exit_when_idle),
+        Command::Status => run_status(&location, cli.json),
+        Command::Set { profile } => run_set(&location, &profile, cli.dry_run),
+        Command::Oneshot { fallback } => run_oneshot(&location, fallback, cli.dry_run),
+        Command::Diff { profile, all } => run_diff(&location, profile, all, cli.json),
+        Command::List => run_list(cli.json),
+        Command::Cycle { reverse } => run_cycle(reverse, cli.dry_run),
+        Command::Hold { profile, reason } => run_hold(&profile, &reason),
+        Command::Release { cookie } => run_release(cookie),
+        Command::Select { name, clear: _ } => run_select(&location, name.as_deref()),
+        Command::Explain { profile } => run_explain(&location, &profile, cli.json),
+        Command::Snapshot { file } => run_snapshot(&location, &file),
+        Command::Restore { file } => run_restore(&file, cli.dry_run),
+        Command::Init { output, force } => run_init(&output, force, cli.dry_run),
+        Command::Import {
+            from,
+            input,
+            output,
+            force,
+        } => run_import(from, input.as_deref(), &output, force, cli.dry_run),
+        Command::Doctor => run_doctor(cli.json),
+        Command::Notify {
+            summary,
+            body,
+            urgency,
+        } => run_notify(&summary, &body, urgency),
+        Command::GamemodeWatch => run_gamemode_watch(),
+        Command::ScreensaverWatch => run_screensaver_watch(),
+        Command::ProfileNames => run_profile_names(&location),
+        Command::Statusbar { format, interval } => run_statusbar(&format, interval),
+        Command::Completions { shell } => run_completions(shell),
+        Command::Introspect => print!("{}", service::introspection_xml()),
+        Command::Watch => run_watch(cli.json),
+        Command::Monitor => run_monitor(),
+        Command::Config {
+            command: ConfigCommand::Show { origin, effective },
+        } => run_config_show(&location, origin, effective, cli.json),
+        Command::Config {
+            command: ConfigCommand::PrintDefault,
+        } => print!("{}", config::DEFAULT_CONFIG),
+        Command::Config {
+            command:
+                ConfigCommand::Set {
+                    key,
+                    value,
+                    no_reload,
+                },
+        } => run_config_set(&location, &key, &value, no_reload, cli.dry_run),
+        Command::Validate { skip_hardware } => run_validate(&location, skip_hardware, cli.json),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caps(driver: &str, has_epp: bool) -> sysfs::PolicyCapabilities {
+        sysfs::PolicyCapabilities {
+            name: "policy0".to_string(),
+            cpus: Some("0".to_string()),
+            driver: Some(driver.to_string()),
+            available_epps: has_epp.then(|| {
+                ["default", "performance", "balance_performance", "power"]
+                    .map(String::from)
+                    .to_vec()
+            }),
+            has_epp,
+            available_governors: Some(vec!["powersave".to_string(), "performance".to_string()]),
+            min_freq_khz: None,
+            max_freq_khz: None,
+        }
+    }
+
+    #[test]
+    fn raw_epp_is_accepted_by_drivers_with_epp() {
+        let mut table: toml::Table = toml::from_str(config::DEFAULT_CONFIG).unwrap();
+        table["epp"]["balanced"] = toml::Value::Integer(64);
+        let config = config::parse_table(table).unwrap_or_else(|e| panic!("{e}"));
+        let condition = Condition::default();
+        let scope = condition.scope("policy0", &[0], None);
+        let problems = |caps| {
+            validate_profile(
+                &config,
+                &scope,
+                "balanced",
+                &PPDPowerProfile::Balanced,
+                &caps,
+            )
+        };
+        assert!(problems(caps("amd-pstate-epp", true)).is_empty());
+        assert!(problems(caps(sysfs::INTEL_PSTATE_DRIVER, true)).is_empty());
+        let problems = problems(caps("acpi-cpufreq", false));
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].knob, controller::EPP_KNOB);
+        assert_eq!(problems[0].value, "64");
+    }
+
+    #[test]
+    fn completions_complete_profile_names_of_select() {
+        use clap_complete::Shell;
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+            assert!(
+                completion_script(shell).contains("pstate_update profile-names"),
+                "{shell}"
+            );
         }
+        assert!(completion_script(Shell::Bash).contains("complete -F _pstate_update_select "));
+        assert!(completion_script(Shell::Zsh).contains(":_pstate_update_profiles' \\\n"));
     }
 }
@@ -0,0 +1,119 @@
+use std::io;
+use std::path;
+use std::time::Duration;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, Paragraph, Row, Table};
+use ratatui::Frame;
+
+use crate::sysfs;
+use crate::PowerProfilesDaemonManagerProxyBlocking;
+
+/// Interval between each refresh of the displayed values.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Live view of the per-policy cpufreq state and the active PPD profile.
+pub struct Monitor<'a> {
+    cpufreq_path: &'a path::Path,
+    proxy: Option<PowerProfilesDaemonManagerProxyBlocking<'a>>,
+    active_profile: String,
+    policies: Vec<sysfs::PolicyStatus>,
+}
+
+impl<'a> Monitor<'a> {
+    pub fn new(
+        cpufreq_path: &'a path::Path,
+        proxy: Option<PowerProfilesDaemonManagerProxyBlocking<'a>>,
+    ) -> Self {
+        Monitor {
+            cpufreq_path,
+            proxy,
+            active_profile: String::new(),
+            policies: Vec::new(),
+        }
+    }
+
+    /// Re-read all displayed values from sysfs and D-Bus.
+    fn refresh(&mut self) -> io::Result<()> {
+        self.policies = sysfs::read_all_policy_status(self.cpufreq_path)?;
+        self.active_profile = match &self.proxy {
+            Some(proxy) => match proxy.active_profile() {
+                Ok(p) => p,
+                Err(e) => format!("unknown ({e})"),
+            },
+            None => "unknown (not connected to D-Bus)".to_string(),
+        };
+        Ok(())
+    }
+
+    /// Run the UI until the user quits with `q` or `Esc`.
+    pub fn run(&mut self) -> io::Result<()> {
+        let mut terminal = ratatui::init();
+        let result = self.event_loop(&mut terminal);
+        ratatui::restore();
+        result
+    }
+
+    fn event_loop(&mut self, terminal: &mut ratatui::DefaultTerminal) -> io::Result<()> {
+        loop {
+            self.refresh()?;
+            terminal.draw(|frame| self.draw(frame))?;
+            if event::poll(REFRESH_INTERVAL)? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press
+                        && matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)
+                    {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [header, body, footer] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let profile = Paragraph::new(format!("Active profile: {}", self.active_profile))
+            .block(Block::bordered().title("power-profiles-daemon"));
+        frame.render_widget(profile, header);
+
+        let rows = self.policies.iter().map(|p| {
+            let freq = match p.cur_freq_khz {
+                Some(khz) => (khz / 1000).to_string(),
+                None => "-".to_string(),
+            };
+            Row::new(vec![
+                p.name.clone(),
+                p.cpus.clone().unwrap_or_else(|| "-".to_string()),
+                freq,
+                p.epp.clone().unwrap_or_else(|| "-".to_string()),
+                p.governor.clone().unwrap_or_else(|| "-".to_string()),
+            ])
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(10),
+                Constraint::Length(12),
+                Constraint::Length(11),
+                Constraint::Length(22),
+                Constraint::Min(12),
+            ],
+        )
+        .header(
+            Row::new(vec!["POLICY", "CPUS", "FREQ (MHz)", "EPP", "GOVERNOR"])
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .block(Block::bordered().title("cpufreq policies"));
+        frame.render_widget(table, body);
+
+        frame.render_widget(Paragraph::new("Press q or Esc to quit."), footer);
+    }
+}
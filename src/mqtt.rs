@@ -0,0 +1,180 @@
+use std::fs;
+use std::io;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rumqttc::{Client, LastWill, MqttOptions, Packet, Publish, QoS, TlsConfiguration, Transport};
+use serde_json::json;
+
+use crate::config::MqttConfig;
+use crate::controller::{AppliedValues, Event};
+use crate::power::PowerSource;
+use crate::PPDPowerProfile;
+
+/// How often the client pings the broker while idle.
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+/// How long to wait before reconnecting after losing the broker.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Requests that may be queued for the broker before publishing starts to drop reports.
+const QUEUE_CAPACITY: usize = 16;
+
+/// Connection to the MQTT broker. Applied profiles are published to `<topic>/state`,
+/// and whether the daemon runs to `<topic>/online`. Profiles are applied from
+/// `<topic>/set` and selected from `<topic>/select`.
+pub struct Publisher {
+    client: Client,
+    topic: String,
+}
+
+impl Publisher {
+    /// Connect to the broker in the background, reconnecting whenever the connection is
+    /// lost. Commands received from the broker are forwarded to the event loop through
+    /// `sender`. Fails if the configured CA file cannot be read.
+    pub fn connect(config: &MqttConfig, sender: mpsc::Sender<Event>) -> io::Result<Self> {
+        let topic = config
+            .topic
+            .clone()
+            .unwrap_or_else(|| format!("pstate_update/{}", hostname()));
+        let mut options = MqttOptions::new(topic.replace('/', "-"), &config.host, config.port);
+        options
+            .set_keep_alive(KEEP_ALIVE)
+            .set_last_will(LastWill::new(
+                format!("{topic}/online"),
+                "false",
+                QoS::AtLeastOnce,
+                true,
+            ));
+        if let Some(username) = &config.username {
+            options.set_credentials(username, config.password.as_deref().unwrap_or_default());
+        }
+        if config.tls {
+            let tls = match &config.ca {
+                Some(path) => TlsConfiguration::Simple {
+                    ca: fs::read(path).map_err(|e| {
+                        io::Error::new(e.kind(), format!("Cannot read the CA file {path:?}: {e}"))
+                    })?,
+                    alpn: None,
+                    client_auth: None,
+                },
+                None => TlsConfiguration::default(),
+            };
+            options.set_transport(Transport::tls_with_config(tls));
+        } else if config.password.is_some() {
+            log::warn!(
+                "The MQTT connection is not encrypted, so the password is sent in plain text. \
+                 Set tls = true in [mqtt] if the broker supports it."
+            );
+        }
+        let (client, mut connection) = Client::new(options, QUEUE_CAPACITY);
+        log::info!(
+            "Reporting to the MQTT broker at {}:{} under {topic}{}.",
+            config.host,
+            config.port,
+            if config.tls { " over TLS" } else { "" }
+        );
+        let (loop_client, loop_topic) = (client.clone(), topic.clone());
+        thread::spawn(move || {
+            let mut connected = true;
+            for notification in connection.iter() {
+                match notification {
+                    Ok(rumqttc::Event::Incoming(Packet::ConnAck(_))) => {
+                        log::info!("Connected to the MQTT broker.");
+                        connected = true;
+                        subscribe(&loop_client, &loop_topic);
+                    }
+                    Ok(rumqttc::Event::Incoming(Packet::Publish(message))) => {
+                        handle_command(&loop_topic, &message, &sender)
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        if connected {
+                            log::warn!("Cannot reach the MQTT broker, retrying: {e}");
+                            connected = false;
+                        }
+                        thread::sleep(RECONNECT_DELAY);
+                    }
+                }
+            }
+        });
+        Ok(Publisher { client, topic })
+    }
+
+    /// Report an applied profile as retained JSON on `<topic>/state`. Never blocks, so a
+    /// slow broker cannot hold up the event loop.
+    pub fn profile_applied(
+        &self,
+        profile: &str,
+        applied: &AppliedValues,
+        power_source: Option<PowerSource>,
+    ) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let state = json!({
+            "profile": profile,
+            "epp": applied.epp,
+            "governor": applied.governor,
+            "power_source": power_source.map(|s| s.to_string()),
+            "timestamp": timestamp,
+        });
+        let topic = format!("{}/state", self.topic);
+        if let Err(e) = self
+            .client
+            .try_publish(topic, QoS::AtLeastOnce, true, state.to_string())
+        {
+            log::warn!("Failed to publish the applied profile to MQTT: {e}");
+        }
+    }
+}
+
+/// Subscribe to the command topics and announce the daemon, which is needed again on
+/// every connection since the broker forgets clean sessions.
+fn subscribe(client: &Client, topic: &str) {
+    let result = client
+        .try_subscribe(format!("{topic}/set"), QoS::AtLeastOnce)
+        .and_then(|()| client.try_subscribe(format!("{topic}/select"), QoS::AtLeastOnce))
+        .and_then(|()| {
+            client.try_publish(format!("{topic}/online"), QoS::AtLeastOnce, true, "true")
+        });
+    if let Err(e) = result {
+        log::warn!("Failed to subscribe to MQTT commands: {e}");
+    }
+}
+
+/// Forward a command from the broker to the event loop. `<topic>/set` takes a built-in
+/// profile to apply once, and `<topic>/select` a `[profile]` section to select, or an
+/// empty message to clear the selection.
+fn handle_command(topic: &str, message: &Publish, sender: &mpsc::Sender<Event>) {
+    let Ok(payload) = std::str::from_utf8(&message.payload) else {
+        log::warn!(
+            "Ignoring MQTT message on {} that is not UTF-8.",
+            message.topic
+        );
+        return;
+    };
+    let payload = payload.trim();
+    let event = match message.topic.strip_prefix(topic) {
+        Some("/set") => match payload.parse::<PPDPowerProfile>() {
+            Ok(profile) => Event::ApplyRequested(profile),
+            Err(e) => {
+                log::warn!("Ignoring MQTT message on {}: {e}", message.topic);
+                return;
+            }
+        },
+        Some("/select") if payload.is_empty() => Event::ProfileSelected(None),
+        Some("/select") => Event::ProfileSelected(Some(payload.to_string())),
+        _ => return,
+    };
+    log::info!("Received {payload:?} on {}.", message.topic);
+    let _ = sender.send(event);
+}
+
+/// Host name of the machine, which tells machines apart on a shared broker.
+fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|h| h.trim().to_string())
+        .unwrap_or_else(|_| "localhost".to_string())
+}
@@ -0,0 +1,102 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use zbus::zvariant::Value;
+
+use crate::config::NotifyConfig;
+use crate::logind;
+
+/// Notification server of the desktop session.
+#[zbus::dbus_proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+pub trait Notifications {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: &[&str],
+        hints: HashMap<&str, Value<'_>>,
+        expire_timeout: i32,
+    ) -> zbus::Result<u32>;
+}
+
+/// Urgency levels of the desktop notification spec.
+#[derive(Clone, Copy, clap::ValueEnum, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Urgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+impl fmt::Display for Urgency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Urgency::Low => write!(f, "low"),
+            Urgency::Normal => write!(f, "normal"),
+            Urgency::Critical => write!(f, "critical"),
+        }
+    }
+}
+
+/// Show a notification through the session bus of the current user.
+pub fn send(summary: &str, body: &str, urgency: Urgency) -> zbus::Result<()> {
+    let conn = zbus::blocking::Connection::session()?;
+    let proxy = NotificationsProxyBlocking::new(&conn)?;
+    let hints = HashMap::from([("urgency", Value::U8(urgency as u8))]);
+    proxy.notify("pstate_update", 0, "", summary, body, &[], hints, -1)?;
+    Ok(())
+}
+
+/// Show a notification on every local desktop, in the background, by running
+/// `pstate_update notify` in each desktop session.
+pub fn notify_desktops(summary: &str, body: &str, urgency: Urgency) {
+    let commands = match zbus::block_on(logind::session_commands()) {
+        Ok(commands) => commands,
+        Err(e) => {
+            log::warn!("Cannot send notification, {e}");
+            return;
+        }
+    };
+    for (_, mut command) in commands {
+        let urgency = urgency.to_string();
+        command.args(["notify", "--urgency", &urgency, summary, body]);
+        match command.spawn() {
+            // Reap the child without holding up the caller.
+            Ok(mut child) => drop(thread::spawn(move || child.wait())),
+            Err(e) => log::warn!("Failed to send notification: {e}"),
+        }
+    }
+}
+
+/// Shows notifications about failures, at most one per configured interval so that a
+/// persistent problem does not flood the desktop.
+#[derive(Default)]
+pub struct ErrorNotifier {
+    last: Cell<Option<Instant>>,
+}
+
+impl ErrorNotifier {
+    pub fn notify(&self, config: &NotifyConfig, body: &str) {
+        if !config.errors {
+            return;
+        }
+        let interval = Duration::from_secs(config.error_interval_s);
+        if self.last.get().is_some_and(|t| t.elapsed() < interval) {
+            log::debug!("Not notifying about the failure, another one was shown recently.");
+            return;
+        }
+        self.last.set(Some(Instant::now()));
+        notify_desktops("pstate_update failed", body, Urgency::Critical);
+    }
+}
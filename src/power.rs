@@ -0,0 +1,69 @@
+use std::fmt;
+use std::path;
+
+use crate::sysfs;
+
+/// Root of the power supply class in sysfs.
+pub const POWER_SUPPLY_PATH: &str = "/sys/class/power_supply";
+
+/// Where the system currently draws its power from.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PowerSource {
+    Ac,
+    Battery,
+}
+
+impl PowerSource {
+    pub const ALL: [PowerSource; 2] = [PowerSource::Ac, PowerSource::Battery];
+}
+
+impl fmt::Display for PowerSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PowerSource::Ac => write!(f, "AC"),
+            PowerSource::Battery => write!(f, "battery"),
+        }
+    }
+}
+
+/// How urgently UPower warns about the battery running out.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BatteryWarning {
+    Low,
+    Critical,
+}
+
+impl fmt::Display for BatteryWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BatteryWarning::Low => write!(f, "low"),
+            BatteryWarning::Critical => write!(f, "critical"),
+        }
+    }
+}
+
+/// Determine the power source from the supplies in the given `power_supply` folder.
+/// The system is on battery when it has a system battery and no external supply is
+/// online. Systems without a battery are always on AC. Returns `None` if the folder
+/// cannot be read.
+pub fn read_power_source(power_supply_path: &path::Path) -> Option<PowerSource> {
+    let mut has_battery = false;
+    for entry in power_supply_path.read_dir().ok()? {
+        let Ok(entry) = entry else {
+            continue;
+        };
+        let dir = entry.path();
+        let read = |attr: &str| sysfs::read_value(&dir.join(attr)).ok();
+        match read("type").as_deref() {
+            // Batteries in peripherals like mice report a device scope.
+            Some("Battery") => has_battery |= read("scope").as_deref() != Some("Device"),
+            Some(_) if read("online").as_deref() == Some("1") => return Some(PowerSource::Ac),
+            _ => {}
+        }
+    }
+    Some(if has_battery {
+        PowerSource::Battery
+    } else {
+        PowerSource::Ac
+    })
+}
@@ -0,0 +1,41 @@
+use std::path;
+
+use crate::sysfs;
+
+/// Root of the powercap class in sysfs.
+pub const POWERCAP_PATH: &str = "/sys/class/powercap";
+
+/// Name of the RAPL constraint holding the sustained power limit (PL1).
+pub const PL1_CONSTRAINT: &str = "long_term";
+
+/// Name of the RAPL constraint holding the burst power limit (PL2).
+pub const PL2_CONSTRAINT: &str = "short_term";
+
+/// Find the RAPL package zones, e.g. `intel-rapl:0` and its MMIO twin `intel-rapl-mmio:0`.
+/// Subzones for cores or uncore are left out, since the limits are set per package.
+pub fn find_package_zones(powercap_path: &path::Path) -> Vec<path::PathBuf> {
+    let Ok(entries) = powercap_path.read_dir() else {
+        return Vec::new();
+    };
+    let mut zones: Vec<_> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with("intel-rapl"))
+        })
+        .filter(|p| sysfs::read_value(&p.join("name")).is_ok_and(|n| n.starts_with("package")))
+        .collect();
+    zones.sort();
+    zones
+}
+
+/// File of the given attribute, e.g. `power_limit_uw`, of the zone constraint with the
+/// given name. Returns `None` if the zone has no such constraint.
+pub fn constraint_file(zone: &path::Path, constraint: &str, attr: &str) -> Option<path::PathBuf> {
+    (0..)
+        .map(|i| (i, zone.join(format!("constraint_{i}_name"))))
+        .take_while(|(_, name_file)| name_file.exists())
+        .find(|(_, name_file)| sysfs::read_value(name_file).is_ok_and(|n| n == constraint))
+        .map(|(i, _)| zone.join(format!("constraint_{i}_{attr}")))
+}
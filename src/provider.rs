@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Mutex};
+
+use futures_util::lock::Mutex as AsyncMutex;
+use futures_util::StreamExt;
+use zbus::zvariant::{OwnedValue, Value};
+use zbus::{MatchRule, MessageStream, MessageType, SignalContext};
+
+use crate::controller::Event;
+use crate::executor;
+use crate::service;
+use crate::PPDPowerProfile;
+
+/// Well-known name of power-profiles-daemon, claimed in provider mode.
+pub const SERVICE_NAME: &str = "net.hadess.PowerProfiles";
+/// Object path of the power-profiles-daemon interface.
+pub const OBJECT_PATH: &str = "/net/hadess/PowerProfiles";
+/// Name power-profiles-daemon 0.20 and later claim as well, which newer desktops use.
+pub const UPOWER_SERVICE_NAME: &str = "org.freedesktop.UPower.PowerProfiles";
+/// Object path of the interface under `UPOWER_SERVICE_NAME`.
+pub const UPOWER_OBJECT_PATH: &str = "/org/freedesktop/UPower/PowerProfiles";
+/// Polkit action guarding profile switches, like power-profiles-daemon's
+/// `org.freedesktop.UPower.PowerProfiles.switch-profile`.
+pub const SWITCH_ACTION: &str = "org.pstate_update1.switch-profile";
+
+/// State shared by the interface under both names.
+struct Shared {
+    /// Channel to the controller's event loop.
+    sender: mpsc::Sender<Event>,
+    conn: zbus::Connection,
+    active_profile: Mutex<String>,
+}
+
+impl Shared {
+    /// Switch to a profile on behalf of the given caller, if polkit allows it.
+    async fn switch(&self, caller: &str, profile: String) -> zbus::fdo::Result<()> {
+        if !PPDPowerProfile::NAMES.contains(&profile.as_str()) {
+            return Err(zbus::fdo::Error::InvalidArgs(format!(
+                "Invalid profile name {profile:?}."
+            )));
+        }
+        service::authorize(&self.conn, caller, SWITCH_ACTION).await?;
+        self.sender
+            .send(Event::ActiveProfileChanged(profile.clone()))
+            .map_err(|_| zbus::fdo::Error::Failed("The daemon is shutting down.".to_string()))?;
+        *self.active_profile.lock().unwrap() = profile;
+        Ok(())
+    }
+
+    /// The available profiles, each with the driver implementing it.
+    fn profiles() -> Vec<HashMap<String, OwnedValue>> {
+        PPDPowerProfile::NAMES
+            .iter()
+            .map(|name| {
+                HashMap::from([
+                    ("Profile".to_string(), Value::from(*name).into()),
+                    ("Driver".to_string(), Value::from("pstate_update").into()),
+                ])
+            })
+            .collect()
+    }
+}
+
+/// `Set` calls for the `ActiveProfile` property of one interface. zbus does not tell
+/// property setters who called them, so the setter finds its caller among these by the
+/// profile set. Concurrent calls for the same profile may swap callers, which switches to
+/// that profile either way.
+struct SetCalls(AsyncMutex<MessageStream>);
+
+impl SetCalls {
+    fn new(conn: &zbus::Connection, interface: &str, path: &str) -> zbus::Result<Self> {
+        let rule = MatchRule::builder()
+            .msg_type(MessageType::MethodCall)
+            .interface("org.freedesktop.DBus.Properties")?
+            .member("Set")?
+            .path(path)?
+            .arg(0, interface)?
+            .arg(1, "ActiveProfile")?
+            .build();
+        let stream = executor::block_on_call(MessageStream::for_match_rule(rule, conn, None))?;
+        Ok(SetCalls(AsyncMutex::new(stream)))
+    }
+
+    /// Unique bus name of the caller that set the property to `profile`.
+    async fn caller(&self, profile: &str) -> zbus::fdo::Result<String> {
+        let mut calls = self.0.lock().await;
+        let caller = executor::call(async {
+            while let Some(msg) = calls.next().await {
+                let msg = msg?;
+                let Ok((_, _, value)) = msg.body::<(String, String, OwnedValue)>() else {
+                    continue;
+                };
+                if <&str>::try_from(&*value).is_ok_and(|v| v == profile) {
+                    if let Some(sender) = msg.header()?.sender()? {
+                        return Ok(sender.to_string());
+                    }
+                }
+            }
+            Err(zbus::Error::Failure(
+                "Set calls are no longer followed".to_string(),
+            ))
+        });
+        caller
+            .await
+            .map_err(|e| zbus::fdo::Error::AccessDenied(format!("Unknown caller: {e}")))
+    }
+}
+
+/// Declare the power-profiles-daemon interface under one of its names. Both share their
+/// state, so a change through one is announced on the other as well.
+macro_rules! power_profiles_interface {
+    ($(#[$meta:meta])* $ty:ident, $name:tt, $other_name:expr, $other_path:expr) => {
+        $(#[$meta])*
+        pub struct $ty {
+            shared: Arc<Shared>,
+            set_calls: SetCalls,
+        }
+
+        #[zbus::dbus_interface(name = $name)]
+        impl $ty {
+            /// The selected profile, one of `power-saver`, `balanced` and `performance`.
+            #[dbus_interface(property)]
+            async fn active_profile(&self) -> String {
+                self.shared.active_profile.lock().unwrap().clone()
+            }
+
+            #[dbus_interface(property)]
+            async fn set_active_profile(&self, profile: String) -> zbus::Result<()> {
+                let caller = self.set_calls.caller(&profile).await?;
+                self.shared.switch(&caller, profile.clone()).await?;
+                let ctxt = SignalContext::new(&self.shared.conn, $other_path)?;
+                let value = Value::from(profile);
+                zbus::fdo::Properties::properties_changed(
+                    &ctxt,
+                    zbus::names::InterfaceName::from_static_str_unchecked($other_name),
+                    &HashMap::from([("ActiveProfile", &value)]),
+                    &[],
+                )
+                .await?;
+                Ok(())
+            }
+
+            /// The available profiles, each with the driver implementing it.
+            #[dbus_interface(property)]
+            async fn profiles(&self) -> Vec<HashMap<String, OwnedValue>> {
+                Shared::profiles()
+            }
+
+            /// Why the performance profile is degraded. It never is here.
+            #[dbus_interface(property)]
+            async fn performance_degraded(&self) -> String {
+                String::new()
+            }
+
+            /// Actions run on profile changes besides the driver. There are none here.
+            #[dbus_interface(property)]
+            async fn actions(&self) -> Vec<String> {
+                Vec::new()
+            }
+        }
+    };
+}
+
+power_profiles_interface!(
+    /// Stand-in for power-profiles-daemon, so that desktop power sliders keep working on
+    /// systems without it. Profile changes are forwarded to the event loop like the ones
+    /// power-profiles-daemon would report.
+    PowerProfiles,
+    "net.hadess.PowerProfiles",
+    UPOWER_SERVICE_NAME,
+    UPOWER_OBJECT_PATH
+);
+
+power_profiles_interface!(
+    /// `PowerProfiles` under the name power-profiles-daemon 0.20 and later use.
+    UPowerPowerProfiles,
+    "org.freedesktop.UPower.PowerProfiles",
+    SERVICE_NAME,
+    OBJECT_PATH
+);
+
+/// Serve the power-profiles-daemon interface under both of its names on the given
+/// connection, starting out with `active_profile`, and claim the names. Fails if
+/// power-profiles-daemon already owns them.
+pub fn serve(
+    conn: &zbus::blocking::Connection,
+    sender: mpsc::Sender<Event>,
+    active_profile: &str,
+) -> zbus::Result<()> {
+    let shared = Arc::new(Shared {
+        sender,
+        conn: conn.inner().clone(),
+        active_profile: Mutex::new(active_profile.to_string()),
+    });
+    let iface = PowerProfiles {
+        shared: shared.clone(),
+        set_calls: SetCalls::new(conn.inner(), SERVICE_NAME, OBJECT_PATH)?,
+    };
+    conn.object_server().at(OBJECT_PATH, iface)?;
+    let iface = UPowerPowerProfiles {
+        shared,
+        set_calls: SetCalls::new(conn.inner(), UPOWER_SERVICE_NAME, UPOWER_OBJECT_PATH)?,
+    };
+    conn.object_server().at(UPOWER_OBJECT_PATH, iface)?;
+    for name in [SERVICE_NAME, UPOWER_SERVICE_NAME] {
+        let flags = zbus::fdo::RequestNameFlags::DoNotQueue.into();
+        executor::block_on_call(conn.inner().request_name_with_flags(name, flags)).map_err(
+            |e| match e {
+                zbus::Error::NameTaken => zbus::Error::Failure(format!(
+                    "{name} is already owned, probably by power-profiles-daemon"
+                )),
+                e => e,
+            },
+        )?;
+    }
+    log::info!(
+        "Providing {SERVICE_NAME} on {OBJECT_PATH} and {UPOWER_SERVICE_NAME} on \
+         {UPOWER_OBJECT_PATH} with {active_profile} active."
+    );
+    Ok(())
+}
@@ -0,0 +1,402 @@
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::controller::Event;
+use crate::systemd;
+use crate::PPDPowerProfile;
+
+/// Control socket of the daemon, for systems without D-Bus.
+pub const SOCKET_PATH: &str = "/run/pstate_update.sock";
+
+/// How long to wait for the daemon to answer a request.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a client may stay silent before its connection is closed.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Longest request line accepted, which is plenty for any of the methods.
+const MAX_LINE_LEN: u64 = 64 * 1024;
+
+/// Most connections served at once.
+const MAX_CONNECTIONS: usize = 16;
+
+// JSON-RPC 2.0 error codes. The ones above -32100 are the server errors defined here.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+const ACCESS_DENIED: i64 = -32000;
+
+/// State of the daemon returned by `get-status`.
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct Status {
+    /// Profile name most recently received from power-profiles-daemon.
+    pub active_profile: Option<String>,
+    /// `[profile]` section selected on top of the active profile.
+    pub selected_profile: Option<String>,
+    /// Current power source, if known.
+    pub power_source: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Bound on the connections a server handles at once, each on a thread of its own, so
+/// that clients cannot pile up threads.
+#[derive(Clone)]
+pub struct ConnectionLimit {
+    active: Arc<AtomicUsize>,
+    max: usize,
+}
+
+impl ConnectionLimit {
+    pub fn new(max: usize) -> Self {
+        ConnectionLimit {
+            active: Arc::new(AtomicUsize::new(0)),
+            max,
+        }
+    }
+
+    /// Take a slot for a new connection, or `None` if all are taken. The slot is given
+    /// back when it is dropped.
+    pub fn acquire(&self) -> Option<ConnectionSlot> {
+        let taken = self
+            .active
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                (n < self.max).then_some(n + 1)
+            });
+        taken.ok().map(|_| ConnectionSlot(self.active.clone()))
+    }
+}
+
+/// A connection counted against a `ConnectionLimit`.
+pub struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Listen for JSON-RPC 2.0 requests on the given socket, one per line, and answer each
+/// with a line of its own. Anyone may ask for the status, while `set-profile` and
+/// `reload` are only accepted from root. Requests are forwarded to the event loop
+/// through `sender`. When started by `pstate_update.socket`, the socket passed by systemd
+/// is served instead of binding a new one.
+pub fn serve(socket: &path::Path, sender: mpsc::Sender<Event>) -> io::Result<()> {
+    let listener = match systemd::listen_fds().first() {
+        Some(&fd) => {
+            // SAFETY: The socket unit passes only the listening control socket, which
+            // nothing else in the process owns.
+            let passed = unsafe { UnixListener::from_raw_fd(fd) };
+            // The passed descriptor would be inherited by hook commands, unlike a copy.
+            let listener = passed.try_clone()?;
+            log::info!("Serving the control API on the socket passed by systemd.");
+            listener
+        }
+        None => bind(socket)?,
+    };
+    let limit = ConnectionLimit::new(MAX_CONNECTIONS);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let Some(slot) = limit.acquire() else {
+                        log::warn!("Too many control connections, closing a new one.");
+                        continue;
+                    };
+                    let sender = sender.clone();
+                    thread::spawn(move || {
+                        handle_connection(stream, &sender);
+                        drop(slot);
+                    });
+                }
+                Err(e) => log::warn!("Failed to accept control connection: {e}"),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Bind the control socket at the given path, so that anyone may connect.
+fn bind(socket: &path::Path) -> io::Result<UnixListener> {
+    // A socket left behind by an earlier run would make binding fail.
+    match fs::remove_file(socket) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let listener = UnixListener::bind(socket)?;
+    fs::set_permissions(socket, fs::Permissions::from_mode(0o666))?;
+    log::info!("Serving the control API on {socket:?}.");
+    Ok(listener)
+}
+
+fn handle_connection(mut stream: UnixStream, sender: &mpsc::Sender<Event>) {
+    let peer = nix::sys::socket::getsockopt(
+        stream.as_raw_fd(),
+        nix::sys::socket::sockopt::PeerCredentials,
+    );
+    let uid = peer.ok().map(|c| c.uid());
+    if stream.set_read_timeout(Some(IDLE_TIMEOUT)).is_err() {
+        return;
+    }
+    let Ok(reader) = stream.try_clone() else {
+        return;
+    };
+    let mut reader = io::BufReader::new(reader);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match (&mut reader).take(MAX_LINE_LEN).read_line(&mut line) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
+        if !line.ends_with('\n') && line.len() as u64 == MAX_LINE_LEN {
+            let error = RpcError::new(INVALID_REQUEST, "Request line too long.");
+            let _ = writeln!(stream, "{}", response(Value::Null, Err(error)));
+            return;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (id, result) = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                let result = dispatch(&request, uid, sender);
+                (request.id, result)
+            }
+            Err(e) => (Value::Null, Err(RpcError::new(PARSE_ERROR, e.to_string()))),
+        };
+        if writeln!(stream, "{}", response(id, result)).is_err() {
+            return;
+        }
+    }
+}
+
+/// The JSON-RPC response to a request with the given ID.
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err(e) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {"code": e.code, "message": e.message},
+        }),
+    }
+}
+
+/// Ask the event loop for the daemon's status.
+pub fn request_status(sender: &mpsc::Sender<Event>) -> Result<Status, String> {
+    let (reply, status) = mpsc::channel();
+    sender
+        .send(Event::StatusRequested(reply))
+        .map_err(|_| "The daemon is shutting down.".to_string())?;
+    status
+        .recv_timeout(TIMEOUT)
+        .map_err(|_| "The daemon did not answer.".to_string())
+}
+
+fn dispatch(
+    request: &Request,
+    uid: Option<u32>,
+    sender: &mpsc::Sender<Event>,
+) -> Result<Value, RpcError> {
+    let send = |event| {
+        sender
+            .send(event)
+            .map_err(|_| RpcError::new(INTERNAL_ERROR, "The daemon is shutting down."))
+    };
+    let require_root = || match uid {
+        Some(0) => Ok(()),
+        _ => Err(RpcError::new(
+            ACCESS_DENIED,
+            format!("{} is only allowed for root.", request.method),
+        )),
+    };
+    match request.method.as_str() {
+        "get-status" => {
+            let status = request_status(sender).map_err(|e| RpcError::new(INTERNAL_ERROR, e))?;
+            serde_json::to_value(status).map_err(|e| RpcError::new(INTERNAL_ERROR, e.to_string()))
+        }
+        "set-profile" => {
+            require_root()?;
+            let profile = request
+                .params
+                .get("profile")
+                .and_then(|p| p.as_str())
+                .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Missing profile parameter."))?;
+            let profile: PPDPowerProfile = profile
+                .parse()
+                .map_err(|e: String| RpcError::new(INVALID_PARAMS, e))?;
+            send(Event::ApplyRequested(profile))?;
+            Ok(Value::Null)
+        }
+        "reload" => {
+            require_root()?;
+            send(Event::Reload)?;
+            Ok(Value::Null)
+        }
+        method => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("Unknown method {method}."),
+        )),
+    }
+}
+
+/// Call a method of the running daemon's control API and return its result.
+pub fn call(method: &str, params: Value) -> Result<Value, String> {
+    let mut stream = UnixStream::connect(SOCKET_PATH)
+        .map_err(|e| format!("could not connect to {SOCKET_PATH}: {e}"))?;
+    stream
+        .set_read_timeout(Some(TIMEOUT))
+        .map_err(|e| e.to_string())?;
+    let request = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+    writeln!(stream, "{request}").map_err(|e| e.to_string())?;
+    let mut line = String::new();
+    io::BufReader::new(&stream)
+        .read_line(&mut line)
+        .map_err(|e| e.to_string())?;
+    let response: Value = serde_json::from_str(&line).map_err(|e| e.to_string())?;
+    if let Some(error) = response.get("error") {
+        let message = error.get("message").and_then(|m| m.as_str());
+        return Err(message.unwrap_or("unknown error").to_string());
+    }
+    Ok(response.get("result").cloned().unwrap_or(Value::Null))
+}
+
+/// Ask the running daemon for its status through the control socket.
+pub fn fetch_status() -> Result<Status, String> {
+    let status = call("get-status", Value::Null)?;
+    serde_json::from_value(status).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, params: Value) -> Request {
+        Request {
+            id: json!(1),
+            method: method.to_string(),
+            params,
+        }
+    }
+
+    fn error_code(result: Result<Value, RpcError>) -> i64 {
+        result.expect_err("request should fail").code
+    }
+
+    #[test]
+    fn dispatch_forwards_root_requests_to_event_loop() {
+        let (sender, events) = mpsc::channel();
+        let set_profile = request("set-profile", json!({"profile": "power-saver"}));
+        assert_eq!(
+            dispatch(&set_profile, Some(0), &sender).ok(),
+            Some(Value::Null)
+        );
+        assert!(matches!(
+            events.try_recv(),
+            Ok(Event::ApplyRequested(PPDPowerProfile::PowerSaver))
+        ));
+        assert!(dispatch(&request("reload", Value::Null), Some(0), &sender).is_ok());
+        assert!(matches!(events.try_recv(), Ok(Event::Reload)));
+    }
+
+    #[test]
+    fn dispatch_answers_status_from_event_loop() {
+        let (sender, events) = mpsc::channel();
+        let event_loop = thread::spawn(move || {
+            let Ok(Event::StatusRequested(reply)) = events.recv() else {
+                panic!("expected a status request");
+            };
+            let status = Status {
+                active_profile: Some("balanced".to_string()),
+                selected_profile: None,
+                power_source: Some("ac".to_string()),
+            };
+            reply.send(status).unwrap();
+        });
+        let status = dispatch(&request("get-status", Value::Null), Some(1000), &sender);
+        event_loop.join().unwrap();
+        assert_eq!(
+            status.ok(),
+            Some(json!({
+                "active_profile": "balanced",
+                "selected_profile": null,
+                "power_source": "ac",
+            }))
+        );
+    }
+
+    #[test]
+    fn dispatch_rejects_invalid_requests() {
+        let (sender, events) = mpsc::channel();
+        let set_profile = request("set-profile", json!({"profile": "performance"}));
+        assert_eq!(
+            error_code(dispatch(&set_profile, Some(1000), &sender)),
+            ACCESS_DENIED
+        );
+        assert_eq!(
+            error_code(dispatch(&set_profile, None, &sender)),
+            ACCESS_DENIED
+        );
+        let reload = request("reload", Value::Null);
+        assert_eq!(
+            error_code(dispatch(&reload, Some(1000), &sender)),
+            ACCESS_DENIED
+        );
+        let missing = request("set-profile", json!({}));
+        assert_eq!(
+            error_code(dispatch(&missing, Some(0), &sender)),
+            INVALID_PARAMS
+        );
+        let unknown = request("set-profile", json!({"profile": "turbo"}));
+        assert_eq!(
+            error_code(dispatch(&unknown, Some(0), &sender)),
+            INVALID_PARAMS
+        );
+        let method = request("shutdown", Value::Null);
+        assert_eq!(
+            error_code(dispatch(&method, Some(0), &sender)),
+            METHOD_NOT_FOUND
+        );
+        assert!(events.try_recv().is_err(), "no event should be sent");
+
+        drop(events);
+        let reload = request("reload", Value::Null);
+        assert_eq!(
+            error_code(dispatch(&reload, Some(0), &sender)),
+            INTERNAL_ERROR
+        );
+    }
+}
@@ -0,0 +1,48 @@
+/// Screen saver of a desktop session, as implemented by KDE, Xfce and others. GNOME Shell
+/// implements the same interface as `org.gnome.ScreenSaver`.
+#[zbus::dbus_proxy(
+    interface = "org.freedesktop.ScreenSaver",
+    default_service = "org.freedesktop.ScreenSaver",
+    default_path = "/org/freedesktop/ScreenSaver"
+)]
+pub trait ScreenSaver {
+    /// Whether the screen saver is active, which locks the screen on most desktops.
+    fn get_active(&self) -> zbus::Result<bool>;
+
+    /// Emitted when the screen saver is activated or deactivated.
+    #[dbus_proxy(signal)]
+    fn active_changed(&self, active: bool) -> zbus::Result<()>;
+}
+
+/// Bus name and object path of GNOME Shell's screen saver. The name is its interface too.
+const GNOME_SCREEN_SAVER: (&str, &str) = ("org.gnome.ScreenSaver", "/org/gnome/ScreenSaver");
+
+/// Print whether the screen saver is active, and again on every change.
+fn follow(proxy: &ScreenSaverProxyBlocking) -> zbus::Result<()> {
+    // Subscribe before reading, so that a change cannot go unnoticed in between.
+    let changes = proxy.receive_active_changed()?;
+    println!("{}", u8::from(proxy.get_active()?));
+    for signal in changes {
+        println!("{}", u8::from(signal.args()?.active));
+    }
+    Ok(())
+}
+
+/// Print 1 if the screen is locked in the current session and 0 otherwise, and again on
+/// every change, one number per line. GNOME's screen saver is preferred, since GNOME only
+/// emits its signals under its own name. Runs until the session bus goes away or stdout
+/// is closed.
+pub fn watch() -> zbus::Result<()> {
+    let conn = zbus::blocking::Connection::session()?;
+    let (name, path) = GNOME_SCREEN_SAVER;
+    let gnome = ScreenSaverProxyBlocking::builder(&conn)
+        .destination(name)?
+        .path(path)?
+        .interface(name)?
+        .build()?;
+    let dbus = zbus::blocking::fdo::DBusProxy::new(&conn)?;
+    if dbus.name_has_owner(name.try_into()?)? {
+        return follow(&gnome);
+    }
+    follow(&ScreenSaverProxyBlocking::new(&conn)?)
+}
@@ -0,0 +1,758 @@
+use std::collections::HashMap;
+use std::path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use pstate_update_client::{
+    DaemonProxyBlocking, ProfileApplied, ProfileReceived, ValueWritten, WriteFailed,
+};
+pub use pstate_update_client::{INTERFACE_NAME, OBJECT_PATH, SERVICE_NAME};
+use zbus::zvariant::Value;
+use zbus::{Interface, SignalContext};
+
+use crate::config::{self, Config, UserOverride};
+use crate::controller::{Event, PlannedWrite};
+use crate::executor;
+use crate::PPDPowerProfile;
+
+/// Polkit action guarding `SelectProfile` and `ClearProfile`.
+pub const SELECT_ACTION: &str = "org.pstate_update1.select-profile";
+/// Polkit action guarding `SetProfileOverride` and `ClearOverride`.
+pub const OVERRIDE_ACTION: &str = "org.pstate_update1.set-override";
+/// Polkit action guarding `ApplyProfile`.
+pub const APPLY_ACTION: &str = "org.pstate_update1.apply-profile";
+/// Polkit action guarding `Inhibit`.
+pub const INHIBIT_ACTION: &str = "org.pstate_update1.inhibit";
+/// Polkit action guarding `Reset` of the statistics.
+pub const RESET_STATISTICS_ACTION: &str = "org.pstate_update1.reset-statistics";
+
+#[zbus::dbus_proxy(
+    interface = "org.freedesktop.PolicyKit1.Authority",
+    default_service = "org.freedesktop.PolicyKit1",
+    default_path = "/org/freedesktop/PolicyKit1/Authority"
+)]
+trait PolkitAuthority {
+    #[allow(clippy::type_complexity)]
+    fn check_authorization(
+        &self,
+        subject: &(&str, HashMap<&str, Value<'_>>),
+        action_id: &str,
+        details: HashMap<&str, &str>,
+        flags: u32,
+        cancellation_id: &str,
+    ) -> zbus::Result<(bool, bool, HashMap<String, String>)>;
+}
+
+/// Lets polkit ask the user to authenticate instead of denying right away.
+const POLKIT_ALLOW_USER_INTERACTION: u32 = 1;
+
+/// How long to wait for polkit, which includes the time the user takes to authenticate.
+const POLKIT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Ask polkit whether the sender of a method call may perform the given action.
+async fn check_authorization(
+    conn: &zbus::Connection,
+    header: &zbus::MessageHeader<'_>,
+    action: &str,
+) -> zbus::fdo::Result<()> {
+    let sender = header
+        .sender()?
+        .ok_or_else(|| zbus::fdo::Error::AccessDenied("Unknown sender.".to_string()))?;
+    authorize(conn, sender.as_str(), action).await
+}
+
+/// Ask polkit whether the given bus name may perform the given action.
+pub async fn authorize(
+    conn: &zbus::Connection,
+    sender: &str,
+    action: &str,
+) -> zbus::fdo::Result<()> {
+    let subject = (
+        "system-bus-name",
+        HashMap::from([("name", Value::from(sender))]),
+    );
+    let (authorized, _, _) = executor::call_within(POLKIT_TIMEOUT, async {
+        PolkitAuthorityProxy::new(conn)
+            .await?
+            .check_authorization(
+                &subject,
+                action,
+                HashMap::new(),
+                POLKIT_ALLOW_USER_INTERACTION,
+                "",
+            )
+            .await
+    })
+    .await?;
+    if authorized {
+        Ok(())
+    } else {
+        Err(zbus::fdo::Error::AccessDenied(format!(
+            "{sender} is not authorized for {action}."
+        )))
+    }
+}
+
+/// State of the daemon exposed as properties of its D-Bus interface.
+#[derive(Clone, Default, PartialEq)]
+pub struct DaemonState {
+    /// Name of the profile most recently applied.
+    pub active_profile: String,
+    /// EPPs written by the most recent application, comma-separated if policies differ.
+    pub applied_epp: String,
+    /// Governors written by the most recent application, comma-separated if policies
+    /// differ.
+    pub applied_governor: String,
+    /// When the most recent application finished, in seconds since the Unix epoch.
+    pub last_apply_timestamp: u64,
+    /// Number of CPU cores whose EPP the daemon manages.
+    pub managed_core_count: u32,
+}
+
+/// An application's lock of the daemon to a profile, taken with `Inhibit`.
+#[derive(Clone)]
+pub struct Inhibitor {
+    /// Handed to the application for `Uninhibit`.
+    pub cookie: u32,
+    /// Unique bus name of the application, which releases the lock by leaving the bus.
+    pub owner: String,
+    /// Built-in profile or `[profile]` section to apply.
+    pub profile: String,
+    /// Why the application took the lock, for the log.
+    pub reason: String,
+}
+
+/// D-Bus interface exposed by the running daemon. Its state sits behind mutexes rather
+/// than being changed through `&mut self`, since zbus keeps the interface locked while a
+/// method runs, and `Events` must not wait for a method that waits for polkit.
+pub struct DaemonInterface {
+    /// Channel to the controller's event loop.
+    sender: mpsc::Sender<Event>,
+    state: Mutex<DaemonState>,
+    /// The config the controller uses, to check the profiles of requests against.
+    config: Mutex<Arc<Config>>,
+    /// Locks in the order they were taken.
+    inhibitors: Mutex<Vec<Inhibitor>>,
+    next_cookie: AtomicU32,
+}
+
+impl DaemonInterface {
+    fn new(sender: mpsc::Sender<Event>, config: Arc<Config>) -> Self {
+        DaemonInterface {
+            sender,
+            state: Mutex::new(DaemonState::default()),
+            config: Mutex::new(config),
+            inhibitors: Mutex::new(Vec::new()),
+            next_cookie: AtomicU32::new(0),
+        }
+    }
+
+    fn send(&self, event: Event) -> zbus::fdo::Result<()> {
+        self.sender
+            .send(event)
+            .map_err(|_| zbus::fdo::Error::Failed("The daemon is shutting down.".to_string()))
+    }
+
+    /// Forward the current locks to the event loop and announce them on the bus.
+    async fn inhibitors_updated(&self, ctxt: &SignalContext<'_>) -> zbus::fdo::Result<()> {
+        Events::log_emit_error(self.inhibitors_changed(ctxt).await);
+        let inhibitors = self.inhibitors.lock().unwrap().clone();
+        self.send(Event::InhibitorsChanged(inhibitors))
+    }
+}
+
+#[zbus::dbus_interface(name = "org.pstate_update1.Daemon")]
+impl DaemonInterface {
+    /// Layer a `[profile]` section from the config on top of the profile reported by
+    /// power-profiles-daemon.
+    async fn select_profile(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: zbus::MessageHeader<'_>,
+        name: String,
+    ) -> zbus::fdo::Result<()> {
+        check_authorization(conn, &header, SELECT_ACTION).await?;
+        self.send(Event::ProfileSelected(Some(name)))
+    }
+
+    /// Go back to the mapping of the profile reported by power-profiles-daemon alone.
+    async fn clear_profile(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: zbus::MessageHeader<'_>,
+    ) -> zbus::fdo::Result<()> {
+        check_authorization(conn, &header, SELECT_ACTION).await?;
+        self.send(Event::ProfileSelected(None))
+    }
+
+    /// Force an EPP and governor on every profile until `ClearOverride` is called, e.g. to
+    /// run a build at full performance. An empty string leaves the knob to the mapping.
+    async fn set_profile_override(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: zbus::MessageHeader<'_>,
+        epp: &str,
+        governor: &str,
+    ) -> zbus::fdo::Result<()> {
+        check_authorization(conn, &header, OVERRIDE_ACTION).await?;
+        let user_override =
+            UserOverride::parse(epp, governor).map_err(zbus::fdo::Error::InvalidArgs)?;
+        self.send(Event::OverrideChanged(Some(user_override)))
+    }
+
+    /// Go back to the mapping after `SetProfileOverride`.
+    async fn clear_override(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: zbus::MessageHeader<'_>,
+    ) -> zbus::fdo::Result<()> {
+        check_authorization(conn, &header, OVERRIDE_ACTION).await?;
+        self.send(Event::OverrideChanged(None))
+    }
+
+    /// Apply the mapping of a built-in profile once, like `pstate_update set`. The next
+    /// profile change replaces it.
+    async fn apply_profile(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: zbus::MessageHeader<'_>,
+        profile: &str,
+    ) -> zbus::fdo::Result<()> {
+        check_authorization(conn, &header, APPLY_ACTION).await?;
+        let profile: PPDPowerProfile = profile.parse().map_err(zbus::fdo::Error::InvalidArgs)?;
+        self.send(Event::ApplyRequested(profile))
+    }
+
+    /// Lock the daemon to the mapping of a built-in profile or `[profile]` section, e.g.
+    /// for a benchmark, until `Uninhibit` is called with the returned cookie or the caller
+    /// leaves the bus. The most recent lock wins while several are in place.
+    async fn inhibit(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: zbus::MessageHeader<'_>,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        profile: String,
+        reason: String,
+    ) -> zbus::fdo::Result<u32> {
+        check_authorization(conn, &header, INHIBIT_ACTION).await?;
+        if let Err(e) = self.config.lock().unwrap().resolve_profile(&profile) {
+            return Err(zbus::fdo::Error::InvalidArgs(e));
+        }
+        let owner = header
+            .sender()?
+            .ok_or_else(|| zbus::fdo::Error::AccessDenied("Unknown sender.".to_string()))?
+            .to_string();
+        let cookie = self.next_cookie.fetch_add(1, Ordering::Relaxed) + 1;
+        log::info!("{owner} inhibits profile changes with {profile}: {reason}");
+        self.inhibitors.lock().unwrap().push(Inhibitor {
+            cookie,
+            owner,
+            profile,
+            reason,
+        });
+        self.inhibitors_updated(&ctxt).await?;
+        Ok(cookie)
+    }
+
+    /// Release a lock taken with `Inhibit`. Only the application that took it may.
+    async fn uninhibit(
+        &self,
+        #[zbus(header)] header: zbus::MessageHeader<'_>,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        cookie: u32,
+    ) -> zbus::fdo::Result<()> {
+        let sender = header.sender()?.map(|s| s.to_string());
+        let inhibitor = {
+            let mut inhibitors = self.inhibitors.lock().unwrap();
+            let i = inhibitors
+                .iter()
+                .position(|i| i.cookie == cookie && Some(&i.owner) == sender.as_ref())
+                .ok_or_else(|| zbus::fdo::Error::InvalidArgs(format!("No inhibitor {cookie}.")))?;
+            inhibitors.remove(i)
+        };
+        log::info!("{} released inhibitor {cookie}.", inhibitor.owner);
+        self.inhibitors_updated(&ctxt).await
+    }
+
+    /// Locks taken with `Inhibit`, as owner, profile and reason.
+    #[dbus_interface(property)]
+    async fn inhibitors(&self) -> Vec<(String, String, String)> {
+        self.inhibitors
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|i| (i.owner.clone(), i.profile.clone(), i.reason.clone()))
+            .collect()
+    }
+
+    /// Name of the profile most recently applied.
+    #[dbus_interface(property)]
+    async fn active_profile(&self) -> String {
+        self.state.lock().unwrap().active_profile.clone()
+    }
+
+    /// EPPs written by the most recent application.
+    #[dbus_interface(property)]
+    async fn applied_epp(&self) -> String {
+        self.state.lock().unwrap().applied_epp.clone()
+    }
+
+    /// Governors written by the most recent application.
+    #[dbus_interface(property)]
+    async fn applied_governor(&self) -> String {
+        self.state.lock().unwrap().applied_governor.clone()
+    }
+
+    /// When the most recent application finished, in seconds since the Unix epoch.
+    #[dbus_interface(property)]
+    async fn last_apply_timestamp(&self) -> u64 {
+        self.state.lock().unwrap().last_apply_timestamp
+    }
+
+    /// Number of CPU cores whose EPP the daemon manages.
+    #[dbus_interface(property)]
+    async fn managed_core_count(&self) -> u32 {
+        self.state.lock().unwrap().managed_core_count
+    }
+
+    /// Emitted when a new ActiveProfile value is received from power-profiles-daemon.
+    #[dbus_interface(signal)]
+    async fn profile_received(ctxt: &SignalContext<'_>, profile: &str) -> zbus::Result<()>;
+
+    /// Emitted after a profile has been applied, with the knob, path and value of every
+    /// successful write and the number of writes that failed.
+    #[dbus_interface(signal)]
+    async fn profile_applied(
+        ctxt: &SignalContext<'_>,
+        profile: &str,
+        written: &[(&str, &str, &str)],
+        failures: u32,
+    ) -> zbus::Result<()>;
+
+    /// Emitted after a value has been written to a sysfs file.
+    #[dbus_interface(signal)]
+    async fn value_written(
+        ctxt: &SignalContext<'_>,
+        knob: &str,
+        path: &str,
+        value: &str,
+    ) -> zbus::Result<()>;
+
+    /// Emitted when writing a value to a sysfs file failed.
+    #[dbus_interface(signal)]
+    async fn write_failed(
+        ctxt: &SignalContext<'_>,
+        knob: &str,
+        path: &str,
+        value: &str,
+        error: &str,
+    ) -> zbus::Result<()>;
+}
+
+/// Counters of the daemon's work since it started or they were reset.
+#[derive(Default)]
+struct Statistics {
+    /// Number of times a different profile was applied.
+    profile_changes: u64,
+    /// Failed writes per cpufreq policy, or per file for system-wide knobs.
+    write_failures: HashMap<String, u32>,
+    /// Time spent in each profile, without the current stretch.
+    profile_time: HashMap<String, Duration>,
+    /// Profile applied most recently, and since when its time is counted.
+    current: Option<(String, Instant)>,
+    /// When a different profile was last applied.
+    last_change: Option<Instant>,
+}
+
+impl Statistics {
+    /// Count an applied profile. Returns whether it differs from the previous one.
+    fn record_profile(&mut self, profile: &str) -> bool {
+        if self.current.as_ref().is_some_and(|(p, _)| p == profile) {
+            return false;
+        }
+        let now = Instant::now();
+        if let Some((previous, since)) = self.current.take() {
+            *self.profile_time.entry(previous).or_default() += now - since;
+        }
+        self.current = Some((profile.to_string(), now));
+        self.last_change = Some(now);
+        self.profile_changes += 1;
+        true
+    }
+
+    /// Count a failed write to the given sysfs file.
+    fn record_failure(&mut self, file: &str) {
+        let policy = path::Path::new(file)
+            .parent()
+            .and_then(|dir| dir.file_name())
+            .and_then(|name| name.to_str())
+            .filter(|name| name.starts_with("policy"));
+        let key = policy.unwrap_or(file).to_string();
+        *self.write_failures.entry(key).or_default() += 1;
+    }
+}
+
+/// The statistics, exposed next to the daemon interface for monitoring. The counters sit
+/// behind a mutex so that `Reset` waits for polkit without holding the interface's lock.
+#[derive(Default)]
+pub struct StatisticsInterface {
+    stats: Mutex<Statistics>,
+}
+
+#[zbus::dbus_interface(name = "org.pstate_update1.Statistics")]
+impl StatisticsInterface {
+    /// Set every counter back to zero. The current profile's time starts counting anew.
+    async fn reset(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: zbus::MessageHeader<'_>,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> zbus::fdo::Result<()> {
+        check_authorization(conn, &header, RESET_STATISTICS_ACTION).await?;
+        {
+            let mut stats = self.stats.lock().unwrap();
+            stats.profile_changes = 0;
+            stats.write_failures.clear();
+            stats.profile_time.clear();
+            if let Some((_, since)) = &mut stats.current {
+                *since = Instant::now();
+            }
+        }
+        log::info!("Statistics reset.");
+        self.profile_changes_changed(&ctxt).await?;
+        self.write_failures_changed(&ctxt).await?;
+        Ok(())
+    }
+
+    /// Number of times a different profile was applied.
+    #[dbus_interface(property)]
+    async fn profile_changes(&self) -> u64 {
+        self.stats.lock().unwrap().profile_changes
+    }
+
+    /// Number of failed writes per cpufreq policy, e.g. `policy4`, or per sysfs file for
+    /// system-wide knobs.
+    #[dbus_interface(property)]
+    async fn write_failures(&self) -> HashMap<String, u32> {
+        self.stats.lock().unwrap().write_failures.clone()
+    }
+
+    /// Seconds since a different profile was last applied, or 0 before the first. Computed
+    /// when read, so it never emits PropertiesChanged.
+    #[dbus_interface(property)]
+    async fn seconds_since_last_change(&self) -> u64 {
+        let last_change = self.stats.lock().unwrap().last_change;
+        last_change.map_or(0, |t| t.elapsed().as_secs())
+    }
+
+    /// Seconds spent in each profile, including the current one up to now.
+    #[dbus_interface(property)]
+    async fn profile_time(&self) -> HashMap<String, u64> {
+        let stats = self.stats.lock().unwrap();
+        let mut time = stats.profile_time.clone();
+        if let Some((profile, since)) = &stats.current {
+            *time.entry(profile.clone()).or_default() += since.elapsed();
+        }
+        time.into_iter().map(|(p, t)| (p, t.as_secs())).collect()
+    }
+}
+
+/// Handle for emitting daemon signals on the bus. Failures to emit are only logged, since
+/// they must never stop the controller from doing its work.
+pub struct Events {
+    iface: zbus::blocking::InterfaceRef<DaemonInterface>,
+    stats: zbus::blocking::InterfaceRef<StatisticsInterface>,
+}
+
+impl Events {
+    /// Serve the daemon interface on the given connection and claim the service name.
+    /// Method calls are forwarded to the event loop through `sender`.
+    pub fn serve(
+        conn: &zbus::blocking::Connection,
+        sender: mpsc::Sender<Event>,
+        config: Arc<Config>,
+    ) -> zbus::Result<Self> {
+        let iface = DaemonInterface::new(sender, config);
+        conn.object_server().at(OBJECT_PATH, iface)?;
+        conn.object_server()
+            .at(OBJECT_PATH, StatisticsInterface::default())?;
+        executor::block_on_call(conn.inner().request_name(SERVICE_NAME))?;
+        log::info!("Serving {INTERFACE_NAME} as {SERVICE_NAME} on {OBJECT_PATH}.");
+        Ok(Events {
+            iface: conn.object_server().interface(OBJECT_PATH)?,
+            stats: conn.object_server().interface(OBJECT_PATH)?,
+        })
+    }
+
+    /// Update the state properties, and emit PropertiesChanged for the ones that changed.
+    pub fn update_state(&self, update: impl FnOnce(&mut DaemonState)) {
+        let iface = self.iface.get();
+        let (before, state) = {
+            let mut state = iface.state.lock().unwrap();
+            let before = state.clone();
+            update(&mut state);
+            (before, state.clone())
+        };
+        let ctxt = self.iface.signal_context();
+        if state.active_profile != before.active_profile {
+            Events::log_emit_error(zbus::block_on(iface.active_profile_changed(ctxt)));
+        }
+        if state.applied_epp != before.applied_epp {
+            Events::log_emit_error(zbus::block_on(iface.applied_epp_changed(ctxt)));
+        }
+        if state.applied_governor != before.applied_governor {
+            Events::log_emit_error(zbus::block_on(iface.applied_governor_changed(ctxt)));
+        }
+        if state.last_apply_timestamp != before.last_apply_timestamp {
+            Events::log_emit_error(zbus::block_on(iface.last_apply_timestamp_changed(ctxt)));
+        }
+        if state.managed_core_count != before.managed_core_count {
+            Events::log_emit_error(zbus::block_on(iface.managed_core_count_changed(ctxt)));
+        }
+    }
+
+    /// Check the profiles of later requests against a reloaded config.
+    pub fn set_config(&self, config: Arc<Config>) {
+        *self.iface.get().config.lock().unwrap() = config;
+    }
+
+    /// Release the locks of an application that left the bus.
+    pub fn release_inhibitors_of(&self, owner: &str) {
+        let iface = self.iface.get();
+        {
+            let mut inhibitors = iface.inhibitors.lock().unwrap();
+            let before = inhibitors.len();
+            inhibitors.retain(|i| i.owner != owner);
+            if inhibitors.len() == before {
+                return;
+            }
+        }
+        log::info!("{owner} left the bus, releasing its inhibitors.");
+        let ctxt = self.iface.signal_context();
+        if let Err(e) = zbus::block_on(iface.inhibitors_updated(ctxt)) {
+            log::warn!("Failed to release inhibitors: {e}");
+        }
+    }
+
+    fn log_emit_error(result: zbus::Result<()>) {
+        if let Err(e) = result {
+            log::warn!("Failed to emit D-Bus signal: {e}");
+        }
+    }
+
+    pub fn profile_received(&self, profile: &str) {
+        Events::log_emit_error(zbus::block_on(DaemonInterface::profile_received(
+            self.iface.signal_context(),
+            profile,
+        )));
+    }
+
+    pub fn profile_applied(&self, profile: &str, written: &[&PlannedWrite], failures: u32) {
+        let paths: Vec<_> = written.iter().map(|w| w.path.to_string_lossy()).collect();
+        let written: Vec<_> = written
+            .iter()
+            .zip(&paths)
+            .map(|(w, path)| (w.knob.as_str(), path.as_ref(), w.value.as_str()))
+            .collect();
+        Events::log_emit_error(zbus::block_on(DaemonInterface::profile_applied(
+            self.iface.signal_context(),
+            profile,
+            &written,
+            failures,
+        )));
+        let stats = self.stats.get();
+        if stats.stats.lock().unwrap().record_profile(profile) {
+            let ctxt = self.stats.signal_context();
+            Events::log_emit_error(zbus::block_on(stats.profile_changes_changed(ctxt)));
+        }
+    }
+
+    pub fn value_written(&self, knob: &str, path: &str, value: &str) {
+        Events::log_emit_error(zbus::block_on(DaemonInterface::value_written(
+            self.iface.signal_context(),
+            knob,
+            path,
+            value,
+        )));
+    }
+
+    pub fn write_failed(&self, knob: &str, path: &str, value: &str, error: &str) {
+        Events::log_emit_error(zbus::block_on(DaemonInterface::write_failed(
+            self.iface.signal_context(),
+            knob,
+            path,
+            value,
+            error,
+        )));
+        let stats = self.stats.get();
+        stats.stats.lock().unwrap().record_failure(path);
+        let ctxt = self.stats.signal_context();
+        Events::log_emit_error(zbus::block_on(stats.write_failures_changed(ctxt)));
+    }
+}
+
+/// Ask the running daemon to reload its config by sending it SIGHUP. The daemon is found
+/// through the owner of its name on the system bus. Returns the daemon's process ID.
+pub fn reload_daemon() -> Result<u32, String> {
+    let conn = zbus::blocking::Connection::system().map_err(|e| e.to_string())?;
+    let dbus = zbus::blocking::fdo::DBusProxy::new(&conn).map_err(|e| e.to_string())?;
+    let name = zbus::names::BusName::try_from(SERVICE_NAME).map_err(|e| e.to_string())?;
+    let pid = dbus
+        .get_connection_unix_process_id(name)
+        .map_err(|e| e.to_string())?;
+    let target = nix::unistd::Pid::from_raw(pid as i32);
+    nix::sys::signal::kill(target, nix::sys::signal::Signal::SIGHUP).map_err(|e| e.to_string())?;
+    Ok(pid)
+}
+
+/// Ask the running daemon to layer a `[profile]` section from its config on top of the
+/// profile reported by power-profiles-daemon, or to stop doing so when `name` is `None`.
+pub fn select_profile(name: Option<&str>) -> zbus::Result<()> {
+    let conn = zbus::blocking::Connection::system()?;
+    let proxy = DaemonProxyBlocking::new(&conn)?;
+    match name {
+        Some(name) => proxy.select_profile(name),
+        None => proxy.clear_profile(),
+    }
+}
+
+/// Ask the running daemon to apply the mapping of a built-in profile once, for users who
+/// may not write to sysfs themselves.
+pub fn apply_profile(profile: &PPDPowerProfile) -> zbus::Result<()> {
+    let conn = zbus::blocking::Connection::system()?;
+    DaemonProxyBlocking::new(&conn)?.apply_profile(&profile.to_string())
+}
+
+/// Read the state of the running daemon from its properties.
+pub fn fetch_state() -> zbus::Result<DaemonState> {
+    let conn = zbus::blocking::Connection::system()?;
+    let proxy = DaemonProxyBlocking::new(&conn)?;
+    Ok(DaemonState {
+        active_profile: proxy.active_profile()?,
+        applied_epp: proxy.applied_epp()?,
+        applied_governor: proxy.applied_governor()?,
+        last_apply_timestamp: proxy.last_apply_timestamp()?,
+        managed_core_count: proxy.managed_core_count()?,
+    })
+}
+
+/// Introspection XML of the daemon's interfaces, for installing to
+/// `/usr/share/dbus-1/interfaces/` and generating bindings in other languages.
+pub fn introspection_xml() -> String {
+    let daemon = DaemonInterface::new(mpsc::channel().0, Arc::new(config::default_config()));
+    let mut xml = String::from(
+        "<!DOCTYPE node PUBLIC \"-//freedesktop//DTD D-BUS Object Introspection 1.0//EN\"\n \
+         \"http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd\">\n<node>\n",
+    );
+    daemon.introspect_to_writer(&mut xml, 2);
+    StatisticsInterface::default().introspect_to_writer(&mut xml, 2);
+    xml.push_str("</node>\n");
+    xml
+}
+
+/// A single entry in the live feed printed by `watch`.
+#[derive(serde::Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum FeedEntry {
+    ProfileReceived {
+        profile: String,
+    },
+    ProfileApplied {
+        profile: String,
+        written: usize,
+        failures: u32,
+    },
+    ValueWritten {
+        knob: String,
+        path: String,
+        value: String,
+    },
+    WriteFailed {
+        knob: String,
+        path: String,
+        value: String,
+        error: String,
+    },
+}
+
+impl std::fmt::Display for FeedEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FeedEntry::ProfileReceived { profile } => write!(f, "Profile received: {profile}"),
+            FeedEntry::ProfileApplied {
+                profile,
+                written,
+                failures,
+            } => write!(
+                f,
+                "Applied {profile}: {written} values written, {failures} failed"
+            ),
+            FeedEntry::ValueWritten { knob, path, value } => {
+                write!(f, "Wrote {knob} '{value}' to {path}")
+            }
+            FeedEntry::WriteFailed {
+                knob,
+                path,
+                value,
+                error,
+            } => write!(f, "FAILED to write {knob} '{value}' to {path}: {error}"),
+        }
+    }
+}
+
+/// Decode a daemon signal into a feed entry. Returns `None` for unknown signals.
+fn decode(msg: std::sync::Arc<zbus::Message>) -> zbus::Result<Option<FeedEntry>> {
+    if let Some(s) = ProfileReceived::from_message(msg.clone()) {
+        let args = s.args()?;
+        return Ok(Some(FeedEntry::ProfileReceived {
+            profile: args.profile.to_string(),
+        }));
+    }
+    if let Some(s) = ProfileApplied::from_message(msg.clone()) {
+        let args = s.args()?;
+        return Ok(Some(FeedEntry::ProfileApplied {
+            profile: args.profile.to_string(),
+            written: args.written.len(),
+            failures: args.failures,
+        }));
+    }
+    if let Some(s) = ValueWritten::from_message(msg.clone()) {
+        let args = s.args()?;
+        return Ok(Some(FeedEntry::ValueWritten {
+            knob: args.knob.to_string(),
+            path: args.path.to_string(),
+            value: args.value.to_string(),
+        }));
+    }
+    if let Some(s) = WriteFailed::from_message(msg) {
+        let args = s.args()?;
+        return Ok(Some(FeedEntry::WriteFailed {
+            knob: args.knob.to_string(),
+            path: args.path.to_string(),
+            value: args.value.to_string(),
+            error: args.error.to_string(),
+        }));
+    }
+    Ok(None)
+}
+
+/// Subscribe to all signals from the daemon and pass each decoded entry to `on_entry`.
+pub fn watch(mut on_entry: impl FnMut(FeedEntry)) -> zbus::Result<()> {
+    let conn = zbus::blocking::Connection::system()?;
+    let rule = zbus::MatchRule::builder()
+        .msg_type(zbus::MessageType::Signal)
+        .sender(SERVICE_NAME)?
+        .interface(INTERFACE_NAME)?
+        .build();
+    let messages = zbus::blocking::MessageIterator::for_match_rule(rule, &conn, None)?;
+    log::info!("Watching signals from {SERVICE_NAME}.");
+    for msg in messages {
+        match decode(msg?) {
+            Ok(Some(entry)) => on_entry(entry),
+            Ok(None) => {}
+            Err(e) => log::warn!("Could not decode signal: {e}"),
+        }
+    }
+    Ok(())
+}
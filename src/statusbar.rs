@@ -0,0 +1,122 @@
+use std::io::{self, Write};
+use std::path;
+use std::thread;
+use std::time::Duration;
+
+use crate::sysfs;
+use crate::PowerProfilesDaemonManagerProxyBlocking;
+
+/// Output format of the status bar payload.
+#[derive(Clone, clap::ValueEnum)]
+pub enum Format {
+    /// A waybar custom module object per line.
+    Waybar,
+    /// The i3bar protocol, as used by i3bar and swaybar.
+    I3bar,
+}
+
+/// Values shown in the status bar.
+struct BarState {
+    profile: Option<String>,
+    epp: Option<String>,
+    avg_freq_mhz: Option<u64>,
+}
+
+impl BarState {
+    fn read(
+        cpufreq_path: &path::Path,
+        proxy: Option<&PowerProfilesDaemonManagerProxyBlocking>,
+    ) -> Self {
+        let policies = sysfs::read_all_policy_status(cpufreq_path).unwrap_or_default();
+        let freqs: Vec<u64> = policies.iter().filter_map(|p| p.cur_freq_khz).collect();
+        let avg_freq_mhz = match freqs.len() {
+            0 => None,
+            n => Some(freqs.iter().sum::<u64>() / n as u64 / 1000),
+        };
+        BarState {
+            profile: proxy.and_then(|p| p.active_profile().ok()),
+            epp: policies.iter().find_map(|p| p.epp.clone()),
+            avg_freq_mhz,
+        }
+    }
+
+    fn icon(&self) -> &'static str {
+        match self.profile.as_deref() {
+            Some("power-saver") => "🍃",
+            Some("balanced") => "⚖",
+            Some("performance") => "🚀",
+            _ => "?",
+        }
+    }
+
+    fn text(&self) -> String {
+        let freq = match self.avg_freq_mhz {
+            Some(mhz) => format!("{:.1} GHz", mhz as f64 / 1000.0),
+            None => "-".to_string(),
+        };
+        format!(
+            "{} {} {freq}",
+            self.icon(),
+            self.epp.as_deref().unwrap_or("-")
+        )
+    }
+
+    fn tooltip(&self) -> String {
+        format!(
+            "Profile: {}\nEPP: {}\nAverage frequency: {} MHz",
+            self.profile.as_deref().unwrap_or("unknown"),
+            self.epp.as_deref().unwrap_or("unknown"),
+            self.avg_freq_mhz
+                .map(|f| f.to_string())
+                .unwrap_or_else(|| "unknown".to_string()),
+        )
+    }
+
+    fn to_json(&self, format: &Format) -> serde_json::Value {
+        let profile = self.profile.as_deref().unwrap_or("unknown");
+        match format {
+            Format::Waybar => serde_json::json!({
+                "text": self.text(),
+                "alt": profile,
+                "tooltip": self.tooltip(),
+                "class": profile,
+            }),
+            Format::I3bar => serde_json::json!({
+                "name": "pstate_update",
+                "instance": profile,
+                "full_text": self.text(),
+            }),
+        }
+    }
+}
+
+/// Print the status bar payload once, or every `interval` if given.
+pub fn run(
+    cpufreq_path: &path::Path,
+    proxy: Option<&PowerProfilesDaemonManagerProxyBlocking>,
+    format: &Format,
+    interval: Option<Duration>,
+) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    let Some(interval) = interval else {
+        let payload = BarState::read(cpufreq_path, proxy).to_json(format);
+        return match format {
+            Format::Waybar => writeln!(stdout, "{payload}"),
+            Format::I3bar => writeln!(stdout, "[{payload}]"),
+        };
+    };
+    if let Format::I3bar = format {
+        // The i3bar protocol is a header followed by an endless JSON array of status lines.
+        writeln!(stdout, "{{\"version\":1}}")?;
+        writeln!(stdout, "[")?;
+    }
+    loop {
+        let payload = BarState::read(cpufreq_path, proxy).to_json(format);
+        match format {
+            Format::Waybar => writeln!(stdout, "{payload}")?,
+            Format::I3bar => writeln!(stdout, "[{payload}],")?,
+        }
+        stdout.flush()?;
+        thread::sleep(interval);
+    }
+}
@@ -0,0 +1,28 @@
+use std::path;
+
+/// Root of the NVMe class in sysfs.
+pub const NVME_PATH: &str = "/sys/class/nvme";
+
+/// Latency tolerance of an NVMe controller in microseconds, relative to the controller
+/// directory. The nvme driver only lets APST enter power states whose exit latency fits
+/// within it, and disables APST at 0.
+pub const LATENCY_TOLERANCE_FILE: &str = "power/pm_qos_latency_tolerance_us";
+
+/// Find the NVMe controllers with a latency tolerance, e.g. `/sys/class/nvme/nvme0`.
+pub fn find_nvme_controllers(nvme_path: &path::Path) -> Vec<path::PathBuf> {
+    let Ok(entries) = nvme_path.read_dir() else {
+        return Vec::new();
+    };
+    let mut controllers: Vec<_> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.strip_prefix("nvme"))
+                .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+        })
+        .filter(|p| p.join(LATENCY_TOLERANCE_FILE).exists())
+        .collect();
+    controllers.sort();
+    controllers
+}
@@ -0,0 +1,400 @@
+use std::fs;
+use std::io;
+use std::path;
+
+/// Root of the per-policy cpufreq interface in sysfs.
+pub const CPUFREQ_PATH: &str = "/sys/devices/system/cpu/cpufreq";
+
+/// Directory of the logical CPUs, `cpu0`, `cpu1` and so on.
+pub const CPU_PATH: &str = "/sys/devices/system/cpu";
+
+/// SMT control, `on` or `off` when it can be switched.
+pub const SMT_CONTROL_PATH: &str = "/sys/devices/system/cpu/smt/control";
+
+/// Value of `max_cstate` that enables every idle state.
+pub const ALL_IDLE_STATES: &str = "all";
+
+/// Root of the cpuidle interface in sysfs.
+pub const CPUIDLE_PATH: &str = "/sys/devices/system/cpu/cpuidle";
+
+/// ACPI platform profile, which selects firmware fan and power behavior.
+pub const PLATFORM_PROFILE_PATH: &str = "/sys/firmware/acpi/platform_profile";
+
+/// Platform profiles the firmware supports.
+pub const PLATFORM_PROFILE_CHOICES_PATH: &str = "/sys/firmware/acpi/platform_profile_choices";
+
+/// Operation mode of the amd_pstate driver. Writing it re-registers the driver, which
+/// replaces the files of every cpufreq policy.
+pub const AMD_PSTATE_STATUS_PATH: &str = "/sys/devices/system/cpu/amd_pstate/status";
+
+/// Global knobs of the intel_pstate driver, like `no_turbo` and `max_perf_pct`.
+pub const INTEL_PSTATE_PATH: &str = "/sys/devices/system/cpu/intel_pstate";
+
+/// `scaling_driver` of intel_pstate in active mode, the only mode with EPP. In passive
+/// mode it reports `intel_cpufreq`.
+pub const INTEL_PSTATE_DRIVER: &str = "intel_pstate";
+
+/// Root of the sysctl interface in procfs.
+pub const SYSCTL_PATH: &str = "/proc/sys";
+
+/// File of a sysctl given by its name, e.g. `vm.dirty_writeback_centisecs`. Like
+/// sysctl(8), names may also be separated by `/`. Returns `None` for names that would
+/// point outside the sysctl tree.
+pub fn sysctl_file(sysctl_path: &path::Path, name: &str) -> Option<path::PathBuf> {
+    let separator = if name.contains('/') { '/' } else { '.' };
+    let parts: Vec<&str> = name.split(separator).collect();
+    if parts
+        .iter()
+        .any(|p| p.is_empty() || *p == "." || *p == "..")
+    {
+        return None;
+    }
+    Some(
+        parts
+            .iter()
+            .fold(sysctl_path.to_path_buf(), |p, part| p.join(part)),
+    )
+}
+
+/// Read a sysfs attribute and strip the trailing newline.
+pub fn read_value(file: &path::Path) -> io::Result<String> {
+    Ok(fs::read_to_string(file)?.trim().to_string())
+}
+
+/// Read a whitespace-separated list attribute like `scaling_available_governors`.
+/// Returns `None` if the attribute does not exist or cannot be read.
+pub fn read_available(file: &path::Path) -> Option<Vec<String>> {
+    let s = read_value(file).ok()?;
+    Some(s.split_whitespace().map(String::from).collect())
+}
+
+/// CPU model name as reported by the first processor in `/proc/cpuinfo`.
+pub fn read_cpu_model() -> Option<String> {
+    let cpuinfo = fs::read_to_string("/proc/cpuinfo").ok()?;
+    cpuinfo.lines().find_map(|l| {
+        let (key, value) = l.split_once(':')?;
+        (key.trim() == "model name").then(|| value.trim().to_string())
+    })
+}
+
+/// Parse a kernel CPU list like `0-7,16-23` or `0 1 2` into a sorted list of CPU numbers.
+pub fn parse_cpu_list(s: &str) -> Result<Vec<u32>, String> {
+    let mut cpus = Vec::new();
+    for part in s.split(|c: char| c == ',' || c.is_whitespace()) {
+        if part.is_empty() {
+            continue;
+        }
+        let parse = |n: &str| {
+            n.parse::<u32>()
+                .map_err(|_| format!("Invalid CPU number '{n}' in CPU list '{s}'."))
+        };
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (parse(first)?, parse(last)?);
+                if first > last {
+                    return Err(format!("Invalid CPU range '{part}' in CPU list '{s}'."));
+                }
+                cpus.extend(first..=last);
+            }
+            None => cpus.push(parse(part)?),
+        }
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+    Ok(cpus)
+}
+
+/// Logical CPUs covered by the policy in the given `policyN` directory. Empty if
+/// `affected_cpus` cannot be read or parsed.
+pub fn read_policy_cpus(dir: &path::Path) -> Vec<u32> {
+    read_value(&dir.join("affected_cpus"))
+        .ok()
+        .and_then(|s| parse_cpu_list(&s).ok())
+        .unwrap_or_default()
+}
+
+/// Whether any CPU of the policy in the given `policyN` directory is online. Policies
+/// whose CPUs all went offline, e.g. with SMT turned off, stay in sysfs but refuse reads
+/// and writes.
+pub fn is_active_policy(dir: &path::Path) -> bool {
+    read_value(&dir.join("affected_cpus")).is_ok()
+}
+
+/// Name of the policy directory, e.g. `policy0`.
+pub fn policy_name(dir: &path::Path) -> String {
+    dir.file_name()
+        .map(|f| f.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Extract the policy number from a `policyN` directory name.
+fn policy_number(dir: &path::Path) -> Option<u32> {
+    dir.file_name()?
+        .to_str()?
+        .strip_prefix("policy")?
+        .parse()
+        .ok()
+}
+
+/// Collect all `policyN` directories in the given `cpufreq` folder, sorted by policy number.
+pub fn find_policy_dirs(cpufreq_path: &path::Path) -> io::Result<Vec<path::PathBuf>> {
+    let mut dirs = Vec::new();
+    for entry in cpufreq_path.read_dir()? {
+        let p = entry.expect("any path from read_dir should be Ok").path();
+        if policy_number(&p).is_some() {
+            dirs.push(p);
+        }
+    }
+    dirs.sort_by_key(|p| policy_number(p));
+    Ok(dirs)
+}
+
+/// Whether the current user may write to the given file. Opening it for writing changes
+/// nothing in sysfs, only writes do.
+pub fn is_writable(file: &path::Path) -> bool {
+    fs::OpenOptions::new().write(true).open(file).is_ok()
+}
+
+/// Traverse the given `cpufreq` folder and collect valid EPP files for each CPU core.
+/// Policies with any of the `exclude_cpus` are skipped.
+pub fn find_cpu_core_epp_paths(
+    cpufreq_path: &path::Path,
+    exclude_cpus: &[u32],
+) -> io::Result<Vec<path::PathBuf>> {
+    let mut paths = Vec::new();
+    log::info!("Looking for EPP files for individual CPU cores in {cpufreq_path:?}.");
+    for p in find_policy_dirs(cpufreq_path)? {
+        if !is_active_policy(&p) {
+            log::info!("Skipping {p:?}, whose CPUs are all offline.");
+            continue;
+        }
+        if read_policy_cpus(&p)
+            .iter()
+            .any(|c| exclude_cpus.contains(c))
+        {
+            log::info!("Skipping {p:?}, which has excluded CPUs.");
+            continue;
+        }
+        let epp_file = p.join("energy_performance_preference");
+        if !epp_file.exists() {
+            log::warn!("EPP file does not exist: {epp_file:?}.");
+            continue;
+        }
+        log::debug!("Found valid EPP file: {epp_file:?}.");
+        paths.push(epp_file);
+    }
+    log::info!("Found {} valid EPP files.", paths.len());
+    Ok(paths)
+}
+
+/// Traverse the given `cpufreq` folder and collect the governor files of policies without
+/// EPP, as with acpi-cpufreq. Policies with any of the `exclude_cpus` are skipped.
+pub fn find_cpu_core_governor_paths(
+    cpufreq_path: &path::Path,
+    exclude_cpus: &[u32],
+) -> io::Result<Vec<path::PathBuf>> {
+    let mut paths = Vec::new();
+    for p in find_policy_dirs(cpufreq_path)? {
+        if !is_active_policy(&p) {
+            continue;
+        }
+        if read_policy_cpus(&p)
+            .iter()
+            .any(|c| exclude_cpus.contains(c))
+        {
+            continue;
+        }
+        let governor_file = p.join("scaling_governor");
+        if governor_file.exists() {
+            paths.push(governor_file);
+        }
+    }
+    log::info!("Found {} valid governor files.", paths.len());
+    Ok(paths)
+}
+
+/// EPP and governor files of every policy without excluded CPUs. Without any EPP files,
+/// the governor files are found on their own, so that the governors can still follow
+/// the profile.
+pub fn find_managed_files(
+    cpufreq_path: &path::Path,
+    exclude_cpus: &[u32],
+) -> io::Result<(Vec<path::PathBuf>, Vec<path::PathBuf>)> {
+    let epp_files = find_cpu_core_epp_paths(cpufreq_path, exclude_cpus)?;
+    if !epp_files.is_empty() {
+        let governor_files = generate_cpu_core_gorvernor_paths(&epp_files);
+        return Ok((epp_files, governor_files));
+    }
+    log::warn!("No EPP files found, running in governor-only mode.");
+    let governor_files = find_cpu_core_governor_paths(cpufreq_path, exclude_cpus)?;
+    Ok((epp_files, governor_files))
+}
+
+/// Boost files that turn turbo frequencies on or off for the policies in `policy_dirs`.
+/// The per-policy `boost` files of amd-pstate are used where any policy has one, since
+/// the global `boost` file in `cpufreq_path` would also reach excluded policies. Without
+/// them, the global file covers every policy with a single write.
+pub fn find_boost_files(
+    cpufreq_path: &path::Path,
+    policy_dirs: &[&path::Path],
+) -> Vec<path::PathBuf> {
+    let per_policy: Vec<_> = policy_dirs
+        .iter()
+        .map(|dir| dir.join("boost"))
+        .filter(|f| f.exists())
+        .collect();
+    if !per_policy.is_empty() {
+        return per_policy;
+    }
+    let global = cpufreq_path.join("boost");
+    if global.exists() {
+        vec![global]
+    } else {
+        Vec::new()
+    }
+}
+
+/// `energy_perf_bias` files of every logical CPU in `cpu_path` that exposes one, skipping
+/// the `exclude_cpus`.
+pub fn find_epb_files(cpu_path: &path::Path, exclude_cpus: &[u32]) -> Vec<path::PathBuf> {
+    find_cpu_files(cpu_path, "power/energy_perf_bias")
+        .into_iter()
+        .filter(|(cpu, _)| !exclude_cpus.contains(cpu))
+        .map(|(_, file)| file)
+        .collect()
+}
+
+/// Idle states of every logical CPU in `cpu_path`, skipping the `exclude_cpus`. Each
+/// state is given by its name, e.g. `C2`, and its `stateN` directory, from the shallowest
+/// to the deepest.
+pub fn find_idle_states(
+    cpu_path: &path::Path,
+    exclude_cpus: &[u32],
+) -> Vec<(u32, Vec<(String, path::PathBuf)>)> {
+    find_cpu_files(cpu_path, "cpuidle")
+        .into_iter()
+        .filter(|(cpu, _)| !exclude_cpus.contains(cpu))
+        .map(|(cpu, dir)| {
+            let mut states: Vec<(u32, String, path::PathBuf)> = fs::read_dir(&dir)
+                .into_iter()
+                .flatten()
+                .filter_map(|e| {
+                    let path = e.ok()?.path();
+                    let index = path
+                        .file_name()?
+                        .to_str()?
+                        .strip_prefix("state")?
+                        .parse()
+                        .ok()?;
+                    let name = read_value(&path.join("name")).ok()?;
+                    Some((index, name, path))
+                })
+                .collect();
+            states.sort_unstable_by_key(|(index, _, _)| *index);
+            let states = states
+                .into_iter()
+                .map(|(_, name, dir)| (name, dir))
+                .collect();
+            (cpu, states)
+        })
+        .collect()
+}
+
+/// `online` files of every logical CPU in `cpu_path` that can be taken offline, which
+/// usually leaves out `cpu0`.
+pub fn find_hotplug_cpus(cpu_path: &path::Path) -> Vec<(u32, path::PathBuf)> {
+    find_cpu_files(cpu_path, "online")
+}
+
+/// The given file of every logical CPU in `cpu_path` that has it, in order of the CPUs.
+fn find_cpu_files(cpu_path: &path::Path, file: &str) -> Vec<(u32, path::PathBuf)> {
+    let Ok(entries) = fs::read_dir(cpu_path) else {
+        return Vec::new();
+    };
+    let mut cpus: Vec<(u32, path::PathBuf)> = entries
+        .filter_map(|e| {
+            let e = e.ok()?;
+            let cpu = e.file_name().to_str()?.strip_prefix("cpu")?.parse().ok()?;
+            Some((cpu, e.path().join(file)))
+        })
+        .filter(|(_, file)| file.exists())
+        .collect();
+    cpus.sort_unstable_by_key(|(cpu, _)| *cpu);
+    cpus
+}
+
+pub fn generate_cpu_core_gorvernor_paths(epp_paths: &[path::PathBuf]) -> Vec<path::PathBuf> {
+    let mut paths = Vec::new();
+    for epp in epp_paths {
+        let p = epp.with_file_name("scaling_governor");
+        if !p.exists() {
+            log::warn!("Governor file does not exist: {p:?}.");
+            continue;
+        }
+        paths.push(p);
+    }
+    log::info!("Found {} valid governor files.", paths.len());
+    paths
+}
+
+/// Current state of a single cpufreq policy. Attributes that could not be read are `None`.
+#[derive(serde::Serialize)]
+pub struct PolicyStatus {
+    pub name: String,
+    pub cpus: Option<String>,
+    pub epp: Option<String>,
+    pub governor: Option<String>,
+    pub cur_freq_khz: Option<u64>,
+}
+
+/// Read the current state of the policy in the given `policyN` directory.
+pub fn read_policy_status(dir: &path::Path) -> PolicyStatus {
+    let read = |attr: &str| read_value(&dir.join(attr)).ok();
+    PolicyStatus {
+        name: policy_name(dir),
+        cpus: read("affected_cpus"),
+        epp: read("energy_performance_preference"),
+        governor: read("scaling_governor"),
+        cur_freq_khz: read("scaling_cur_freq").and_then(|f| f.parse().ok()),
+    }
+}
+
+/// Read the current state of all policies in the given `cpufreq` folder.
+pub fn read_all_policy_status(cpufreq_path: &path::Path) -> io::Result<Vec<PolicyStatus>> {
+    Ok(find_policy_dirs(cpufreq_path)?
+        .iter()
+        .map(|d| read_policy_status(d))
+        .collect())
+}
+
+/// Values a single cpufreq policy accepts. Attributes that could not be read are `None`.
+#[derive(serde::Serialize)]
+pub struct PolicyCapabilities {
+    pub name: String,
+    pub cpus: Option<String>,
+    /// `scaling_driver`, e.g. `amd-pstate-epp` or `intel_pstate`.
+    pub driver: Option<String>,
+    pub available_epps: Option<Vec<String>>,
+    /// Whether the policy has an `energy_performance_preference` file, as on drivers in
+    /// active mode like amd-pstate-epp and intel_pstate.
+    #[serde(skip)]
+    pub has_epp: bool,
+    pub available_governors: Option<Vec<String>>,
+    pub min_freq_khz: Option<u64>,
+    pub max_freq_khz: Option<u64>,
+}
+
+/// Read the supported values of the policy in the given `policyN` directory.
+pub fn read_policy_capabilities(dir: &path::Path) -> PolicyCapabilities {
+    let read_khz = |attr: &str| read_value(&dir.join(attr)).ok()?.parse().ok();
+    PolicyCapabilities {
+        name: policy_name(dir),
+        cpus: read_value(&dir.join("affected_cpus")).ok(),
+        driver: read_value(&dir.join("scaling_driver")).ok(),
+        available_epps: read_available(&dir.join("energy_performance_available_preferences")),
+        has_epp: dir.join("energy_performance_preference").exists(),
+        available_governors: read_available(&dir.join("scaling_available_governors")),
+        min_freq_khz: read_khz("cpuinfo_min_freq"),
+        max_freq_khz: read_khz("cpuinfo_max_freq"),
+    }
+}
@@ -0,0 +1,86 @@
+use std::env;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::RawFd;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::path;
+use std::process;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Send a state update like `READY=1` or `STATUS=...` to systemd, if it supervises the
+/// daemon through `NOTIFY_SOCKET`. Does nothing otherwise.
+pub fn notify(state: &str) {
+    let Some(socket) = env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let sent = UnixDatagram::unbound().and_then(|s| match socket.as_bytes().strip_prefix(b"@") {
+        Some(name) => s.send_to_addr(state.as_bytes(), &SocketAddr::from_abstract_name(name)?),
+        None => s.send_to(state.as_bytes(), path::Path::new(&socket)),
+    });
+    if let Err(e) = sent {
+        log::debug!("Failed to notify systemd of {state:?}: {e}");
+    }
+}
+
+/// How often to ping the watchdog, which is half the interval systemd expects pings in.
+/// `None` if systemd does not watch this process.
+pub fn watchdog_interval() -> Option<Duration> {
+    let pid = env::var("WATCHDOG_PID").ok();
+    if pid.is_some_and(|p| p.parse() != Ok(process::id())) {
+        return None;
+    }
+    let usec: u64 = env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    Some(Duration::from_micros(usec / 2)).filter(|i| !i.is_zero())
+}
+
+/// First file descriptor passed by socket activation.
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Sockets systemd passed through `LISTEN_FDS` when a socket unit started this process,
+/// in the order of the unit's `Listen` lines. Empty if the process was started otherwise.
+pub fn listen_fds() -> Vec<RawFd> {
+    let pid = env::var("LISTEN_PID").ok();
+    if pid.and_then(|p| p.parse().ok()) != Some(process::id()) {
+        return Vec::new();
+    }
+    let count: RawFd = env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(0);
+    (LISTEN_FDS_START..LISTEN_FDS_START + count).collect()
+}
+
+/// Receive a message like `Receiver::recv_timeout`, or wait forever if `timeout` is zero.
+/// The watchdog is pinged in the meantime, since the event loop does not run while the
+/// daemon waits here.
+pub fn recv_timeout<T>(
+    receiver: &mpsc::Receiver<T>,
+    timeout: Duration,
+) -> Result<T, mpsc::RecvTimeoutError> {
+    let deadline = Some(Instant::now() + timeout).filter(|_| !timeout.is_zero());
+    loop {
+        let remaining = deadline.map_or(Duration::MAX, |d| d - Instant::now().min(d));
+        let step = watchdog_interval().map_or(remaining, |i| i.min(remaining));
+        match receiver.recv_timeout(step) {
+            Err(mpsc::RecvTimeoutError::Timeout) if step < remaining => notify("WATCHDOG=1"),
+            result => return result,
+        }
+    }
+}
+
+/// Sleep like `thread::sleep`, pinging the watchdog in the meantime.
+pub fn sleep(duration: Duration) {
+    let deadline = Instant::now() + duration;
+    loop {
+        let remaining = deadline - Instant::now().min(deadline);
+        match watchdog_interval() {
+            Some(interval) if interval < remaining => {
+                thread::sleep(interval);
+                notify("WATCHDOG=1");
+            }
+            _ => return thread::sleep(remaining),
+        }
+    }
+}
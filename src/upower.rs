@@ -0,0 +1,72 @@
+use crate::power::{BatteryWarning, PowerSource};
+
+/// UPower daemon itself.
+#[zbus::dbus_proxy(
+    interface = "org.freedesktop.UPower",
+    default_service = "org.freedesktop.UPower",
+    default_path = "/org/freedesktop/UPower"
+)]
+pub trait UPower {
+    #[dbus_proxy(property)]
+    fn on_battery(&self) -> zbus::Result<bool>;
+}
+
+/// Power source matching UPower's `OnBattery` property.
+pub fn power_source(on_battery: bool) -> PowerSource {
+    if on_battery {
+        PowerSource::Battery
+    } else {
+        PowerSource::Ac
+    }
+}
+
+/// Composite battery that UPower exposes for the whole system.
+#[zbus::dbus_proxy(
+    interface = "org.freedesktop.UPower.Device",
+    default_service = "org.freedesktop.UPower",
+    default_path = "/org/freedesktop/UPower/devices/DisplayDevice"
+)]
+pub trait DisplayDevice {
+    #[dbus_proxy(property)]
+    fn is_present(&self) -> zbus::Result<bool>;
+
+    #[dbus_proxy(property)]
+    fn percentage(&self) -> zbus::Result<f64>;
+
+    #[dbus_proxy(property)]
+    fn warning_level(&self) -> zbus::Result<u32>;
+}
+
+/// Battery warning matching UPower's `WarningLevel` property. The action level, at which
+/// the system is about to shut down, counts as critical.
+pub fn battery_warning(warning_level: u32) -> Option<BatteryWarning> {
+    match warning_level {
+        3 => Some(BatteryWarning::Low),
+        4 | 5 => Some(BatteryWarning::Critical),
+        _ => None,
+    }
+}
+
+/// Battery level in percent from UPower, or `None` if the system has no battery.
+pub async fn read_battery_level(proxy: &DisplayDeviceProxy<'_>) -> zbus::Result<Option<f64>> {
+    if !proxy.is_present().await? {
+        return Ok(None);
+    }
+    Ok(Some(proxy.percentage().await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn battery_warning_follows_warning_level() {
+        // Unknown, None and Discharging (UPS only) are no warning.
+        for level in [0, 1, 2, 6] {
+            assert!(battery_warning(level).is_none(), "{level} should not warn");
+        }
+        assert!(battery_warning(3) == Some(BatteryWarning::Low));
+        assert!(battery_warning(4) == Some(BatteryWarning::Critical));
+        assert!(battery_warning(5) == Some(BatteryWarning::Critical));
+    }
+}